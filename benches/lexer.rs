@@ -0,0 +1,22 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use phantom_language_server::{chumsky, lexer};
+
+/// A large PHP file, made of copies of the syntax example. Each copy ends in inline HTML, so the
+/// next one starts outside of the PHP tags as the first one does.
+fn source() -> String {
+    include_str!("../examples/syntax.php").repeat(200)
+}
+
+fn lex(c: &mut Criterion) {
+    let src = source();
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.sample_size(10);
+
+    group.bench_function("combinators", |b| b.iter(|| chumsky::lex(black_box(&src))));
+    group.bench_function("hand-written", |b| b.iter(|| lexer::lex(black_box(&src))));
+    group.finish();
+}
+
+criterion_group!(benches, lex);
+criterion_main!(benches);
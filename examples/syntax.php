@@ -0,0 +1,143 @@
+<!DOCTYPE html>
+<?php
+declare(strict_types=1);
+
+namespace App\Models;
+
+use App\Contracts\{HasName, HasId as Identified};
+use function App\Support\format_name;
+use const App\Support\VERSION;
+
+const DEFAULT_NAME = 'anonymous';
+
+interface Named extends HasName
+{
+    public function name(): string;
+}
+
+trait Greets
+{
+    public function greet(?string $greeting = null): string
+    {
+        return ($greeting . 'Hello') . ', ' . $this->name();
+    }
+}
+
+enum Status: string implements Named
+{
+    case Active = 'active';
+    case Inactive = 'inactive';
+
+    const DEFAULT = self::Active;
+
+    public function name(): string
+    {
+        return match ($this) {
+            self::Active => 'Active',
+            self::Inactive, self::DEFAULT => 'Inactive',
+        };
+    }
+}
+
+abstract class Model implements Named, Identified
+{
+    use Greets;
+
+    protected static int $count = 0;
+    private array $attributes = [], $dirty = [];
+
+    public function __construct(public readonly int $id, protected (Countable&Traversable)|array|null $tags = null)
+    {
+        static::$count++;
+    }
+
+    abstract protected function table(): string;
+}
+
+final class User extends Model
+{
+    public string $email {
+        get => strtolower($this->email);
+        set(string $value) {
+            $this->email = $value;
+        }
+    }
+
+    public function name(): string
+    {
+        return format_name($this->attributes, DEFAULT_NAME);
+    }
+
+    protected function table(): string
+    {
+        return 'users';
+    }
+
+    public static function total(int ...$extra): int|float
+    {
+        return (static::$count + count($extra)) * 2 - -1;
+    }
+}
+
+function scores(array &$users, callable $score = null): array
+{
+    global $weights;
+    static $calls = 0, $cache;
+    $calls++;
+    $total = 0;
+    $factor = fn($x) => $x * 2;
+    $sum = function ($a) use ($total, &$calls): int {
+        return $a + $total;
+    };
+
+    foreach ($users as $id => $user) {
+        if ($user?->email === null) {
+            continue;
+        } elseif (!isset($user->email, $weights) != empty($user->tags)) {
+            unset($users, $id);
+            break 1;
+        } else {
+            $total = $total + $factor($sum(1)) - ($total - 1) / 3;
+        }
+    }
+
+    for ($i = 0, $j = 10; $i < $j; $i++, --$j) {
+        echo $i . $j, "\n";
+    }
+
+    while ($total > 100):
+        $total = $total / 2;
+    endwhile;
+
+    switch ($total) {
+        case 0:
+            print 'none';
+            break;
+        case 1;
+        default:
+            $total = ! !$total == true;
+    }
+
+    if ($total): ?>
+        <p>Total: <?= $total ?></p>
+    <?php endif;
+
+    retry:
+    if ($total < 0) {
+        goto retry;
+    }
+
+    $user = (new User(1))->greet();
+    $name = User::class . Status::Active->value;
+    $copy = clone_of($user)($name);
+
+    return [$total, $user, (print 'done') + 1];
+}
+
+require_once __DIR__ . '/helpers.php';
+
+if (!function_exists('format_name')) {
+    exit(1);
+}
+?>
+</html>
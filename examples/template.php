@@ -0,0 +1,34 @@
+<!DOCTYPE html>
+<html>
+<body>
+<?php if ($usuario): ?>
+    <h1>Olá, <?= $usuario ?>!</h1>
+<?php elseif ($convidado): ?>
+    <h1>Bem-vindo, convidado</h1>
+<?php else: ?>
+    <h1>Faça login</h1>
+<?php endif; ?>
+
+<ul>
+<?php foreach ($itens as $indice => $item): ?>
+    <li><?= $indice, ': ', $item ?></li>
+<?php endforeach; ?>
+</ul>
+
+<?php for ($i = 0; $i < 3; $i++): ?>
+    <span><?= $i ?></span>
+<?php endfor; ?>
+
+<?php while ($i > 0): ?>
+    <?php $i--; ?>
+<?php endwhile; ?>
+
+<?php switch ($status): ?>
+<?php case 'ativo': ?>
+    <p>Ativo</p>
+    <?php break; ?>
+<?php default: ?>
+    <p>Inativo</p>
+<?php endswitch; ?>
+</body>
+</html>
@@ -0,0 +1,335 @@
+//! Names that PHP and its bundled extensions declare, so that they're known without a stub file.
+//! The lists are sorted, and cover the names most code uses rather than every extension.
+
+/// Functions, which are matched case-insensitively as PHP does.
+pub const FUNCTIONS: &[&str] = &[
+    "abs",
+    "array_chunk",
+    "array_column",
+    "array_combine",
+    "array_diff",
+    "array_diff_key",
+    "array_fill",
+    "array_fill_keys",
+    "array_filter",
+    "array_flip",
+    "array_intersect",
+    "array_intersect_key",
+    "array_is_list",
+    "array_key_exists",
+    "array_key_first",
+    "array_key_last",
+    "array_keys",
+    "array_map",
+    "array_merge",
+    "array_merge_recursive",
+    "array_pad",
+    "array_pop",
+    "array_product",
+    "array_push",
+    "array_reduce",
+    "array_replace",
+    "array_reverse",
+    "array_search",
+    "array_shift",
+    "array_slice",
+    "array_splice",
+    "array_sum",
+    "array_unique",
+    "array_unshift",
+    "array_values",
+    "array_walk",
+    "arsort",
+    "asort",
+    "assert",
+    "base64_decode",
+    "base64_encode",
+    "basename",
+    "bin2hex",
+    "call_user_func",
+    "call_user_func_array",
+    "ceil",
+    "chr",
+    "class_exists",
+    "compact",
+    "count",
+    "date",
+    "debug_backtrace",
+    "define",
+    "defined",
+    "dirname",
+    "explode",
+    "extract",
+    "fclose",
+    "feof",
+    "fgets",
+    "file",
+    "file_exists",
+    "file_get_contents",
+    "file_put_contents",
+    "filter_var",
+    "floor",
+    "fopen",
+    "fread",
+    "func_get_args",
+    "function_exists",
+    "fwrite",
+    "get_class",
+    "get_object_vars",
+    "get_parent_class",
+    "gettype",
+    "hash",
+    "header",
+    "htmlspecialchars",
+    "http_build_query",
+    "implode",
+    "in_array",
+    "intdiv",
+    "interface_exists",
+    "intval",
+    "is_a",
+    "is_array",
+    "is_bool",
+    "is_callable",
+    "is_dir",
+    "is_file",
+    "is_float",
+    "is_int",
+    "is_iterable",
+    "is_null",
+    "is_numeric",
+    "is_object",
+    "is_string",
+    "is_subclass_of",
+    "iterator_to_array",
+    "join",
+    "json_decode",
+    "json_encode",
+    "krsort",
+    "ksort",
+    "lcfirst",
+    "ltrim",
+    "max",
+    "mb_str_split",
+    "mb_strlen",
+    "mb_strtolower",
+    "mb_strtoupper",
+    "mb_substr",
+    "md5",
+    "method_exists",
+    "microtime",
+    "min",
+    "mkdir",
+    "mt_rand",
+    "nl2br",
+    "number_format",
+    "ob_get_clean",
+    "ob_start",
+    "ord",
+    "parse_str",
+    "parse_url",
+    "password_hash",
+    "password_verify",
+    "pathinfo",
+    "preg_match",
+    "preg_match_all",
+    "preg_quote",
+    "preg_replace",
+    "preg_replace_callback",
+    "preg_split",
+    "print_r",
+    "printf",
+    "property_exists",
+    "random_int",
+    "range",
+    "rawurlencode",
+    "realpath",
+    "round",
+    "rsort",
+    "rtrim",
+    "serialize",
+    "session_start",
+    "settype",
+    "sha1",
+    "shuffle",
+    "sizeof",
+    "sort",
+    "spl_autoload_register",
+    "spl_object_id",
+    "sprintf",
+    "sqrt",
+    "str_contains",
+    "str_ends_with",
+    "str_pad",
+    "str_repeat",
+    "str_replace",
+    "str_split",
+    "str_starts_with",
+    "strcasecmp",
+    "strcmp",
+    "strip_tags",
+    "stripos",
+    "stripslashes",
+    "strlen",
+    "strpos",
+    "strrev",
+    "strrpos",
+    "strstr",
+    "strtolower",
+    "strtotime",
+    "strtoupper",
+    "strval",
+    "substr",
+    "substr_count",
+    "time",
+    "trigger_error",
+    "trim",
+    "uasort",
+    "ucfirst",
+    "ucwords",
+    "uksort",
+    "uniqid",
+    "unlink",
+    "unserialize",
+    "urldecode",
+    "urlencode",
+    "usleep",
+    "usort",
+    "var_dump",
+    "var_export",
+    "vsprintf",
+    "wordwrap",
+];
+
+/// Classes and interfaces, which are matched case-insensitively as PHP does.
+pub const CLASSES: &[&str] = &[
+    "ArgumentCountError",
+    "ArithmeticError",
+    "ArrayAccess",
+    "ArrayIterator",
+    "ArrayObject",
+    "BackedEnum",
+    "BadFunctionCallException",
+    "BadMethodCallException",
+    "Closure",
+    "Countable",
+    "DateInterval",
+    "DateTime",
+    "DateTimeImmutable",
+    "DateTimeInterface",
+    "DateTimeZone",
+    "DivisionByZeroError",
+    "DomainException",
+    "Error",
+    "ErrorException",
+    "Exception",
+    "Generator",
+    "InvalidArgumentException",
+    "Iterator",
+    "IteratorAggregate",
+    "JsonException",
+    "JsonSerializable",
+    "LengthException",
+    "LogicException",
+    "OutOfBoundsException",
+    "OutOfRangeException",
+    "OverflowException",
+    "PDO",
+    "PDOException",
+    "PDOStatement",
+    "RangeException",
+    "RuntimeException",
+    "SplObjectStorage",
+    "SplStack",
+    "Stringable",
+    "Throwable",
+    "Traversable",
+    "TypeError",
+    "UnderflowException",
+    "UnexpectedValueException",
+    "UnitEnum",
+    "ValueError",
+    "WeakMap",
+    "WeakReference",
+    "stdClass",
+];
+
+/// Constants, which are case-sensitive.
+pub const CONSTANTS: &[&str] = &[
+    "DIRECTORY_SEPARATOR",
+    "E_ALL",
+    "E_DEPRECATED",
+    "E_ERROR",
+    "E_NOTICE",
+    "E_STRICT",
+    "E_USER_DEPRECATED",
+    "E_USER_ERROR",
+    "E_USER_NOTICE",
+    "E_USER_WARNING",
+    "E_WARNING",
+    "JSON_PRETTY_PRINT",
+    "JSON_THROW_ON_ERROR",
+    "JSON_UNESCAPED_SLASHES",
+    "JSON_UNESCAPED_UNICODE",
+    "M_PI",
+    "PHP_EOL",
+    "PHP_INT_MAX",
+    "PHP_INT_MIN",
+    "PHP_INT_SIZE",
+    "PHP_OS",
+    "PHP_OS_FAMILY",
+    "PHP_VERSION",
+    "PHP_VERSION_ID",
+    "SORT_NUMERIC",
+    "SORT_STRING",
+    "__CLASS__",
+    "__DIR__",
+    "__FILE__",
+    "__FUNCTION__",
+    "__LINE__",
+    "__METHOD__",
+    "__NAMESPACE__",
+    "__TRAIT__",
+];
+
+/// The types that aren't classes, as in `int` or `never`.
+pub const TYPES: &[&str] = &[
+    "array", "bool", "callable", "false", "float", "int", "iterable", "mixed", "never", "null",
+    "object", "parent", "self", "static", "string", "true", "void",
+];
+
+/// Whether `name`, which may be qualified, is a function PHP declares.
+pub fn is_function(name: &str) -> bool {
+    contains(FUNCTIONS, name)
+}
+
+/// Whether `name`, which may be qualified, is a class or interface PHP declares.
+pub fn is_class(name: &str) -> bool {
+    contains(CLASSES, name)
+}
+
+pub fn is_constant(name: &str) -> bool {
+    CONSTANTS.binary_search(&name.trim_start_matches('\\')).is_ok()
+}
+
+pub fn is_type(name: &str) -> bool {
+    TYPES.iter().any(|ty| ty.eq_ignore_ascii_case(name))
+}
+
+/// Whether the global `name`, written with a leading backslash or none, is among `names`.
+fn contains(names: &[&str], name: &str) -> bool {
+    let name = name.strip_prefix('\\').unwrap_or(name);
+    !name.contains('\\') && names.iter().any(|known| known.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists_are_sorted() {
+        for list in [FUNCTIONS, CLASSES, CONSTANTS, TYPES] {
+            assert!(list.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", list);
+        }
+    }
+}
@@ -1,15 +1,22 @@
+// chumsky's combinators dictate `Result<_, Simple<Token>>` in `filter_map`/`try_map` closures
+#![allow(clippy::result_large_err)]
+
+use crate::lexer::{self, Lexer};
+use crate::symbol::Symbol;
+use crate::version::{self, PhpVersion};
 use chumsky::{
     error::Simple,
     prelude::{
-        end, filter, filter_map, just, nested_delimiters, none_of, one_of, recursive,
+        any, end, filter, filter_map, just, nested_delimiters, one_of, recursive, skip_parser,
         skip_then_retry_until, take_until,
     },
+    recursive::Recursive,
     text::{self, TextParser},
-    Error, Parser, Stream,
+    BoxedParser, Error, Parser, Stream,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt};
-use tower_lsp::lsp_types::SemanticTokenType;
+use tower_lsp::lsp_types::{SemanticTokenModifier, SemanticTokenType};
 
 pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::FUNCTION,
@@ -20,6 +27,23 @@ pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::KEYWORD,
     SemanticTokenType::OPERATOR,
     SemanticTokenType::PARAMETER,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::INTERFACE,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::TYPE,
+];
+
+pub const LEGEND_MODIFIER: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::STATIC,
+    SemanticTokenModifier::READONLY,
+    SemanticTokenModifier::DEPRECATED,
+    SemanticTokenModifier::ABSTRACT,
+    SemanticTokenModifier::DEFAULT_LIBRARY,
 ];
 
 impl fmt::Display for Token {
@@ -28,51 +52,178 @@ impl fmt::Display for Token {
             Token::Null => write!(f, "null"),
             Token::Bool(x) => write!(f, "{}", x),
             Token::Num(n) => write!(f, "{}", n),
-            Token::Str(s) => write!(f, "{}", s),
+            Token::Str(_, s) => write!(f, "{}", s),
             Token::Op(s) => write!(f, "{}", s),
             Token::Ctrl(c) => write!(f, "{}", c),
             Token::Ident(s) => write!(f, "{}", s),
             Token::Fn => write!(f, "fn"),
+            Token::Function => write!(f, "function"),
             Token::Dollar => write!(f, "$"),
             Token::Echo => write!(f, "echo"),
+            Token::Print => write!(f, "print"),
             Token::If => write!(f, "if"),
             Token::Else => write!(f, "else"),
+            Token::Return => write!(f, "return"),
+            Token::Global => write!(f, "global"),
+            Token::Static => write!(f, "static"),
+            Token::Unset => write!(f, "unset"),
+            Token::Isset => write!(f, "isset"),
+            Token::Empty => write!(f, "empty"),
+            Token::Declare => write!(f, "declare"),
+            Token::Goto => write!(f, "goto"),
+            Token::Exit => write!(f, "exit"),
+            Token::Die => write!(f, "die"),
+            Token::Include => write!(f, "include"),
+            Token::IncludeOnce => write!(f, "include_once"),
+            Token::Require => write!(f, "require"),
+            Token::RequireOnce => write!(f, "require_once"),
+            Token::HaltCompiler => write!(f, "__halt_compiler"),
+            Token::ElseIf => write!(f, "elseif"),
+            Token::EndIf => write!(f, "endif"),
+            Token::While => write!(f, "while"),
+            Token::EndWhile => write!(f, "endwhile"),
+            Token::For => write!(f, "for"),
+            Token::EndFor => write!(f, "endfor"),
+            Token::Foreach => write!(f, "foreach"),
+            Token::EndForeach => write!(f, "endforeach"),
+            Token::As => write!(f, "as"),
+            Token::Switch => write!(f, "switch"),
+            Token::EndSwitch => write!(f, "endswitch"),
+            Token::Case => write!(f, "case"),
+            Token::Default => write!(f, "default"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::OpenTag => write!(f, "<?php"),
+            Token::OpenTagWithEcho => write!(f, "<?="),
+            Token::CloseTag => write!(f, "?>"),
+            Token::InlineHtml(_) => write!(f, "inline HTML"),
+            Token::Class => write!(f, "class"),
+            Token::Interface => write!(f, "interface"),
+            Token::Trait => write!(f, "trait"),
+            Token::Extends => write!(f, "extends"),
+            Token::Implements => write!(f, "implements"),
+            Token::Public => write!(f, "public"),
+            Token::Protected => write!(f, "protected"),
+            Token::Private => write!(f, "private"),
+            Token::Abstract => write!(f, "abstract"),
+            Token::Final => write!(f, "final"),
+            Token::Const => write!(f, "const"),
+            Token::New => write!(f, "new"),
+            Token::Namespace => write!(f, "namespace"),
+            Token::Use => write!(f, "use"),
+            Token::DoubleColon => write!(f, "::"),
+            Token::NullsafeArrow => write!(f, "?->"),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct ParserResult {
-    pub ast: Option<HashMap<String, Func>>, // Ajuste o tipo de acordo com seu AST
+    pub ast: Option<Vec<Item>>,
     pub parse_errors: Vec<Simple<String>>,
+    pub duplicates: Vec<Duplicate>,
     pub semantic_tokens: Vec<ImCompleteSemanticToken>,
 }
 
 pub type Span = std::ops::Range<usize>;
 
+/// The quotes a string is written in. Variables are interpolated into double-quoted strings,
+/// and only there are escapes read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Quote {
+    Single,
+    Double,
+}
+
+impl Quote {
+    pub fn as_char(self) -> char {
+        match self {
+            Quote::Single => '\'',
+            Quote::Double => '"',
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Token {
     Null,
     Bool(bool),
     Num(String),
-    Str(String),
+    Str(Quote, String),
     Op(String),
     Ctrl(char),
-    Ident(String),
+    Ident(Symbol),
     Fn,
+    Function,
     If,
     Else,
     Dollar,
 
     Echo,
+    Print,
+    Return,
+    Global,
+    Static,
+    Unset,
+    Isset,
+    Empty,
+    Declare,
+    Goto,
+    Exit,
+    Die,
+    Include,
+    IncludeOnce,
+    Require,
+    RequireOnce,
+    HaltCompiler,
+
+    ElseIf,
+    EndIf,
+    While,
+    EndWhile,
+    For,
+    EndFor,
+    Foreach,
+    EndForeach,
+    As,
+    Switch,
+    EndSwitch,
+    Case,
+    Default,
+    Break,
+    Continue,
+
+    OpenTag,
+    OpenTagWithEcho,
+    CloseTag,
+    InlineHtml(String),
+
+    Class,
+    Interface,
+    Trait,
+    Extends,
+    Implements,
+    Public,
+    Protected,
+    Private,
+    Abstract,
+    Final,
+    Const,
+    New,
+    Namespace,
+    Use,
+    DoubleColon,
+    NullsafeArrow,
 }
 
 fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
     // A parser for comments
-    let simple_line = just::<_, _, Simple<char>>("//").then(none_of("\n").repeated()).ignored();
+    // Single line comments end at a newline or at the `?>` close tag
+    let line_rest = just("?>").or(just("\n")).not().repeated();
 
-    let simple_line_with_sharp =
-        just::<_, _, Simple<char>>("#").then(none_of("\n").repeated()).ignored();
+    let simple_line = just::<_, _, Simple<char>>("//").then(line_rest).ignored();
+
+    let simple_line_with_sharp = just::<_, _, Simple<char>>("#").then(line_rest).ignored();
 
     let multi_line = just::<_, _, Simple<char>>("/*").then(take_until(just("*/"))).ignored();
 
@@ -89,27 +240,44 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         .ignore_then(filter(|c| *c != '"').repeated())
         .then_ignore(just('"'))
         .collect::<String>()
-        .map(Token::Str);
+        .map(|str| Token::Str(Quote::Double, str));
 
     let str_single = just('\'')
         .ignore_then(filter(|c| *c != '\'').repeated())
         .then_ignore(just('\''))
         .collect::<String>()
-        .map(Token::Str);
+        .map(|str| Token::Str(Quote::Single, str));
 
     let str_ = str_double.or(str_single);
 
     // A parser for identifiers and keywords
-    let ident = text::ident().map(|ident: String| match ident.as_str() {
-        "fn" => Token::Fn,
-        "echo" => Token::Echo,
-        "if" => Token::If,
-        "else" => Token::Else,
-        "true" => Token::Bool(true),
-        "false" => Token::Bool(false),
-        "null" => Token::Null,
-        _ => Token::Ident(ident),
-    });
+    let keyword = |ident: String| lexer::keyword(&ident).unwrap_or(Token::Ident(ident.into()));
+
+    // Qualified names, as in `App\Models\User` or `\strlen`, are a single identifier
+    let ident = just('\\')
+        .or_not()
+        .then(text::ident())
+        .then(just('\\').ignore_then(text::ident()).repeated())
+        .map(move |((root, first), rest): ((_, String), Vec<String>)| {
+            if root.is_none() && rest.is_empty() {
+                return keyword(first);
+            }
+            let name = std::iter::once(first).chain(rest).collect::<Vec<_>>().join("\\");
+            Token::Ident(match root {
+                Some(_) => format!("\\{}", name).into(),
+                None => name.into(),
+            })
+        });
+
+    // A parser for the PHP open tags
+    let tag = just("<?php").to(Token::OpenTag).or(just("<?=").to(Token::OpenTagWithEcho));
+
+    // The close tag swallows a single newline directly after it, as PHP does
+    let close_tag = just("?>")
+        .then(just('\n').or_not())
+        .to(Token::CloseTag)
+        .map_with_span(|tok, span| (tok, span))
+        .chain(inline_html());
 
     // A parser for operators
     let op = one_of("+-*/!=<>").repeated().at_least(1).collect::<String>().map(Token::Op);
@@ -118,42 +286,260 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
     let dollar = one_of("$").repeated().at_least(1).collect::<String>().map(|_| Token::Dollar);
 
     // A parser for control characters
-    let ctrl = one_of("(){}[],:?|;.").map(Token::Ctrl);
+    let ctrl = just("::")
+        .to(Token::DoubleColon)
+        .or(just("?->").to(Token::NullsafeArrow))
+        .or(one_of("(){}[],:?|;.\\&").map(Token::Ctrl));
 
     // A parser for main tokens
     let token = num
         .or(str_)
+        .or(tag)
         .or(op)
+        .or(ident)
         .or(ctrl)
         .or(dollar)
-        .or(ident)
         .recover_with(skip_then_retry_until([]));
 
-    token
-        .padded_by(comment.padded().repeated())
+    // Comments are left out of the token spans
+    let trivia = comment.padded().repeated().padded();
+    let token = token.map_with_span(|tok, span| (tok, span)).padded_by(trivia);
+
+    // `__halt_compiler();` stops the lexer: everything after it is raw data, not PHP
+    let spanned_ctrl =
+        |c| just(c).to(Token::Ctrl(c)).map_with_span(|tok, span| (tok, span)).padded();
+    let halt_compiler = text::keyword("__halt_compiler")
+        .to(Token::HaltCompiler)
         .map_with_span(|tok, span| (tok, span))
         .padded()
-        .repeated()
+        .chain(spanned_ctrl('('))
+        .chain(spanned_ctrl(')'))
+        .chain(spanned_ctrl(';'))
+        .then_ignore(any().repeated());
+
+    // Tokens are followed by their padding, but the first one isn't preceded by any other token
+    trivia.ignore_then(
+        halt_compiler.or(close_tag).or(token.map(|tok| vec![tok])).repeated().flatten(),
+    )
+}
+
+/// Anything outside of the PHP tags is inline HTML
+fn inline_html() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> + Clone {
+    take_until(just("<?php").or(just("<?=")).rewind().ignored().or(end()))
+        .map(|(html, _)| html.into_iter().collect::<String>())
+        .map_with_span(|html, span| match html.is_empty() {
+            true => vec![],
+            false => vec![(Token::InlineHtml(html), span)],
+        })
+}
+
+/// Lexes a whole PHP file, which starts out as inline HTML until the first open tag
+fn file_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
+    inline_html().chain(lexer())
 }
 
 pub type Spanned<T> = (T, Span);
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Func {
-    pub args: Vec<Spanned<String>>,
+    pub args: Vec<Param>,
+    pub ret: Option<Spanned<Type>>,
     pub body: Spanned<Expr>,
-    pub name: Spanned<String>,
+    pub name: Spanned<Symbol>,
+    pub span: Span,
+}
+
+/// A function or method parameter, as in `?int $x = null`.
+#[derive(Clone, Debug)]
+pub struct Param {
+    /// Visibility modifiers promote a constructor parameter to a property
+    pub modifiers: Vec<Spanned<Modifier>>,
+    pub ty: Option<Spanned<Type>>,
+    /// Passed by reference, as in `&$x`
+    pub by_ref: bool,
+    /// Collects the remaining arguments, as in `...$xs`
+    pub variadic: bool,
+    pub name: Spanned<Symbol>,
+    pub default: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+/// A type declaration, as in `?Foo`, `int|string` or `(A&B)|null`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Named(Spanned<Symbol>),
+    Nullable(Box<Type>),
+    Union(Vec<Type>),
+    Intersection(Vec<Type>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    Public,
+    Protected,
+    Private,
+    Static,
+    Abstract,
+    Final,
+    Readonly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClassKind {
+    Class,
+    Interface,
+    Trait,
+    Enum,
+}
+
+#[derive(Clone, Debug)]
+pub struct Class {
+    pub kind: ClassKind,
+    pub modifiers: Vec<Spanned<Modifier>>,
+    pub name: Spanned<Symbol>,
+    /// The type of a backed enum's values, as in `enum Suit: string`
+    pub backing: Option<Spanned<Type>>,
+    pub extends: Vec<Spanned<Symbol>>,
+    pub implements: Vec<Spanned<Symbol>>,
+    pub members: Vec<Member>,
+    pub span: Span,
+}
+
+/// A declaration in the body of a class, interface or trait.
+#[derive(Clone, Debug)]
+pub enum Member {
+    Method(Method),
+    Property(Property),
+    Const(Const),
+    /// A `use Name;` of a trait
+    TraitUse(Spanned<Symbol>),
+    Case(EnumCase),
+}
+
+#[derive(Clone, Debug)]
+pub struct Method {
+    pub modifiers: Vec<Spanned<Modifier>>,
+    pub name: Spanned<Symbol>,
+    pub args: Vec<Param>,
+    pub ret: Option<Spanned<Type>>,
+    /// Abstract and interface methods have no body
+    pub body: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+/// A property, one per name when several share a declaration, as in `public $a, $b;`.
+#[derive(Clone, Debug)]
+pub struct Property {
+    pub modifiers: Vec<Spanned<Modifier>>,
+    pub ty: Option<Spanned<Type>>,
+    pub name: Spanned<Symbol>,
+    pub default: Option<Spanned<Expr>>,
+    pub hooks: Vec<PropertyHook>,
+    pub span: Span,
+}
+
+/// A `get` or `set` hook of a property, as in `get => $this->name;`.
+#[derive(Clone, Debug)]
+pub struct PropertyHook {
+    pub name: Spanned<Symbol>,
+    pub args: Vec<Param>,
+    /// Abstract hooks have no body
+    pub body: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+/// A `case` of an enum, with a value when the enum is backed.
+#[derive(Clone, Debug)]
+pub struct EnumCase {
+    pub name: Spanned<Symbol>,
+    pub value: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+/// A class or top-level constant, one per name when several share a declaration.
+#[derive(Clone, Debug)]
+pub struct Const {
+    pub modifiers: Vec<Spanned<Modifier>>,
+    pub name: Spanned<Symbol>,
+    pub value: Spanned<Expr>,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UseKind {
+    Class,
+    Function,
+    Const,
+}
+
+/// An import, one per name when several share a `use` statement.
+#[derive(Clone, Debug)]
+pub struct Use {
+    pub kind: UseKind,
+    /// The fully qualified name, without a leading backslash
+    pub name: Spanned<Symbol>,
+    pub alias: Option<Spanned<Symbol>>,
+    pub span: Span,
+}
+
+/// A namespace and the items declared in it, either in braces or up to the next namespace.
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    /// `None` for the global namespace, as in `namespace { ... }`
+    pub name: Option<Spanned<Symbol>>,
+    pub items: Vec<Item>,
     pub span: Span,
 }
 
+/// A top-level item of a PHP file.
+#[derive(Clone, Debug)]
+pub enum Item {
+    Func(Func),
+    Class(Class),
+    Const(Const),
+    Use(Use),
+    Namespace(Namespace),
+    Stmt(Spanned<Expr>),
+}
+
+impl Item {
+    pub fn span(&self) -> &Span {
+        match self {
+            Item::Func(func) => &func.span,
+            Item::Class(class) => &class.span,
+            Item::Const(constant) => &constant.span,
+            Item::Use(use_) => &use_.span,
+            Item::Namespace(namespace) => &namespace.span,
+            Item::Stmt((_, span)) => span,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum BinaryOp {
     Add,
     Sub,
     Mul,
     Div,
+    Concat,
     Eq,
     NotEq,
+    Identical,
+    NotIdentical,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Clone, Debug)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    PreInc,
+    PreDec,
+    PostInc,
+    PostDec,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -161,48 +547,308 @@ pub enum Value {
     Null,
     Bool(bool),
     Num(f64),
-    Str(String),
+    Str(Quote, String),
     List(Vec<Value>),
     Func(String),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncludeKind {
+    Include,
+    IncludeOnce,
+    Require,
+    RequireOnce,
+}
+
+#[derive(Clone, Debug)]
 pub enum Expr {
     Error,
     Value(Value),
     List(Vec<Spanned<Self>>),
-    Local(Spanned<String>),
+    /// A variable read, e.g. `$x`
+    Local(Spanned<Symbol>),
+    /// A bare identifier, e.g. a function or constant name
+    Name(Spanned<Symbol>),
 
-    Var(String, Box<Spanned<Self>>, Box<Spanned<Self>>, Span),
+    /// `$name = value;`, binding the statements after it
+    Var(Spanned<Symbol>, Box<Spanned<Self>>, Box<Spanned<Self>>),
 
     Then(Box<Spanned<Self>>, Box<Spanned<Self>>),
     Binary(Box<Spanned<Self>>, BinaryOp, Box<Spanned<Self>>),
     Call(Box<Spanned<Self>>, Spanned<Vec<Spanned<Self>>>),
     If(Box<Spanned<Self>>, Box<Spanned<Self>>, Box<Spanned<Self>>),
 
-    Echo(Box<Spanned<Self>>),
+    Echo(Vec<Spanned<Self>>),
+    Print(Box<Spanned<Self>>),
+    Return(Option<Box<Spanned<Self>>>),
+    Global(Vec<Spanned<Symbol>>),
+    Static(Vec<(Spanned<Symbol>, Option<Spanned<Self>>)>),
+    Unset(Vec<Spanned<Self>>),
+    Isset(Vec<Spanned<Self>>),
+    Empty(Box<Spanned<Self>>),
+    Declare(Vec<(Spanned<Symbol>, Spanned<Self>)>),
+    Goto(Spanned<Symbol>),
+    Label(Spanned<Symbol>),
+    /// `exit` and its alias `die`
+    Exit(Option<Box<Spanned<Self>>>),
+    Include(IncludeKind, Box<Spanned<Self>>),
+    HaltCompiler,
+
+    Assign(Box<Spanned<Self>>, Box<Spanned<Self>>),
+    Unary(UnaryOp, Box<Spanned<Self>>),
+
+    While(Box<Spanned<Self>>, Box<Spanned<Self>>),
+    /// `for (init; condition; step) body`
+    For(
+        Vec<Spanned<Self>>,
+        Vec<Spanned<Self>>,
+        Vec<Spanned<Self>>,
+        Box<Spanned<Self>>,
+    ),
+    /// `foreach (subject as key => value) body`
+    Foreach(
+        Box<Spanned<Self>>,
+        Option<Box<Spanned<Self>>>,
+        Box<Spanned<Self>>,
+        Box<Spanned<Self>>,
+    ),
+    Switch(Box<Spanned<Self>>, Vec<SwitchCase>),
+    Break(Option<Box<Spanned<Self>>>),
+    Continue(Option<Box<Spanned<Self>>>),
+
+    /// Text outside of the PHP tags, echoed as is
+    InlineHtml(String),
+
+    /// `new Foo(args)`, where the arguments are optional
+    New(Box<Spanned<Self>>, Option<Spanned<Vec<Spanned<Self>>>>),
+    /// `$obj->name`, which is a method call when called
+    Prop(Box<Spanned<Self>>, Spanned<Symbol>),
+    /// `Foo::$name`
+    StaticProp(Box<Spanned<Self>>, Spanned<Symbol>),
+    /// `Foo::NAME`, which is a static method call when called
+    ClassConst(Box<Spanned<Self>>, Spanned<Symbol>),
+    /// `$obj?->name`
+    NullsafeProp(Box<Spanned<Self>>, Spanned<Symbol>),
+
+    Match(Box<Spanned<Self>>, Vec<MatchArm>),
+    /// `function (args) use ($captured): type { body }`
+    Closure(
+        Vec<Param>,
+        Vec<Spanned<Symbol>>,
+        Option<Spanned<Type>>,
+        Box<Spanned<Self>>,
+    ),
+    /// `fn (args): type => body`
+    ArrowFn(Vec<Param>, Option<Spanned<Type>>, Box<Spanned<Self>>),
 }
 
-pub fn funcs_parser() -> impl Parser<Token, HashMap<String, Func>, Error = Simple<Token>> + Clone {
+/// An arm of a `match`, whose conditions are `None` for the `default` arm.
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub conditions: Option<Vec<Spanned<Expr>>>,
+    pub body: Spanned<Expr>,
+    pub span: Span,
+}
+
+/// A `case` or `default` (when `test` is `None`) arm of a `switch`.
+#[derive(Clone, Debug)]
+pub struct SwitchCase {
+    pub test: Option<Spanned<Expr>>,
+    pub body: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+/// Parses a top-level item, or the several items that a group `use` or a constant list declares.
+fn item_parser() -> impl Parser<Token, Vec<Item>, Error = Simple<Token>> + Clone {
+    let (raw_expr, expr) = expr_parsers();
+
     let ident = filter_map(|span, tok| match tok {
         Token::Ident(ident) => Ok(ident),
         _ => Err(Simple::expected_input_found(span, Vec::new(), Some(tok))),
     });
+    let name = ident.map_with_span(|name, span| (name, span));
 
-    let args = ident
-        .map_with_span(|name, span| (name, span))
-        .separated_by(just(Token::Ctrl(',')))
-        .allow_trailing()
-        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
-        .labelled("function args");
+    let variable = just(Token::Dollar).ignore_then(ident).map_with_span(|name, span| (name, span));
+
+    let modifiers = modifiers();
+
+    let ty = type_parser();
+
+    let default = just(Token::Op("=".to_string())).ignore_then(raw_expr.clone());
 
-    let func = just(Token::Fn)
-        .ignore_then(ident.map_with_span(|name, span| (name, span)).labelled("function name"))
-        .then(args)
+    let args = params(raw_expr.clone());
+
+    let ret = just(Token::Ctrl(':')).ignore_then(ty.clone()).or_not();
+
+    let body = body(expr.clone());
+
+    let func = just(Token::Function)
+        .ignore_then(name.labelled("function name"))
+        .then(args.clone())
+        .then(ret.clone())
+        .then(body.clone())
+        .map_with_span(|(((name, args), ret), body), span| Func {
+            args,
+            ret,
+            body,
+            name,
+            span,
+        })
+        .labelled("function");
+
+    let member_name = member_name().map_with_span(|name, span| (name, span));
+
+    let method = modifiers
+        .then_ignore(just(Token::Function))
+        .then(member_name.clone())
+        .then(args.clone())
+        .then(ret)
+        .then(body.clone().map(Some).or(just(Token::Ctrl(';')).map(|_| None)))
+        .map_with_span(|((((modifiers, name), args), ret), body), span| {
+            vec![Member::Method(Method {
+                modifiers,
+                name,
+                args,
+                ret,
+                body,
+                span,
+            })]
+        })
+        .labelled("method");
+
+    // Property hooks, as in `{ get => $this->name; set(string $value) { ... } }`
+    let hook = modifiers
+        .ignore_then(just(Token::Ctrl('&')).or_not())
+        .ignore_then(member_name.clone())
+        .then(args.clone().or_not())
         .then(
-            expr_parser()
+            just(Token::Op("=>".to_string()))
+                .ignore_then(raw_expr.clone())
+                .then_ignore(just(Token::Ctrl(';')))
+                .or(body.clone())
+                .map(Some)
+                .or(just(Token::Ctrl(';')).map(|_| None)),
+        )
+        .map_with_span(|((name, args), body), span| PropertyHook {
+            name,
+            args: args.unwrap_or_default(),
+            body,
+            span,
+        });
+    let hooks = hook
+        .repeated()
+        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+        .labelled("property hooks");
+
+    let property = modifiers
+        .then(ty.clone().or_not())
+        .then(
+            // A property with hooks declares a single name
+            variable
+                .clone()
+                .then(default.clone().or_not())
+                .then(hooks)
+                .map(|((name, default), hooks)| vec![(name, default, hooks)])
+                .or(variable
+                    .then(default.or_not())
+                    .map(|(name, default)| (name, default, Vec::new()))
+                    .separated_by(just(Token::Ctrl(',')))
+                    .at_least(1)
+                    .then_ignore(just(Token::Ctrl(';')))),
+        )
+        .map_with_span(|((modifiers, ty), properties), span: Span| {
+            properties
+                .into_iter()
+                .map(|(name, default, hooks)| {
+                    Member::Property(Property {
+                        modifiers: modifiers.clone(),
+                        ty: ty.clone(),
+                        name,
+                        default,
+                        hooks,
+                        span: span.clone(),
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .labelled("property");
+
+    let case = just(Token::Case)
+        .ignore_then(member_name.clone())
+        .then(just(Token::Op("=".to_string())).ignore_then(raw_expr.clone()).or_not())
+        .then_ignore(just(Token::Ctrl(';')))
+        .map_with_span(|(name, value), span| vec![Member::Case(EnumCase { name, value, span })])
+        .labelled("enum case");
+
+    let consts = modifiers
+        .then_ignore(just(Token::Const))
+        .then(
+            member_name
+                .then_ignore(just(Token::Op("=".to_string())))
+                .then(raw_expr)
+                .separated_by(just(Token::Ctrl(',')))
+                .at_least(1),
+        )
+        .then_ignore(just(Token::Ctrl(';')))
+        .map_with_span(|(modifiers, consts), span: Span| {
+            consts
+                .into_iter()
+                .map(|(name, value)| Const {
+                    modifiers: modifiers.clone(),
+                    name,
+                    value,
+                    span: span.clone(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .labelled("constant");
+
+    let trait_use = just(Token::Use)
+        .ignore_then(name.separated_by(just(Token::Ctrl(','))).at_least(1))
+        .then_ignore(just(Token::Ctrl(';')))
+        .map(|names| names.into_iter().map(Member::TraitUse).collect::<Vec<_>>());
+
+    // A member that doesn't parse is skipped up to its semicolon, or up to the next member
+    let member_skipped = skip_braces()
+        .or(filter(|token| *token != Token::Ctrl('}')).ignored())
+        .then(
+            skip_braces()
+                .or(filter(|token| {
+                    !is_member_token(token) && !matches!(token, Token::Ctrl(';' | '}'))
+                })
+                .ignored())
+                .repeated(),
+        )
+        .then(just(Token::Ctrl(';')).or_not())
+        .map(|_| Vec::new());
+
+    let member = method
+        .or(property)
+        .or(consts.clone().map(|consts| consts.into_iter().map(Member::Const).collect()))
+        .or(trait_use)
+        .or(case)
+        .recover_with(skip_parser(member_skipped));
+
+    let class_kind = just(Token::Class)
+        .to(ClassKind::Class)
+        .or(just(Token::Interface).to(ClassKind::Interface))
+        .or(just(Token::Trait).to(ClassKind::Trait))
+        .or(contextual("enum").to(ClassKind::Enum));
+
+    let names = name.separated_by(just(Token::Ctrl(','))).at_least(1);
+
+    let class = modifiers
+        .then(class_kind)
+        .then(name.labelled("class name"))
+        .then(just(Token::Ctrl(':')).ignore_then(ty).or_not())
+        .then(just(Token::Extends).ignore_then(names.clone()).or_not())
+        .then(just(Token::Implements).ignore_then(names).or_not())
+        .then(
+            member
+                .repeated()
+                .flatten()
                 .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
-                // Attempt to recover anything that looks like a function body but contains errors
+                // Attempt to recover anything that looks like a class body but contains errors
                 .recover_with(nested_delimiters(
                     Token::Ctrl('{'),
                     Token::Ctrl('}'),
@@ -210,358 +856,1372 @@ pub fn funcs_parser() -> impl Parser<Token, HashMap<String, Func>, Error = Simpl
                         (Token::Ctrl('('), Token::Ctrl(')')),
                         (Token::Ctrl('['), Token::Ctrl(']')),
                     ],
-                    |span| (Expr::Error, span),
+                    |_| Vec::new(),
                 )),
         )
-        .map_with_span(|((name, args), body), span| {
-            (
-                name.clone(),
-                Func {
-                    args,
-                    body,
+        .map_with_span(
+            |((((((modifiers, kind), name), backing), extends), implements), members), span| {
+                Class {
+                    kind,
+                    modifiers,
                     name,
+                    backing,
+                    extends: extends.unwrap_or_default(),
+                    implements: implements.unwrap_or_default(),
+                    members,
                     span,
-                },
-            )
-        })
-        .labelled("function");
-
-    func.repeated()
-        .try_map(|fs, _| {
-            let mut funcs = HashMap::new();
-            for ((name, name_span), f) in fs {
-                if funcs.insert(name.clone(), f).is_some() {
-                    return Err(Simple::custom(
-                        name_span,
-                        format!("Function '{}' already exists", name),
-                    ));
                 }
-            }
-            Ok(funcs)
+            },
+        )
+        .labelled("class");
+
+    let use_kind = just(Token::Function)
+        .to(UseKind::Function)
+        .or(just(Token::Const).to(UseKind::Const))
+        .or_not()
+        .map(|kind| kind.unwrap_or(UseKind::Class));
+
+    let clause = name.then(just(Token::As).ignore_then(name).or_not());
+
+    // `use App\Models\{User, Post};` imports several names under a common prefix
+    let group = name
+        .then_ignore(just(Token::Ctrl('\\')))
+        .then(
+            clause
+                .clone()
+                .separated_by(just(Token::Ctrl(',')))
+                .allow_trailing()
+                .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+        )
+        .map(|((prefix, _), clauses)| {
+            clauses
+                .into_iter()
+                .map(|((name, span), alias)| {
+                    ((format!("{}\\{}", prefix, name).into(), span), alias)
+                })
+                .collect::<Vec<_>>()
+        });
+
+    let use_ = just(Token::Use)
+        .ignore_then(use_kind)
+        .then(group.or(clause.separated_by(just(Token::Ctrl(','))).at_least(1)))
+        .then_ignore(just(Token::Ctrl(';')))
+        .map_with_span(|(kind, clauses), span: Span| {
+            clauses
+                .into_iter()
+                .map(|((name, name_span), alias)| {
+                    Item::Use(Use {
+                        kind,
+                        name: (name.trim_start_matches('\\').into(), name_span),
+                        alias,
+                        span: span.clone(),
+                    })
+                })
+                .collect::<Vec<_>>()
         })
+        .labelled("use");
+
+    // Anything else at the top level is skipped up to the next semicolon or declaration
+    let skipped = skip_braces()
+        .or(any().ignored())
+        .then(
+            skip_braces()
+                .or(
+                    filter(|token| !is_declaration_token(token) && *token != Token::Ctrl(';'))
+                        .ignored(),
+                )
+                .repeated(),
+        )
+        .then(just(Token::Ctrl(';')).or_not())
+        .map_with_span(|_, span| vec![Item::Stmt((Expr::Error, span))]);
+
+    recursive(|item| {
+        // The items of a namespace, up to whatever token can't start one
+        let namespace_items = |stop: fn(&Token) -> bool| {
+            filter(move |token| !stop(token))
+                .rewind()
+                .ignore_then(item.clone())
+                .repeated()
+                .flatten()
+        };
+
+        let namespace = just(Token::Namespace)
+            .ignore_then(
+                name.or_not()
+                    .then(
+                        namespace_items(|token| {
+                            matches!(token, Token::Namespace | Token::Ctrl('}'))
+                        })
+                        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+                    )
+                    // Without braces, a namespace lasts until the next one
+                    .or(name
+                        .map(Some)
+                        .then_ignore(just(Token::Ctrl(';')))
+                        .then(namespace_items(|token| *token == Token::Namespace))),
+            )
+            .map_with_span(|(name, items), span| {
+                vec![Item::Namespace(Namespace { name, items, span })]
+            })
+            .labelled("namespace");
+
+        namespace
+            .or(use_)
+            .or(consts.map(|consts| consts.into_iter().map(Item::Const).collect()))
+            .or(class.map(|class| vec![Item::Class(class)]))
+            .or(func.map(|func| vec![Item::Func(func)]))
+            .or(expr.map(|stmt| vec![Item::Stmt(stmt)]))
+            .recover_with(skip_parser(skipped))
+    })
+}
+
+pub fn funcs_parser() -> impl Parser<Token, Vec<Item>, Error = Simple<Token>> + Clone {
+    // Inline HTML before the first open tag
+    let leading_html = filter_map(|span, tok| match tok {
+        Token::InlineHtml(html) => Ok(html),
+        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(tok))),
+    })
+    .map_with_span(|html, span| Item::Stmt((Expr::InlineHtml(html), span)))
+    .or_not();
+
+    leading_html
+        .then_ignore(just(Token::OpenTag).or_not())
+        .then(item_parser().repeated().flatten())
+        .map(|(html, items)| html.into_iter().chain(items).collect::<Vec<_>>())
         .then_ignore(end())
 }
 
-fn expr_parser() -> impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone {
-    recursive(|expr| {
-        let raw_expr = recursive(|raw| {
-            let val = filter_map(|span, token| match token {
-                Token::Null => Ok(Expr::Value(Value::Null)),
-                Token::Bool(b) => Ok(Expr::Value(Value::Bool(b))),
-                Token::Num(num) => Ok(Expr::Value(Value::Num(num.parse().unwrap()))),
-                Token::Str(str) => Ok(Expr::Value(Value::Str(str))),
-                _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
-            })
-            .labelled("value");
+/// The parameters of a function, in parentheses, whose default values are `expr`s.
+fn params<P>(expr: P) -> impl Parser<Token, Vec<Param>, Error = Simple<Token>> + Clone
+where
+    P: Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone,
+{
+    let variable = just(Token::Dollar)
+        .ignore_then(filter_map(|span, tok| match tok {
+            Token::Ident(ident) => Ok(ident),
+            _ => Err(Simple::expected_input_found(span, Vec::new(), Some(tok))),
+        }))
+        .map_with_span(|name, span| (name, span));
+
+    let ellipsis = just(Token::Ctrl('.')).repeated().exactly(3);
+
+    modifiers()
+        .then(type_parser().or_not())
+        .then(just(Token::Ctrl('&')).or_not().map(|by_ref| by_ref.is_some()))
+        .then(ellipsis.or_not().map(|variadic| variadic.is_some()))
+        .then(variable)
+        .then(just(Token::Op("=".to_string())).ignore_then(expr).or_not())
+        .map_with_span(
+            |(((((modifiers, ty), by_ref), variadic), name), default), span| Param {
+                modifiers,
+                ty,
+                by_ref,
+                variadic,
+                name,
+                default,
+                span,
+            },
+        )
+        .separated_by(just(Token::Ctrl(',')))
+        .allow_trailing()
+        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+        .labelled("function args")
+}
 
-            let id = filter_map(|span, token| match token {
-                Token::Ident(ident) => Ok((ident, span)),
-                _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
-            })
-            .labelled("identifier");
+/// The body of a function, whose statements are `expr`.
+fn body<P>(expr: P) -> impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone
+where
+    P: Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone,
+{
+    expr.or_not()
+        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+        .map_with_span(|body, span| body.unwrap_or((Expr::Value(Value::Null), span)))
+        // Attempt to recover anything that looks like a function body but contains errors
+        .recover_with(nested_delimiters(
+            Token::Ctrl('{'),
+            Token::Ctrl('}'),
+            [
+                (Token::Ctrl('('), Token::Ctrl(')')),
+                (Token::Ctrl('['), Token::Ctrl(']')),
+            ],
+            |span| (Expr::Error, span),
+        ))
+}
 
-            // A list of expressions
-            let items = expr
-                .clone()
-                .chain(just(Token::Ctrl(',')).ignore_then(expr.clone()).repeated())
-                .then_ignore(just(Token::Ctrl(',')).or_not())
-                .or_not()
-                .map(|item| item.unwrap_or_default());
-
-            // A Variable expression
-            let var_ = just(Token::Dollar)
-                .ignore_then(id)
-                .then_ignore(just(Token::Op("=".to_string())))
-                .then(raw)
-                .then_ignore(just(Token::Ctrl(';')))
-                .then(expr.clone())
-                .map(|((name, val), body)| {
-                    Expr::Var(name.0, Box::new(val), Box::new(body), name.1)
-                });
+fn modifiers() -> impl Parser<Token, Vec<Spanned<Modifier>>, Error = Simple<Token>> + Copy {
+    filter_map(|span, tok| match tok {
+        Token::Public => Ok(Modifier::Public),
+        Token::Protected => Ok(Modifier::Protected),
+        Token::Private => Ok(Modifier::Private),
+        Token::Static => Ok(Modifier::Static),
+        Token::Abstract => Ok(Modifier::Abstract),
+        Token::Final => Ok(Modifier::Final),
+        Token::Ident(ident) if ident.eq_ignore_ascii_case("readonly") => Ok(Modifier::Readonly),
+        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(tok))),
+    })
+    .map_with_span(|modifier, span| (modifier, span))
+    .repeated()
+}
 
-            let array = items
-                .clone()
-                .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
-                .map(Expr::List);
-
-            // 'Atoms' are expressions that contain no ambiguity
-            let atom = val
-                .or(id.map(Expr::Local))
-                .or(var_)
-                .or(array)
-                // In Nano Rust, `print` is just a keyword, just like Python 2, for simplicity
-                .or(just(Token::Echo)
-                    .ignore_then(
-                        expr.clone().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
-                    )
-                    .map(|expr| Expr::Echo(Box::new(expr))))
-                .map_with_span(|expr, span| (expr, span))
-                // Atoms can also just be normal expressions, but surrounded with parentheses
-                .or(expr.clone().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
-                // Attempt to recover anything that looks like a parenthesised expression but contains errors
-                .recover_with(nested_delimiters(
-                    Token::Ctrl('('),
-                    Token::Ctrl(')'),
-                    [
-                        (Token::Ctrl('['), Token::Ctrl(']')),
-                        (Token::Ctrl('{'), Token::Ctrl('}')),
-                    ],
-                    |span| (Expr::Error, span),
-                ))
-                // Attempt to recover anything that looks like a list but contains errors
-                .recover_with(nested_delimiters(
-                    Token::Ctrl('['),
-                    Token::Ctrl(']'),
-                    [
-                        (Token::Ctrl('('), Token::Ctrl(')')),
-                        (Token::Ctrl('{'), Token::Ctrl('}')),
-                    ],
-                    |span| (Expr::Error, span),
-                ));
-
-            // Function calls have very high precedence so we prioritise them
-            let call = atom
-                .then(
-                    items
-                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
-                        .map_with_span(|args, span| (args, span))
-                        .repeated(),
-                )
-                .foldl(|f, args| {
-                    let span = f.1.start..args.1.end;
-                    (Expr::Call(Box::new(f), args), span)
-                });
-
-            // Product ops (multiply and divide) have equal precedence
-            let op = just(Token::Op("*".to_string()))
-                .to(BinaryOp::Mul)
-                .or(just(Token::Op("/".to_string())).to(BinaryOp::Div));
-            let product = call.clone().then(op.then(call).repeated()).foldl(|a, (op, b)| {
-                let span = a.1.start..b.1.end;
-                (Expr::Binary(Box::new(a), op, Box::new(b)), span)
-            });
+/// A keyword that is only reserved in some positions, like `enum` or `match`, and is
+/// otherwise a name.
+fn contextual(keyword: &'static str) -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    filter(move |tok| matches!(tok, Token::Ident(ident) if ident.eq_ignore_ascii_case(keyword)))
+        .ignored()
+        .labelled(keyword)
+}
 
-            // Sum ops (add and subtract) have equal precedence
-            let op = just(Token::Op("+".to_string()))
-                .to(BinaryOp::Add)
-                .or(just(Token::Op("-".to_string())).to(BinaryOp::Sub));
-            let sum = product.clone().then(op.then(product).repeated()).foldl(|a, (op, b)| {
-                let span = a.1.start..b.1.end;
-                (Expr::Binary(Box::new(a), op, Box::new(b)), span)
+/// A type declaration, as in `?Foo`, `int|string` or `(A&B)|null`.
+fn type_parser() -> impl Parser<Token, Spanned<Type>, Error = Simple<Token>> + Clone {
+    let named = filter_map(|span, token| match token {
+        Token::Ident(name) => Ok(Type::Named((name, span))),
+        Token::Static | Token::Null | Token::Bool(_) => {
+            Ok(Type::Named((token.to_string().into(), span)))
+        }
+        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+    })
+    .labelled("type");
+
+    let intersection =
+        named
+            .separated_by(just(Token::Ctrl('&')))
+            .at_least(1)
+            .map(|mut types| match types.len() {
+                1 => types.remove(0),
+                _ => Type::Intersection(types),
             });
 
-            // Comparison ops (equal, not-equal) have equal precedence
-            let op = just(Token::Op("==".to_string()))
-                .to(BinaryOp::Eq)
-                .or(just(Token::Op("!=".to_string())).to(BinaryOp::NotEq));
+    // Inside a union, intersections are grouped in parentheses
+    let group = named
+        .separated_by(just(Token::Ctrl('&')))
+        .at_least(2)
+        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+        .map(Type::Intersection);
+
+    just(Token::Ctrl('?'))
+        .ignore_then(named)
+        .map(|ty| Type::Nullable(Box::new(ty)))
+        .or(
+            group.or(intersection).separated_by(just(Token::Ctrl('|'))).at_least(1).map(
+                |mut types| match types.len() {
+                    1 => types.remove(0),
+                    _ => Type::Union(types),
+                },
+            ),
+        )
+        .map_with_span(|ty, span| (ty, span))
+}
+
+/// A method, property or constant name, which unlike other names may be a keyword.
+fn member_name() -> impl Parser<Token, Symbol, Error = Simple<Token>> + Clone {
+    filter_map(|span, token| match token {
+        Token::Ident(name) => Ok(name),
+        Token::Num(_)
+        | Token::Str(..)
+        | Token::Op(_)
+        | Token::Ctrl(_)
+        | Token::Dollar
+        | Token::DoubleColon
+        | Token::NullsafeArrow
+        | Token::OpenTag
+        | Token::OpenTagWithEcho
+        | Token::CloseTag
+        | Token::InlineHtml(_) => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+        _ => Ok(token.to_string().into()),
+    })
+    .labelled("member name")
+}
 
-            sum.clone().then(op.then(sum).repeated()).foldl(|a, (op, b)| {
-                let span = a.1.start..b.1.end;
-                (Expr::Binary(Box::new(a), op, Box::new(b)), span)
-            })
-        });
+/// Parsers for a single expression and for a sequence of statements.
+fn expr_parsers() -> (
+    impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone,
+    impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone,
+) {
+    let mut expr = Recursive::declare();
+
+    let val = filter_map(|span, token| match token {
+        Token::Null => Ok(Expr::Value(Value::Null)),
+        Token::Bool(b) => Ok(Expr::Value(Value::Bool(b))),
+        Token::Num(num) => Ok(Expr::Value(Value::Num(num.parse().unwrap()))),
+        Token::Str(quote, str) => Ok(Expr::Value(Value::Str(quote, str))),
+        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+    })
+    .labelled("value");
+
+    let id = filter_map(|span, token| match token {
+        Token::Ident(ident) => Ok((ident, span)),
+        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+    })
+    .labelled("identifier");
+
+    // A `$name` variable, spanned over the dollar sign and the name
+    let variable = just(Token::Dollar)
+        .ignore_then(id)
+        .map_with_span(|(name, _), span| (name, span))
+        .labelled("variable");
+
+    // `static` names the called class, as in `static::create()`
+    let static_name =
+        just(Token::Static).map_with_span(|_, span| Expr::Name(("static".into(), span)));
+
+    let raw_expr = recursive(|raw| {
+        // A list of expressions
+        let items = raw
+            .clone()
+            .chain(just(Token::Ctrl(',')).ignore_then(raw.clone()).repeated())
+            .then_ignore(just(Token::Ctrl(',')).or_not())
+            .or_not()
+            .map(|item| item.unwrap_or_default());
 
-        let block = expr
+        // A comma-separated list of statement arguments, as in `echo $a, $b`
+        let args = raw.clone().separated_by(just(Token::Ctrl(','))).at_least(1);
+
+        let parenthesized_args = raw
+            .clone()
+            .separated_by(just(Token::Ctrl(',')))
+            .allow_trailing()
+            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')));
+
+        let array = items
             .clone()
-            .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
-            // Attempt to recover anything that looks like a block but contains errors
+            .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+            .map(Expr::List);
+
+        // `<?= $x ?>` is a shorthand for `<?php echo $x ?>`
+        let echo = just(Token::Echo)
+            .or(just(Token::OpenTagWithEcho))
+            .ignore_then(args.clone())
+            .map(Expr::Echo);
+
+        let print =
+            just(Token::Print).ignore_then(raw.clone()).map(|expr| Expr::Print(Box::new(expr)));
+
+        let return_ = just(Token::Return)
+            .ignore_then(raw.clone().or_not())
+            .map(|expr| Expr::Return(expr.map(Box::new)));
+
+        let global = just(Token::Global)
+            .ignore_then(variable.clone().separated_by(just(Token::Ctrl(','))).at_least(1))
+            .map(Expr::Global);
+
+        let static_ = just(Token::Static)
+            .ignore_then(
+                variable
+                    .clone()
+                    .then(just(Token::Op("=".to_string())).ignore_then(raw.clone()).or_not())
+                    .separated_by(just(Token::Ctrl(',')))
+                    .at_least(1),
+            )
+            .map(Expr::Static);
+
+        let unset = just(Token::Unset).ignore_then(parenthesized_args.clone()).map(Expr::Unset);
+
+        let isset = just(Token::Isset).ignore_then(parenthesized_args).map(Expr::Isset);
+
+        let empty = just(Token::Empty)
+            .ignore_then(raw.clone().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+            .map(|expr| Expr::Empty(Box::new(expr)));
+
+        let declare = just(Token::Declare)
+            .ignore_then(
+                id.then_ignore(just(Token::Op("=".to_string())))
+                    .then(raw.clone())
+                    .separated_by(just(Token::Ctrl(',')))
+                    .at_least(1)
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(Expr::Declare);
+
+        let goto = just(Token::Goto).ignore_then(id).map(Expr::Goto);
+
+        let exit = just(Token::Exit)
+            .or(just(Token::Die))
+            .ignore_then(
+                raw.clone()
+                    .or_not()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+                    .or_not(),
+            )
+            .map(|expr| Expr::Exit(expr.flatten().map(Box::new)));
+
+        let include_kind = just(Token::Include)
+            .to(IncludeKind::Include)
+            .or(just(Token::IncludeOnce).to(IncludeKind::IncludeOnce))
+            .or(just(Token::Require).to(IncludeKind::Require))
+            .or(just(Token::RequireOnce).to(IncludeKind::RequireOnce));
+        let include = include_kind
+            .then(raw.clone())
+            .map(|(kind, expr)| Expr::Include(kind, Box::new(expr)));
+
+        let halt_compiler = just(Token::HaltCompiler)
+            .then(just(Token::Ctrl('(')))
+            .then(just(Token::Ctrl(')')))
+            .map(|_| Expr::HaltCompiler);
+
+        let break_ = just(Token::Break)
+            .ignore_then(raw.clone().or_not())
+            .map(|expr| Expr::Break(expr.map(Box::new)));
+
+        let continue_ = just(Token::Continue)
+            .ignore_then(raw.clone().or_not())
+            .map(|expr| Expr::Continue(expr.map(Box::new)));
+
+        // Statements that PHP spells like expressions
+        // `new Foo(args)`, `new $class` or `new static`
+        let new = just(Token::New)
+            .ignore_then(
+                id.map(Expr::Name)
+                    .or(variable.clone().map(Expr::Local))
+                    .or(static_name.clone())
+                    .map_with_span(|class, span| (class, span)),
+            )
+            .then(
+                items
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+                    .map_with_span(|args, span| (args, span))
+                    .or_not(),
+            )
+            .map(|(class, args)| Expr::New(Box::new(class), args));
+
+        let match_arm = just(Token::Default)
+            .map(|_| None)
+            .or(raw
+                .clone()
+                .separated_by(just(Token::Ctrl(',')))
+                .allow_trailing()
+                .at_least(1)
+                .map(Some))
+            .then_ignore(just(Token::Op("=>".to_string())))
+            .then(raw.clone())
+            .map_with_span(|(conditions, body), span| MatchArm {
+                conditions,
+                body,
+                span,
+            });
+
+        // A `match` is told apart from a call to a function named `match` by its arms
+        let match_ = contextual("match")
+            .ignore_then(raw.clone().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+            .then(
+                match_arm
+                    .separated_by(just(Token::Ctrl(',')))
+                    .allow_trailing()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+            )
+            .map(|(subject, arms)| Expr::Match(Box::new(subject), arms))
+            .boxed();
+
+        let ret = just(Token::Ctrl(':')).ignore_then(type_parser()).or_not();
+
+        // Closures and arrow functions may be static, as in `static fn() => 1`
+        let captured = just(Token::Ctrl('&'))
+            .or_not()
+            .ignore_then(variable.clone())
+            .separated_by(just(Token::Ctrl(',')))
+            .allow_trailing()
+            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')));
+        let closure = just(Token::Static)
+            .or_not()
+            .ignore_then(just(Token::Function))
+            .ignore_then(just(Token::Ctrl('&')).or_not())
+            .ignore_then(params(raw.clone()))
+            .then(just(Token::Use).ignore_then(captured).or_not())
+            .then(ret.clone())
+            .then(body(expr.clone()))
+            .map(|(((args, uses), ret), body)| {
+                Expr::Closure(args, uses.unwrap_or_default(), ret, Box::new(body))
+            })
+            .boxed();
+
+        let arrow_fn = just(Token::Static)
+            .or_not()
+            .ignore_then(just(Token::Fn))
+            .ignore_then(just(Token::Ctrl('&')).or_not())
+            .ignore_then(params(raw.clone()))
+            .then(ret)
+            .then_ignore(just(Token::Op("=>".to_string())))
+            .then(raw.clone())
+            .map(|((args, ret), body)| Expr::ArrowFn(args, ret, Box::new(body)))
+            .boxed();
+
+        let statement = echo
+            .or(print)
+            .or(return_)
+            .or(global)
+            .or(static_)
+            .or(unset)
+            .or(isset)
+            .or(empty)
+            .or(declare)
+            .or(goto)
+            .or(exit)
+            .or(include)
+            .or(halt_compiler)
+            .or(break_)
+            .or(continue_)
+            .boxed();
+
+        // 'Atoms' are expressions that contain no ambiguity
+        let atom = val
+            .or(variable.clone().map(Expr::Local))
+            .or(match_)
+            .or(id.map(Expr::Name))
+            .or(array)
+            .or(new)
+            .or(statement)
+            .or(closure)
+            .or(arrow_fn)
+            .or(static_name)
+            .map_with_span(|expr, span| (expr, span))
+            // Atoms can also just be normal expressions, but surrounded with parentheses
+            .or(raw.clone().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+            // Attempt to recover anything that looks like a parenthesised expression but contains errors
             .recover_with(nested_delimiters(
-                Token::Ctrl('{'),
-                Token::Ctrl('}'),
+                Token::Ctrl('('),
+                Token::Ctrl(')'),
                 [
-                    (Token::Ctrl('('), Token::Ctrl(')')),
                     (Token::Ctrl('['), Token::Ctrl(']')),
+                    (Token::Ctrl('{'), Token::Ctrl('}')),
+                ],
+                |span| (Expr::Error, span),
+            ))
+            // Attempt to recover anything that looks like a list but contains errors
+            .recover_with(nested_delimiters(
+                Token::Ctrl('['),
+                Token::Ctrl(']'),
+                [
+                    (Token::Ctrl('('), Token::Ctrl(')')),
+                    (Token::Ctrl('{'), Token::Ctrl('}')),
                 ],
                 |span| (Expr::Error, span),
+            ))
+            .boxed();
+
+        // Calls and member accesses have very high precedence so we prioritise them
+        let member = member_name().map_with_span(|name, span| (name, span));
+        let postfix_op = items
+            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+            .map_with_span(|args, span| Postfix::Call((args, span)))
+            .or(just(Token::Op("->".to_string())).ignore_then(member.clone()).map(Postfix::Prop))
+            .or(just(Token::NullsafeArrow).ignore_then(member.clone()).map(Postfix::NullsafeProp))
+            .or(just(Token::DoubleColon).ignore_then(
+                variable.clone().map(Postfix::StaticProp).or(member.map(Postfix::ClassConst)),
             ));
+        let call = atom.then(postfix_op.repeated()).foldl(|a, op| match op {
+            Postfix::Call(args) => {
+                let span = a.1.start..args.1.end;
+                (Expr::Call(Box::new(a), args), span)
+            }
+            Postfix::Prop(name) => {
+                let span = a.1.start..name.1.end;
+                (Expr::Prop(Box::new(a), name), span)
+            }
+            Postfix::NullsafeProp(name) => {
+                let span = a.1.start..name.1.end;
+                (Expr::NullsafeProp(Box::new(a), name), span)
+            }
+            Postfix::StaticProp(name) => {
+                let span = a.1.start..name.1.end;
+                (Expr::StaticProp(Box::new(a), name), span)
+            }
+            Postfix::ClassConst(name) => {
+                let span = a.1.start..name.1.end;
+                (Expr::ClassConst(Box::new(a), name), span)
+            }
+        });
 
-        let if_ = recursive(|if_| {
-            just(Token::If)
-                .ignore_then(expr.clone())
-                .then(block.clone())
-                .then(just(Token::Else).ignore_then(block.clone().or(if_)).or_not())
-                .map_with_span(|((cond, a), b), span| {
-                    (
-                        Expr::If(
-                            Box::new(cond),
-                            Box::new(a),
-                            Box::new(match b {
-                                Some(b) => b,
-                                // If an `if` expression has no trailing `else` block, we magic up one that just produces null
-                                None => (Expr::Value(Value::Null), span.clone()),
-                            }),
-                        ),
-                        span,
-                    )
-                })
+        // Postfix increment and decrement, as in `$i++`
+        let op = just(Token::Op("++".to_string()))
+            .to(UnaryOp::PostInc)
+            .or(just(Token::Op("--".to_string())).to(UnaryOp::PostDec));
+        let postfix =
+            call.then(op.map_with_span(|op, span| (op, span)).or_not()).map(|(a, op)| match op {
+                Some((op, span)) => {
+                    let span = a.1.start..span.end;
+                    (Expr::Unary(op, Box::new(a)), span)
+                }
+                None => a,
+            });
+
+        // Prefix ops bind tighter than any binary op
+        let op = just(Token::Op("!".to_string()))
+            .to(UnaryOp::Not)
+            .or(just(Token::Op("-".to_string())).to(UnaryOp::Neg))
+            .or(just(Token::Op("++".to_string())).to(UnaryOp::PreInc))
+            .or(just(Token::Op("--".to_string())).to(UnaryOp::PreDec));
+        let unary = op.map_with_span(|op, span| (op, span)).repeated().then(postfix).foldr(
+            |(op, span), a| {
+                let span = span.start..a.1.end;
+                (Expr::Unary(op, Box::new(a)), span)
+            },
+        );
+
+        // Product ops (multiply and divide) have equal precedence
+        let op = just(Token::Op("*".to_string()))
+            .to(BinaryOp::Mul)
+            .or(just(Token::Op("/".to_string())).to(BinaryOp::Div));
+        let product = unary.clone().then(op.then(unary).repeated()).foldl(|a, (op, b)| {
+            let span = a.1.start..b.1.end;
+            (Expr::Binary(Box::new(a), op, Box::new(b)), span)
         });
 
-        let block_expr = block.or(if_).labelled("block");
+        // Sum ops (add and subtract) have equal precedence
+        let op = just(Token::Op("+".to_string()))
+            .to(BinaryOp::Add)
+            .or(just(Token::Op("-".to_string())).to(BinaryOp::Sub));
+        let sum = product.clone().then(op.then(product).repeated()).foldl(|a, (op, b)| {
+            let span = a.1.start..b.1.end;
+            (Expr::Binary(Box::new(a), op, Box::new(b)), span)
+        });
 
-        let block_chain = block_expr.clone().then(block_expr.clone().repeated()).foldl(|a, b| {
+        // String concatenation binds looser than arithmetic (PHP 8)
+        let op = just(Token::Ctrl('.')).to(BinaryOp::Concat);
+        let concat = sum.clone().then(op.then(sum).repeated()).foldl(|a, (op, b)| {
             let span = a.1.start..b.1.end;
-            (Expr::Then(Box::new(a), Box::new(b)), span)
+            (Expr::Binary(Box::new(a), op, Box::new(b)), span)
         });
 
-        block_chain
-            // Expressions, chained by semicolons, are statements
-            .or(raw_expr.clone())
-            .then(just(Token::Ctrl(';')).ignore_then(expr.or_not()).repeated())
-            .foldl(|a, b| {
-                let span = a.1.clone(); // TODO: Not correct
-                (
-                    Expr::Then(
-                        Box::new(a),
-                        Box::new(match b {
-                            Some(b) => b,
-                            None => (Expr::Value(Value::Null), span.clone()),
-                        }),
-                    ),
+        // Relational ops (less than, greater than) have equal precedence
+        let op = just(Token::Op("<".to_string()))
+            .to(BinaryOp::Lt)
+            .or(just(Token::Op("<=".to_string())).to(BinaryOp::LtEq))
+            .or(just(Token::Op(">".to_string())).to(BinaryOp::Gt))
+            .or(just(Token::Op(">=".to_string())).to(BinaryOp::GtEq));
+        let relational = concat.clone().then(op.then(concat).repeated()).foldl(|a, (op, b)| {
+            let span = a.1.start..b.1.end;
+            (Expr::Binary(Box::new(a), op, Box::new(b)), span)
+        });
+
+        // Comparison ops (equal, not-equal) have equal precedence
+        let op = just(Token::Op("==".to_string()))
+            .to(BinaryOp::Eq)
+            .or(just(Token::Op("!=".to_string())).to(BinaryOp::NotEq))
+            .or(just(Token::Op("===".to_string())).to(BinaryOp::Identical))
+            .or(just(Token::Op("!==".to_string())).to(BinaryOp::NotIdentical));
+        let comparison =
+            relational.clone().then(op.then(relational).repeated()).foldl(|a, (op, b)| {
+                let span = a.1.start..b.1.end;
+                (Expr::Binary(Box::new(a), op, Box::new(b)), span)
+            });
+
+        // Assignment is right associative and has the lowest precedence
+        comparison
+            .then(just(Token::Op("=".to_string())).ignore_then(raw).or_not())
+            .map(|(a, b)| match b {
+                Some(b) => {
+                    let span = a.1.start..b.1.end;
+                    (Expr::Assign(Box::new(a), Box::new(b)), span)
+                }
+                None => a,
+            })
+    });
+
+    let block = expr
+        .clone()
+        .or_not()
+        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+        .map_with_span(|expr, span| expr.unwrap_or((Expr::Value(Value::Null), span)))
+        // Attempt to recover anything that looks like a block but contains errors
+        .recover_with(nested_delimiters(
+            Token::Ctrl('{'),
+            Token::Ctrl('}'),
+            [
+                (Token::Ctrl('('), Token::Ctrl(')')),
+                (Token::Ctrl('['), Token::Ctrl(']')),
+            ],
+            |span| (Expr::Error, span),
+        ));
+
+    // The body of the alternative syntax, as in `if ($x): ... endif;`, which may be empty
+    let alt_body = just(Token::Ctrl(':'))
+        .map_with_span(|_, span: Span| span)
+        .then(expr.clone().or_not())
+        .map(|(colon, body)| body.unwrap_or((Expr::Value(Value::Null), colon.end..colon.end)));
+
+    // Closes an alternative syntax body, reporting mismatched `end*` keywords
+    let end_keyword = |expected: Token, opener: Token| {
+        filter_map(|span, token| match token {
+            Token::EndIf
+            | Token::EndWhile
+            | Token::EndFor
+            | Token::EndForeach
+            | Token::EndSwitch => Ok(token),
+            _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+        })
+        .validate(move |found, span, emit| {
+            if found != expected {
+                emit(Simple::custom(
                     span,
-                )
+                    format!(
+                        "Expected '{}' to close '{}', found '{}'",
+                        expected, opener, found
+                    ),
+                ))
+            }
+        })
+        // The close tag implies a semicolon
+        .then_ignore(
+            just(Token::Ctrl(';'))
+                .ignored()
+                .or(just(Token::CloseTag).rewind().ignored())
+                .or(end()),
+        )
+    };
+
+    let if_ = recursive(|if_| {
+        let condition = raw_expr.clone();
+
+        let else_if = just(Token::ElseIf)
+            .ignore_then(condition.clone())
+            .then(block.clone())
+            .map_with_span(|branch, span| (branch, span));
+
+        let brace_form = condition
+            .clone()
+            .then(block.clone())
+            .then(else_if.repeated())
+            .then(just(Token::Else).ignore_then(block.clone().or(if_)).or_not());
+
+        let else_if = just(Token::ElseIf)
+            .ignore_then(condition.clone())
+            .then(alt_body.clone())
+            .map_with_span(|branch, span| (branch, span));
+
+        let alt_form = condition
+            .then(alt_body.clone())
+            .then(else_if.repeated())
+            .then(just(Token::Else).ignore_then(alt_body.clone()).or_not())
+            .then_ignore(end_keyword(Token::EndIf, Token::If));
+
+        just(Token::If)
+            .ignore_then(brace_form.or(alt_form))
+            .map_with_span(|(((cond, a), else_ifs), b), span| if_chain(cond, a, else_ifs, b, span))
+    });
+
+    let while_ = just(Token::While)
+        .ignore_then(raw_expr.clone())
+        .then(
+            block
+                .clone()
+                .or(alt_body.clone().then_ignore(end_keyword(Token::EndWhile, Token::While))),
+        )
+        .map_with_span(|(cond, body), span| (Expr::While(Box::new(cond), Box::new(body)), span));
+
+    let for_exprs = raw_expr.clone().separated_by(just(Token::Ctrl(',')));
+    let for_ = just(Token::For)
+        .ignore_then(
+            for_exprs
+                .clone()
+                .then_ignore(just(Token::Ctrl(';')))
+                .then(for_exprs.clone())
+                .then_ignore(just(Token::Ctrl(';')))
+                .then(for_exprs)
+                .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+        )
+        .then(
+            block
+                .clone()
+                .or(alt_body.clone().then_ignore(end_keyword(Token::EndFor, Token::For))),
+        )
+        .map_with_span(|(((init, cond), step), body), span| {
+            (Expr::For(init, cond, step, Box::new(body)), span)
+        });
+
+    let foreach = just(Token::Foreach)
+        .ignore_then(
+            raw_expr
+                .clone()
+                .then_ignore(just(Token::As))
+                .then(raw_expr.clone().then_ignore(just(Token::Op("=>".to_string()))).or_not())
+                .then(raw_expr.clone())
+                .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+        )
+        .then(
+            block
+                .clone()
+                .or(alt_body.clone().then_ignore(end_keyword(Token::EndForeach, Token::Foreach))),
+        )
+        .map_with_span(|(((subject, key), value), body), span| {
+            (
+                Expr::Foreach(
+                    Box::new(subject),
+                    key.map(Box::new),
+                    Box::new(value),
+                    Box::new(body),
+                ),
+                span,
+            )
+        });
+
+    // `?> ... <?php` in the middle of a file, which also ends the previous statement
+    let inline_html = just(Token::CloseTag)
+        .ignore_then(
+            filter_map(|span, token| match token {
+                Token::InlineHtml(html) => Ok(html),
+                _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
             })
+            .or_not(),
+        )
+        .then_ignore(
+            just(Token::OpenTag)
+                .ignored()
+                .or(just(Token::OpenTagWithEcho).rewind().ignored())
+                .or(end()),
+        )
+        .map_with_span(|html, span| (Expr::InlineHtml(html.unwrap_or_default()), span));
+
+    // PHP allows a semicolon in place of the colon after a case
+    let case = just(Token::Case)
+        .ignore_then(raw_expr.clone())
+        .map(Some)
+        .or(just(Token::Default).map(|_| None))
+        .then_ignore(just(Token::Ctrl(':')).or(just(Token::Ctrl(';'))))
+        .then(expr.clone().or_not())
+        .map_with_span(|(test, body), span| SwitchCase { test, body, span });
+
+    let switch = just(Token::Switch)
+        .ignore_then(raw_expr.clone())
+        .then(
+            case.clone()
+                .repeated()
+                .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+                // Templates close the tag between `switch (...):` and the first case
+                .or(just(Token::Ctrl(':'))
+                    .ignore_then(inline_html.clone().or_not())
+                    .ignore_then(case.repeated())
+                    .then_ignore(end_keyword(Token::EndSwitch, Token::Switch))),
+        )
+        .map_with_span(|(subject, cases), span| (Expr::Switch(Box::new(subject), cases), span));
+
+    // A `label:` target for `goto`, which like a block needs no trailing semicolon
+    let label = id
+        .then_ignore(just(Token::Ctrl(':')))
+        .map_with_span(|name, span| (Expr::Label(name), span));
+
+    let block_expr = block
+        .or(if_)
+        .or(while_)
+        .or(for_)
+        .or(foreach)
+        .or(switch)
+        .or(label)
+        .or(inline_html.clone())
+        .labelled("block")
+        .boxed();
+
+    let block_chain = block_expr.clone().then(block_expr.clone().repeated()).foldl(|a, b| {
+        let span = a.1.start..b.1.end;
+        (Expr::Then(Box::new(a), Box::new(b)), span)
+    });
+
+    // Blocks need no semicolon before the statements that follow them
+    let block_chain = block_chain.then(expr.clone().or_not()).map(|(a, b)| match b {
+        Some(b) => {
+            let span = a.1.start..b.1.end;
+            (Expr::Then(Box::new(a), Box::new(b)), span)
+        }
+        None => a,
+    });
+
+    // Statements end with a semicolon, or with a close tag followed by inline HTML
+    let separator = just(Token::Ctrl(';')).map_with_span(|_, span| (None, span)).or(inline_html
+        .map(|html| {
+            let span = html.1.clone();
+            (Some(html), span)
+        }));
+
+    let chain = block_chain
+        // Expressions, chained by semicolons, are statements
+        .or(raw_expr.clone())
+        .then(separator.then(expr.clone().or_not()).repeated())
+        .foldl(|a, ((html, separator), b)| {
+            // Nothing after the last separator is an empty statement at its end
+            let b = b.unwrap_or((Expr::Value(Value::Null), separator.end..separator.end));
+            match (a, html) {
+                // A Variable expression: `$name = value;` binds the statements after it
+                ((Expr::Assign(target, val), span), None) if matches!(target.0, Expr::Local(_)) => {
+                    let Expr::Local(name) = target.0 else {
+                        unreachable!()
+                    };
+                    let span = span.start..b.1.end;
+                    (Expr::Var(name, val, Box::new(b)), span)
+                }
+                (a, html) => {
+                    let a = match html {
+                        Some(html) => {
+                            let span = a.1.start..html.1.end;
+                            (Expr::Then(Box::new(a), Box::new(html)), span)
+                        }
+                        None => a,
+                    };
+                    let span = a.1.start..b.1.end;
+                    (Expr::Then(Box::new(a), Box::new(b)), span)
+                }
+            }
+        })
+        // Whatever follows the statements must close their body
+        .then_ignore(filter(is_sync_token).rewind().ignored().or(end()));
+
+    // A statement that doesn't parse is skipped up to and including its semicolon, or up to
+    // whatever closes its body, so that the statements after it are still parsed
+    let skipped = skip_braces()
+        .or(filter(|token| !is_sync_token(token)).ignored())
+        .repeated()
+        .at_least(1)
+        .then(just(Token::Ctrl(';')).or_not())
+        .map_with_span(|_, span| (Expr::Error, span))
+        .then(expr.clone().or_not())
+        .map(|(a, b)| match b {
+            Some(b) => {
+                let span = a.1.start..b.1.end;
+                (Expr::Then(Box::new(a), Box::new(b)), span)
+            }
+            None => a,
+        });
+
+    expr.define(
+        filter(|token| !is_sync_token(token))
+            .rewind()
+            .ignore_then(chain.recover_with(skip_parser(skipped))),
+    );
+
+    (raw_expr, expr)
+}
+
+/// An operation that follows an atom, as in `f(x)` or `$a->b`.
+enum Postfix {
+    Call(Spanned<Vec<Spanned<Expr>>>),
+    Prop(Spanned<Symbol>),
+    NullsafeProp(Spanned<Symbol>),
+    StaticProp(Spanned<Symbol>),
+    ClassConst(Spanned<Symbol>),
+}
+
+/// Tokens that start a top-level declaration.
+pub(crate) fn is_declaration_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Function
+            | Token::Class
+            | Token::Interface
+            | Token::Trait
+            | Token::Abstract
+            | Token::Final
+            | Token::Const
+            | Token::Namespace
+            | Token::Use
+    )
+}
+
+/// Tokens that start a class member, other than `function` which may be preceded by modifiers.
+fn is_member_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Public
+            | Token::Protected
+            | Token::Private
+            | Token::Static
+            | Token::Abstract
+            | Token::Final
+            | Token::Const
+            | Token::Use
+            | Token::Case
+    )
+}
+
+/// Tokens that end a statement or its body, and where parsing resumes after an error.
+fn is_sync_token(token: &Token) -> bool {
+    is_declaration_token(token)
+        || matches!(
+            token,
+            Token::Ctrl(';' | '}')
+                | Token::Else
+                | Token::ElseIf
+                | Token::EndIf
+                | Token::EndWhile
+                | Token::EndFor
+                | Token::EndForeach
+                | Token::EndSwitch
+                | Token::Case
+                | Token::Default
+        )
+}
+
+/// Skips a balanced pair of braces and everything in between.
+fn skip_braces() -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    recursive(|braces| {
+        just(Token::Ctrl('{'))
+            .then(
+                braces
+                    .or(filter(|token| !matches!(token, Token::Ctrl('{' | '}'))).ignored())
+                    .repeated(),
+            )
+            .then(just(Token::Ctrl('}')))
+            .ignored()
     })
 }
 
+/// Nests `elseif` branches into the `else` of the branch before them.
+fn if_chain(
+    cond: Spanned<Expr>,
+    then: Spanned<Expr>,
+    else_ifs: Vec<Spanned<(Spanned<Expr>, Spanned<Expr>)>>,
+    else_: Option<Spanned<Expr>>,
+    span: Span,
+) -> Spanned<Expr> {
+    let else_ = else_ifs.into_iter().rev().fold(else_, |else_, ((cond, then), branch_span)| {
+        let span = branch_span.start..else_.as_ref().map_or(branch_span.end, |b| b.1.end);
+        Some(if_node(cond, then, else_, span))
+    });
+
+    if_node(cond, then, else_, span)
+}
+
+fn if_node(
+    cond: Spanned<Expr>,
+    then: Spanned<Expr>,
+    else_: Option<Spanned<Expr>>,
+    span: Span,
+) -> Spanned<Expr> {
+    (
+        Expr::If(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(match else_ {
+                Some(b) => b,
+                // If an `if` expression has no trailing `else` block, we magic up an empty one at its end
+                None => (Expr::Value(Value::Null), span.end..span.end),
+            }),
+        ),
+        span,
+    )
+}
+
+/// The kind of a declaration, as named in diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Function,
+    Class,
+    Interface,
+    Trait,
+    Enum,
+    Constant,
+    EnumCase,
+    Method,
+    Property,
+}
+
+impl fmt::Display for DeclarationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeclarationKind::Function => write!(f, "Function"),
+            DeclarationKind::Class => write!(f, "Class"),
+            DeclarationKind::Interface => write!(f, "Interface"),
+            DeclarationKind::Trait => write!(f, "Trait"),
+            DeclarationKind::Enum => write!(f, "Enum"),
+            DeclarationKind::EnumCase => write!(f, "Enum case"),
+            DeclarationKind::Constant => write!(f, "Constant"),
+            DeclarationKind::Method => write!(f, "Method"),
+            DeclarationKind::Property => write!(f, "Property"),
+        }
+    }
+}
+
+impl From<ClassKind> for DeclarationKind {
+    fn from(kind: ClassKind) -> Self {
+        match kind {
+            ClassKind::Class => DeclarationKind::Class,
+            ClassKind::Interface => DeclarationKind::Interface,
+            ClassKind::Trait => DeclarationKind::Trait,
+            ClassKind::Enum => DeclarationKind::Enum,
+        }
+    }
+}
+
+/// A declaration whose name is already taken by an earlier declaration in the same scope.
+#[derive(Debug)]
+pub struct Duplicate {
+    pub kind: DeclarationKind,
+    pub name: Spanned<Symbol>,
+    /// The span of the first declaration's name
+    pub first: Span,
+}
+
+impl Duplicate {
+    pub fn message(&self) -> String {
+        format!("{} '{}' already exists", self.kind, self.name.0)
+    }
+}
+
+/// Finds every declaration whose name is already taken, in source order.
+///
+/// Functions and classes share a scope per namespace and, like methods, compare
+/// case-insensitively; constants and properties are case-sensitive.
+pub fn find_duplicates(items: &[Item]) -> Vec<Duplicate> {
+    let mut scope = Scope::default();
+    scope.items(items, "");
+    scope.duplicates
+}
+
+#[derive(Default)]
+struct Scope {
+    functions: HashMap<String, Span>,
+    classes: HashMap<String, Span>,
+    constants: HashMap<String, Span>,
+    duplicates: Vec<Duplicate>,
+}
+
+impl Scope {
+    fn items(&mut self, items: &[Item], namespace: &str) {
+        let qualify = |name: &str| match namespace.is_empty() {
+            true => name.to_string(),
+            false => format!("{}\\{}", namespace, name),
+        };
+
+        for item in items {
+            match item {
+                Item::Func(func) => declare(
+                    &mut self.functions,
+                    qualify(&func.name.0).to_lowercase(),
+                    DeclarationKind::Function,
+                    &func.name,
+                    &mut self.duplicates,
+                ),
+                Item::Class(class) => {
+                    declare(
+                        &mut self.classes,
+                        qualify(&class.name.0).to_lowercase(),
+                        class.kind.into(),
+                        &class.name,
+                        &mut self.duplicates,
+                    );
+                    members(class, &mut self.duplicates);
+                }
+                Item::Const(constant) => declare(
+                    &mut self.constants,
+                    qualify(&constant.name.0),
+                    DeclarationKind::Constant,
+                    &constant.name,
+                    &mut self.duplicates,
+                ),
+                Item::Namespace(Namespace { name, items, .. }) => {
+                    self.items(items, name.as_ref().map_or("", |(name, _)| name))
+                }
+                Item::Use(_) | Item::Stmt(_) => {}
+            }
+        }
+    }
+}
+
+fn members(class: &Class, duplicates: &mut Vec<Duplicate>) {
+    let mut methods = HashMap::new();
+    let mut properties = HashMap::new();
+    let mut constants = HashMap::new();
+
+    for member in &class.members {
+        match member {
+            Member::Method(method) => {
+                declare(
+                    &mut methods,
+                    method.name.0.to_lowercase(),
+                    DeclarationKind::Method,
+                    &method.name,
+                    duplicates,
+                );
+                // Promoted constructor parameters declare properties too
+                if method.name.0.eq_ignore_ascii_case("__construct") {
+                    for param in method.args.iter().filter(|param| !param.modifiers.is_empty()) {
+                        declare(
+                            &mut properties,
+                            param.name.0.to_string(),
+                            DeclarationKind::Property,
+                            &param.name,
+                            duplicates,
+                        );
+                    }
+                }
+            }
+            Member::Property(property) => declare(
+                &mut properties,
+                property.name.0.to_string(),
+                DeclarationKind::Property,
+                &property.name,
+                duplicates,
+            ),
+            Member::Const(constant) => declare(
+                &mut constants,
+                constant.name.0.to_string(),
+                DeclarationKind::Constant,
+                &constant.name,
+                duplicates,
+            ),
+            // Cases and constants share a scope
+            Member::Case(case) => declare(
+                &mut constants,
+                case.name.0.to_string(),
+                DeclarationKind::EnumCase,
+                &case.name,
+                duplicates,
+            ),
+            Member::TraitUse(_) => {}
+        }
+    }
+}
+
+fn declare(
+    declared: &mut HashMap<String, Span>,
+    key: String,
+    kind: DeclarationKind,
+    name: &Spanned<Symbol>,
+    duplicates: &mut Vec<Duplicate>,
+) {
+    match declared.get(&key) {
+        Some(first) => duplicates.push(Duplicate {
+            kind,
+            name: name.clone(),
+            first: first.clone(),
+        }),
+        None => {
+            declared.insert(key, name.1.clone());
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ImCompleteSemanticToken {
     pub start: usize,
     pub length: usize,
     pub token_type: usize,
-    pub debug: Option<String>
+    /// The bits of the token's modifiers in `LEGEND_MODIFIER`
+    pub token_modifiers: u32,
+    pub debug: Option<String>,
 }
 
-pub fn parser(src: &str) -> ParserResult {
-    let (tokens, errors) = lexer().parse_recovery(src);
+impl ImCompleteSemanticToken {
+    pub fn new(
+        span: &Span,
+        token_type: SemanticTokenType,
+        modifiers: &[SemanticTokenModifier],
+    ) -> Self {
+        ImCompleteSemanticToken {
+            start: span.start,
+            length: span.len(),
+            token_type: LEGEND_TYPE.iter().position(|t| *t == token_type).unwrap(),
+            token_modifiers: modifiers
+                .iter()
+                .map(|modifier| 1 << LEGEND_MODIFIER.iter().position(|m| m == modifier).unwrap())
+                .fold(0, |bits, bit| bits | bit),
+            debug: None,
+        }
+    }
+}
 
-    let (ast, tokenize_errors, semantic_tokens) = if let Some(tokens) = tokens {
-        let semantic_tokens = tokens
-            .iter()
-            .filter_map(|(token, span)| match token {
-                Token::Null => None,
-                Token::Bool(_) => None,
-
-                Token::Num(_) => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::NUMBER)
-                        .unwrap(),
-                    debug: Some("Number".to_string())
-                }),
-
-                Token::Str(_) => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::STRING)
-                        .unwrap(),
-                        debug: Some("String".to_string())
-                }),
-
-                Token::Op(_) => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::OPERATOR)
-                        .unwrap(),
-                        debug: Some("Operator".to_string())
-                }),
-
-                Token::Dollar => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::KEYWORD)
-                        .unwrap(),
-                        debug: Some("Dollar".to_string())
-                }),
-
-                Token::Ctrl(_) => None,
-                Token::Ident(_) => None,
-
-                Token::Fn => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::KEYWORD)
-                        .unwrap(),
-                        debug: Some("Function".to_string())
-                }),
-
-                Token::Echo => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::FUNCTION)
-                        .unwrap(),
-                        debug: Some("Echo".to_string())
-                }),
-
-                Token::If => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::KEYWORD)
-                        .unwrap(),
-                        debug: Some("If".to_string())
-                }),
-
-                Token::Else => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .into_iter()
-                        .position(|t| t == &SemanticTokenType::KEYWORD)
-                        .unwrap(),
-                        debug: Some("Else".to_string())
-                }),
-
-                // Token::Var(_) => Some(ImCompleteSemanticToken {
-                //     start: span.start,
-                //     length: span.len(),
-                //     token_type: LEGEND_TYPE
-                //         .into_iter()
-                //         .position(|t| t == &SemanticTokenType::VARIABLE)
-                //         .unwrap(),
-                // }),
+/// The semantic tokens of the keywords, literals and operators among `tokens`. Names are left to
+/// the AST, which tells what they name.
+pub fn semantic_tokens(tokens: &[(Token, Span)]) -> Vec<ImCompleteSemanticToken> {
+    tokens
+        .iter()
+        .filter_map(|(token, span)| {
+            let token_type = match token {
+                Token::Num(_) => SemanticTokenType::NUMBER,
+                Token::Str(..) => SemanticTokenType::STRING,
+                Token::Op(_) => SemanticTokenType::OPERATOR,
+                Token::Dollar
+                | Token::Ctrl(_)
+                | Token::DoubleColon
+                | Token::NullsafeArrow
+                | Token::Ident(_)
+                | Token::OpenTag
+                | Token::OpenTagWithEcho
+                | Token::CloseTag
+                | Token::InlineHtml(_) => return None,
+                _ => SemanticTokenType::KEYWORD,
+            };
+            Some(ImCompleteSemanticToken {
+                debug: Some(token.to_string()),
+                ..ImCompleteSemanticToken::new(span, token_type, &[])
             })
-            .collect::<Vec<_>>();
+        })
+        .collect()
+}
 
-        let len = src.chars().count();
+thread_local! {
+    // Building the combinators is costly, so each thread builds them once and reuses them
+    static FILE_LEXER: BoxedParser<'static, char, Vec<(Token, Span)>, Simple<char>> =
+        file_lexer().boxed();
+    static FILE_PARSER: BoxedParser<'static, Token, Vec<Item>, Simple<Token>> =
+        funcs_parser().boxed();
+    static ITEMS_PARSER: BoxedParser<'static, Token, Vec<Item>, Simple<Token>> =
+        item_parser().repeated().flatten().then_ignore(end()).boxed();
+}
 
-        let (_ast, _tokenize_errors) =
-            funcs_parser().parse_recovery(Stream::from_iter(len..len + 1, tokens.into_iter()));
+/// Lexes a whole PHP file with the combinators, with spans in characters. Documents are lexed
+/// by the faster [`crate::lexer`], which lexes as this does.
+#[allow(clippy::type_complexity)]
+pub fn lex(src: &str) -> (Option<Vec<(Token, Span)>>, Vec<Simple<char>>) {
+    FILE_LEXER.with(|lexer| lexer.parse_recovery(src))
+}
 
-        (
-            _ast,
-            _tokenize_errors,
-            semantic_tokens,
-        )
-    } else {
-        (None, Vec::new(), Vec::new())
-    };
+/// Lexes and parses a whole PHP file, without checking it against a PHP version, and returns
+/// the lexing errors apart from the parsing ones.
+#[allow(clippy::type_complexity)]
+pub(crate) fn parse_file(
+    src: &str,
+) -> (
+    Option<Vec<(Token, Span)>>,
+    Option<Vec<Item>>,
+    Vec<Simple<String>>,
+    Vec<Simple<String>>,
+) {
+    let (tokens, lex_errors) = lexer::tokens(Lexer::new(src), 0);
+    let len = src.chars().count();
+
+    let (ast, parse_errors) = FILE_PARSER.with(|parser| {
+        parser.parse_recovery(Stream::from_iter(len..len + 1, tokens.iter().cloned()))
+    });
 
-    let parse_errors = errors
+    let tokens = Some(tokens);
+    let parse_errors = parse_errors.into_iter().map(|e| e.map(|tok| tok.to_string())).collect();
+    (tokens, ast, lex_errors, parse_errors)
+}
+
+/// Lexes PHP code that starts and ends outside of any tags, as if it started at `offset`, unless
+/// it has errors.
+pub(crate) fn lex_php(src: &str, offset: usize) -> Option<Vec<(Token, Span)>> {
+    let (tokens, errors) = lexer::tokens(Lexer::in_php(src), offset);
+    errors.is_empty().then_some(tokens)
+}
+
+/// Parses the items that `tokens` make up, as if the input ended at `end`.
+pub(crate) fn parse_items(
+    tokens: Vec<(Token, Span)>,
+    end: usize,
+) -> (Option<Vec<Item>>, Vec<Simple<String>>) {
+    let (items, errors) = ITEMS_PARSER
+        .with(|parser| parser.parse_recovery(Stream::from_iter(end..end + 1, tokens.into_iter())));
+    (
+        items,
+        errors.into_iter().map(|e| e.map(|tok| tok.to_string())).collect(),
+    )
+}
+
+/// Parses a PHP file, reporting syntax that is newer than the `version` it targets.
+pub fn parser(src: &str, version: PhpVersion) -> ParserResult {
+    let (tokens, ast, lex_errors, parse_errors) = parse_file(src);
+
+    let parse_errors = lex_errors
         .into_iter()
-        .map(|e| e.map(|c| c.to_string()))
-        .chain(
-            tokenize_errors
-                .into_iter()
-                .map(|e| e.map(|tok| tok.to_string())),
-        )
+        .chain(parse_errors)
+        .chain(ast.as_deref().map(|ast| version::check(ast, version)).unwrap_or_default())
         .collect::<Vec<_>>();
+    let duplicates = ast.as_deref().map(find_duplicates).unwrap_or_default();
+    let semantic_tokens = tokens.as_deref().map(semantic_tokens).unwrap_or_default();
 
     ParserResult {
         ast,
         parse_errors,
+        duplicates,
         semantic_tokens,
     }
 }
@@ -577,7 +2237,7 @@ mod tests {
 
         assert_eq!(result.len(), 5);
         assert_eq!(result[0].0, Token::Dollar);
-        assert_eq!(result[1].0, Token::Ident("x".to_string()));
+        assert_eq!(result[1].0, Token::Ident("x".into()));
         assert_eq!(result[2].0, Token::Op("=".to_string()));
         assert_eq!(result[3].0, Token::Num("5".to_string()));
         assert_eq!(result[4].0, Token::Ctrl(';'));
@@ -590,9 +2250,288 @@ mod tests {
 
         assert_eq!(result.len(), 5);
         assert_eq!(result[0].0, Token::Dollar);
-        assert_eq!(result[1].0, Token::Ident("x".to_string()));
+        assert_eq!(result[1].0, Token::Ident("x".into()));
         assert_eq!(result[2].0, Token::Op("=".to_string()));
         assert_eq!(result[3].0, Token::Num("5".to_string()));
         assert_eq!(result[4].0, Token::Ctrl(';'));
     }
+
+    #[test]
+    fn test_lexer_spans_exclude_comments() {
+        let src = "/* doc */ $x = 5; // trailing\n# sharp\necho $x;";
+        let result = lexer().parse(src).unwrap();
+
+        assert_eq!(result[0], (Token::Dollar, 10..11));
+        assert_eq!(result[4], (Token::Ctrl(';'), 16..17));
+        assert_eq!(result[5], (Token::Echo, 38..42));
+    }
+
+    #[test]
+    fn test_lexer_halt_compiler_stops_lexing() {
+        let src = "ECHO 1; __halt_compiler(); <?php ) raw data (";
+        let result = lexer().parse(src).unwrap();
+
+        let tokens = result.into_iter().map(|(tok, _)| tok).collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Echo,
+                Token::Num("1".to_string()),
+                Token::Ctrl(';'),
+                Token::HaltCompiler,
+                Token::Ctrl('('),
+                Token::Ctrl(')'),
+                Token::Ctrl(';'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_statements() {
+        let src = r#"<?php
+            declare(strict_types=1);
+
+            function counter($step) {
+                global $total;
+                static $count = 0;
+                unset($total);
+                echo "count: ", $count, "\n";
+                if (isset($step)) {
+                    return $count;
+                }
+                retry:
+                goto retry;
+            }
+
+            require_once 'bootstrap.php';
+            die("done");
+        "#;
+        let result = parser(src, PhpVersion::default());
+
+        assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+        let ast = result.ast.unwrap();
+        assert_eq!(ast.len(), 3);
+        assert!(matches!(&ast[0], Item::Stmt((Expr::Then(declare, _), _))
+            if matches!(declare.0, Expr::Declare(ref directives) if directives[0].0 .0 == "strict_types")));
+        assert!(matches!(&ast[1], Item::Func(func) if func.args[0].name.0 == "step"));
+        assert!(matches!(&ast[2], Item::Stmt((Expr::Then(require, _), _))
+            if matches!(require.0, Expr::Include(IncludeKind::RequireOnce, _))));
+    }
+
+    #[test]
+    fn test_lexer_inline_html() {
+        let src = "<p><?php echo 1 ?>\n</p><?= $x ?>";
+        let result = file_lexer().parse(src).unwrap();
+
+        assert_eq!(result[0], (Token::InlineHtml("<p>".to_string()), 0..3));
+        assert_eq!(result[1].0, Token::OpenTag);
+        assert_eq!(result[4], (Token::CloseTag, 16..19));
+        assert_eq!(result[5], (Token::InlineHtml("</p>".to_string()), 19..23));
+        assert_eq!(result[6].0, Token::OpenTagWithEcho);
+        assert_eq!(result[9].0, Token::CloseTag);
+        assert_eq!(result.len(), 10);
+    }
+
+    #[test]
+    fn test_parser_alternative_syntax() {
+        let brace = "<?php if ($a) { echo 1; } elseif ($b) { echo 2; } else { echo 3; }";
+        let alt = "<?php if ($a): echo 1; elseif ($b): echo 2; else: echo 3; endif;";
+
+        for src in [brace, alt] {
+            let result = parser(src, PhpVersion::default());
+
+            assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+            let ast = result.ast.unwrap();
+            let Item::Stmt((Expr::If(_, then, else_), _)) = &ast[0] else {
+                panic!("expected an if statement, got {:?}", ast)
+            };
+            assert!(matches!(then.0, Expr::Then(ref echo, _) if matches!(echo.0, Expr::Echo(_))));
+            assert!(matches!(else_.0, Expr::If(..)));
+        }
+
+        let loops = r#"<?php
+            foreach ($items as $key => $item): echo $item; endforeach;
+            for ($i = 0; $i < 10; $i++): echo $i; endfor;
+            while ($i > 0): $i--; endwhile;
+            switch ($i): case 1: break; default: echo $i; endswitch;
+        "#;
+        let result = parser(loops, PhpVersion::default());
+
+        assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+    }
+
+    #[test]
+    fn test_parser_mismatched_end_keyword() {
+        let src =
+            "<ul>\n<?php foreach ($items as $item): ?>\n<li><?= $item ?></li>\n<?php endwhile ?>";
+        let result = parser(src, PhpVersion::default());
+
+        assert_eq!(result.parse_errors.len(), 1);
+        assert_eq!(
+            result.parse_errors[0].reason(),
+            &chumsky::error::SimpleReason::Custom(
+                "Expected 'endforeach' to close 'foreach', found 'endwhile'".to_string()
+            )
+        );
+        // The foreach is still part of the AST
+        assert!(
+            matches!(&result.ast.unwrap()[1], Item::Stmt((Expr::Then(foreach, _), _))
+            if matches!(foreach.0, Expr::Foreach(..)))
+        );
+    }
+
+    #[test]
+    fn test_parser_recovers_at_statement_level() {
+        let src = r#"<?php
+            function first($a) {
+                echo $a;
+                $x = ;
+                echo $x;
+            }
+
+            } stray
+
+            function second() {
+                $a = 1
+                $b = 2;
+                return $b;
+            }
+        "#;
+        let result = parser(src, PhpVersion::default());
+
+        // One diagnostic per typo
+        assert_eq!(result.parse_errors.len(), 3, "{:?}", result.parse_errors);
+
+        let ast = result.ast.unwrap();
+        assert_eq!(ast.len(), 3);
+        let Item::Func(first) = &ast[0] else {
+            panic!("expected a function, got {:?}", ast[0])
+        };
+        let Expr::Then(_, rest) = &first.body.0 else {
+            panic!("expected statements")
+        };
+        // The bad statement is replaced on its own, and the statement after it survives
+        let Expr::Then(error, rest) = &rest.0 else {
+            panic!("expected statements")
+        };
+        assert!(matches!(error.0, Expr::Error));
+        assert!(matches!(rest.0, Expr::Then(ref echo, _) if matches!(echo.0, Expr::Echo(_))));
+
+        assert!(matches!(ast[1], Item::Stmt((Expr::Error, _))));
+        assert!(matches!(&ast[2], Item::Func(second) if second.name.0 == "second"));
+    }
+
+    #[test]
+    fn test_parser_reports_duplicate_declarations() {
+        let src = r#"<?php
+            namespace App;
+
+            const VERSION = 1;
+            const VERSION = 2;
+
+            function main() {}
+            function MAIN() {}
+
+            class User {
+                const ROLE = 'user';
+                const ROLE = 'admin';
+                public $name;
+                private ?string $name = null;
+
+                public function __construct(public int $id) {}
+                public function id(): int { return $this->id; }
+                public function ID() {}
+            }
+
+            interface user {}
+
+            namespace Other;
+
+            function main() {}
+        "#;
+        let result = parser(src, PhpVersion::default());
+
+        assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+        let duplicates = result
+            .duplicates
+            .iter()
+            .map(|duplicate| {
+                let first = &src[duplicate.first.clone()];
+                (duplicate.message(), first)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            duplicates,
+            vec![
+                ("Constant 'VERSION' already exists".to_string(), "VERSION"),
+                ("Function 'MAIN' already exists".to_string(), "main"),
+                ("Constant 'ROLE' already exists".to_string(), "ROLE"),
+                ("Property 'name' already exists".to_string(), "$name"),
+                ("Method 'ID' already exists".to_string(), "id"),
+                ("Interface 'user' already exists".to_string(), "User"),
+            ]
+        );
+
+        // Both declarations are kept
+        let ast = result.ast.unwrap();
+        let Item::Namespace(app) = &ast[0] else {
+            panic!("expected a namespace, got {:?}", ast[0])
+        };
+        let funcs = app.items.iter().filter(|item| matches!(item, Item::Func(_))).count();
+        assert_eq!(funcs, 2);
+        assert!(matches!(&ast[1], Item::Namespace(other) if other.items.len() == 1));
+    }
+
+    #[test]
+    fn test_parser_reports_syntax_newer_than_target() {
+        let src = r#"<?php
+            enum Suit: string {
+                case Hearts = 'H';
+            }
+
+            final class Point {
+                public function __construct(public readonly int|float $x) {}
+
+                public string $label {
+                    get => strtoupper($this->label);
+                }
+            }
+
+            function render((A&B)|null $shape): string {
+                $name = $shape?->name;
+                $double = fn($n) => $n * 2;
+                return match($name) { 'a' => 1, default => 2 };
+            }
+        "#;
+
+        let result = parser(src, PhpVersion::default());
+        assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+
+        let result = parser(src, PhpVersion::PHP_7_4);
+        let errors = result
+            .parse_errors
+            .iter()
+            .map(|error| match error.reason() {
+                chumsky::error::SimpleReason::Custom(message) => message.split(',').next().unwrap(),
+                reason => panic!("unexpected error {:?}", reason),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "Enum requires PHP 8.1",
+                "Constructor property promotion requires PHP 8.0",
+                "Readonly property requires PHP 8.1",
+                "Union type requires PHP 8.0",
+                "Property hook requires PHP 8.4",
+                "DNF type requires PHP 8.2",
+                "Nullsafe operator requires PHP 8.0",
+                "Match expression requires PHP 8.0",
+            ]
+        );
+
+        // `match` is only a keyword where a match expression is possible
+        let result = parser("<?php match($x); $match = 1;", PhpVersion::PHP_7_4);
+        assert!(result.parse_errors.is_empty(), "{:?}", result.parse_errors);
+    }
 }
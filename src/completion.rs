@@ -0,0 +1,1438 @@
+//! Completion: the variables, names, keywords and members that can be written where the cursor
+//! is.
+//!
+//! What can be written there is told from the tokens before the cursor, which are there even
+//! when the document being typed doesn't parse, and the scope from the nodes the parser recovers
+//! around it.
+
+use crate::builtins;
+use crate::chumsky::{ClassKind, Expr, Item, Modifier, Span, Token, UseKind};
+use crate::document::Document;
+use crate::fuzzy;
+use crate::hover;
+use crate::phpdoc;
+use crate::position::{span_to_range, PositionEncoding};
+use crate::resolve::{
+    class_of, last_segment, namespace_at, variables, Context, Declaration, DeclarationKind,
+    Resolver, Target,
+};
+use crate::visitor::{node_at, Node};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionItemTag,
+    CompletionList, CompletionTextEdit, Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+    TextEdit,
+};
+
+/// The characters that ask for completions as they're typed.
+pub const TRIGGER_CHARACTERS: &[&str] = &["$", ">", ":", "\\"];
+
+/// The most completions sent at once. The client asks again for more as the name is typed.
+const MAX_COMPLETIONS: usize = 200;
+
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "abstract",
+    "break",
+    "class",
+    "const",
+    "continue",
+    "declare",
+    "do",
+    "echo",
+    "enum",
+    "final",
+    "for",
+    "foreach",
+    "function",
+    "global",
+    "goto",
+    "if",
+    "interface",
+    "namespace",
+    "readonly",
+    "return",
+    "static",
+    "switch",
+    "throw",
+    "trait",
+    "try",
+    "unset",
+    "use",
+    "while",
+    "yield",
+];
+
+const EXPRESSION_KEYWORDS: &[&str] = &[
+    "array",
+    "clone",
+    "die",
+    "empty",
+    "exit",
+    "false",
+    "fn",
+    "function",
+    "include",
+    "include_once",
+    "isset",
+    "list",
+    "match",
+    "new",
+    "null",
+    "print",
+    "require",
+    "require_once",
+    "static",
+    "true",
+];
+
+const MEMBER_KEYWORDS: &[&str] = &[
+    "abstract",
+    "const",
+    "final",
+    "function",
+    "private",
+    "protected",
+    "public",
+    "readonly",
+    "static",
+    "use",
+    "var",
+];
+
+const SUPERGLOBALS: &[&str] = &[
+    "GLOBALS", "_COOKIE", "_ENV", "_FILES", "_GET", "_POST", "_REQUEST", "_SERVER", "_SESSION",
+];
+
+/// What can be written where the cursor is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Position {
+    /// Nothing that can be completed, as in a string, a comment or the name of a declaration
+    Nothing,
+    /// A member, after `->`, `?->` or `::`
+    Member,
+    Variable,
+    /// A class of one of the kinds, as after `new` or `implements`
+    Class(&'static [ClassKind]),
+    Type,
+    /// The name a `use` imports, of the kind
+    Import(UseKind),
+    /// A member of the class the cursor is in the body of, after modifiers when `modified`
+    ClassBody {
+        modified: bool,
+    },
+    Statement,
+    Expression,
+}
+
+/// The completions at `offset`, whose edits count columns in the `encoding`. Functions and
+/// methods are completed as calls if the client takes `snippets`.
+pub fn completions(
+    items: &[Item],
+    tokens: &[(Token, Span)],
+    text: &Rope,
+    resolver: &Resolver,
+    offset: usize,
+    encoding: PositionEncoding,
+    snippets: bool,
+) -> Option<CompletionList> {
+    let start = name_start(text, offset);
+    let prefix = text.slice(start..offset).to_string();
+    let context = Context::at(items, offset);
+    let position = position(tokens, text, &context, start, &prefix);
+
+    // `>` and `:` are typed in comparisons and ternaries too, where there's nothing to complete
+    let triggered = offset.checked_sub(1).map(|at| text.char(at));
+    if prefix.is_empty()
+        && matches!(triggered, Some('>' | ':'))
+        && !matches!(position, Position::Member | Position::Type)
+    {
+        return None;
+    }
+
+    // A qualified name is completed segment by segment
+    let (qualifier, query) = match prefix.rfind('\\') {
+        Some(at) => (Some(&prefix[..at]), &prefix[at + 1..]),
+        None => (None, prefix.as_str()),
+    };
+    let mut completions = Completions {
+        context: &context,
+        resolver,
+        imports: Imports::at(items, tokens, offset),
+        text,
+        encoding,
+        calls: snippets && !matches!(position, Position::Import(_)),
+        found: Vec::new(),
+    };
+    match (position, qualifier) {
+        (Position::Nothing | Position::Member | Position::Variable, Some(_)) => return None,
+        (Position::Nothing, None) => return None,
+        (Position::Member, None) => completions.members(items, tokens, text, start)?,
+        (Position::Variable, None) => completions.variables(items, offset, start, true),
+        (position, Some(qualifier)) => {
+            let namespace = match position {
+                Position::Import(_) => qualifier.trim_start_matches('\\').to_string(),
+                _ if qualifier.is_empty() => String::new(),
+                _ => context.scope.class(qualifier),
+            };
+            completions.qualified(position, &namespace);
+        }
+        (position, None) => {
+            completions.keywords(position, tokens, start);
+            if matches!(position, Position::Statement | Position::Expression) {
+                completions.variables(items, offset, start, false);
+            }
+            completions.names(position);
+        }
+    }
+
+    let range = span_to_range(&(offset - query.chars().count()..offset), text, encoding)?;
+    Some(completions.list(query, range))
+}
+
+/// Where the name being typed at `offset` starts, including the `$` of a variable and the
+/// qualifier of a qualified name.
+fn name_start(text: &Rope, offset: usize) -> usize {
+    let mut start = offset;
+    while start > 0 {
+        let c = text.char(start - 1);
+        if !(c.is_alphanumeric() || c == '_' || c == '\\') {
+            break;
+        }
+        start -= 1;
+    }
+    match start > 0 && text.char(start - 1) == '$' {
+        true => start - 1,
+        false => start,
+    }
+}
+
+fn position(
+    tokens: &[(Token, Span)],
+    text: &Rope,
+    context: &Context,
+    start: usize,
+    prefix: &str,
+) -> Position {
+    let index = tokens.partition_point(|(_, span)| span.end <= start);
+    let before = &tokens[..index];
+    if let Some((token, span)) = tokens.get(index) {
+        if matches!(token, Token::Str(..) | Token::InlineHtml(_)) && span.start < start {
+            return Position::Nothing;
+        }
+    }
+    let gap = before.last().map_or(0, |(_, span)| span.end)..start;
+    if is_comment(&text.slice(gap).to_string()) {
+        return Position::Nothing;
+    }
+    if prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Position::Nothing;
+    }
+    if before.last().is_some_and(|(token, _)| is_access(token)) {
+        return Position::Member;
+    }
+    let in_class_body = matches!(context.chain.last(), Some(Node::Class(_)));
+    if prefix.starts_with('$') {
+        // A property is being declared
+        return match in_class_body {
+            true => Position::Nothing,
+            false => Position::Variable,
+        };
+    }
+
+    let token = |back: usize| before.len().checked_sub(back + 1).map(|at| &before[at].0);
+    match (token(1), token(0)) {
+        (Some(Token::Use), Some(Token::Function)) => return Position::Import(UseKind::Function),
+        (Some(Token::Use), Some(Token::Const)) => return Position::Import(UseKind::Const),
+        (_, Some(Token::Use)) if in_class_body => return Position::Class(&[ClassKind::Trait]),
+        (_, Some(Token::Use)) => return Position::Import(UseKind::Class),
+        (_, Some(Token::New)) => return Position::Class(&[ClassKind::Class]),
+        (_, Some(Token::Ident(name))) if name.eq_ignore_ascii_case("instanceof") => {
+            return Position::Class(&[ClassKind::Class, ClassKind::Interface, ClassKind::Enum])
+        }
+        (_, Some(Token::Ident(name))) if name.eq_ignore_ascii_case("enum") => {
+            return Position::Nothing
+        }
+        (
+            _,
+            Some(
+                Token::Function
+                | Token::Fn
+                | Token::Class
+                | Token::Interface
+                | Token::Trait
+                | Token::Const
+                | Token::Namespace
+                | Token::Goto
+                | Token::As
+                | Token::Case,
+            ),
+        ) => return Position::Nothing,
+        _ => {}
+    }
+
+    // The names listed after `extends`, `implements` and `use`
+    let listed = before
+        .iter()
+        .rev()
+        .skip_while(|(token, _)| matches!(token, Token::Ident(_) | Token::Ctrl(',')))
+        .map(|(token, _)| token);
+    match listed.clone().next() {
+        Some(Token::Implements) => return Position::Class(&[ClassKind::Interface]),
+        Some(Token::Extends) => {
+            // Interfaces extend interfaces, and classes a class
+            let declared =
+                listed.skip(2).find(|token| matches!(token, Token::Class | Token::Interface));
+            return match declared {
+                Some(Token::Interface) => Position::Class(&[ClassKind::Interface]),
+                _ => Position::Class(&[ClassKind::Class]),
+            };
+        }
+        Some(Token::Use) if in_class_body => return Position::Class(&[ClassKind::Trait]),
+        _ => {}
+    }
+
+    match token(0) {
+        Some(Token::Ctrl('(' | ',') | Token::Op(_)) if in_parameters(before) => {
+            return Position::Type
+        }
+        // A return type
+        Some(Token::Ctrl(':')) if before.len() >= 2 => {
+            if let Some(open) = opening(&before[..before.len() - 1]) {
+                if is_parameter_list(&before[..open]) {
+                    return Position::Type;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if in_class_body {
+        let modified = matches!(
+            token(0),
+            Some(
+                Token::Public
+                    | Token::Protected
+                    | Token::Private
+                    | Token::Static
+                    | Token::Abstract
+                    | Token::Final
+            )
+        ) || matches!(token(0), Some(Token::Ident(name)) if *name == "readonly" || *name == "var");
+        return Position::ClassBody { modified };
+    }
+    match token(0) {
+        None | Some(Token::OpenTag | Token::Ctrl(';' | '{' | '}')) => Position::Statement,
+        _ => Position::Expression,
+    }
+}
+
+/// Whether `token` accesses a member, as `->`, `?->` and `::` do.
+fn is_access(token: &Token) -> bool {
+    matches!(token, Token::Op(op) if op == "->")
+        || matches!(token, Token::NullsafeArrow | Token::DoubleColon)
+}
+
+/// The span of the expression whose member is accessed by the last of `tokens`, as
+/// `$this->posts()` of `$this->posts()->`, and whether it's accessed statically, with `::`.
+fn receiver(tokens: &[(Token, Span)]) -> Option<(Span, bool)> {
+    let ((access, _), mut rest) = tokens.split_last()?;
+    let end = rest.last()?.1.end;
+    loop {
+        match rest {
+            [.., (Token::Ctrl(')' | ']'), _)] => {
+                rest = &rest[..group_start(rest)?];
+                // What's called or indexed, unless the parentheses only group an expression
+                if !matches!(
+                    rest.last(),
+                    Some((Token::Ident(_) | Token::Static | Token::Ctrl(')' | ']'), _))
+                ) {
+                    break;
+                }
+                continue;
+            }
+            [.., (Token::Dollar, _), (Token::Ident(_), _)] => rest = &rest[..rest.len() - 2],
+            [.., (Token::Ident(_) | Token::Static, _)] => rest = &rest[..rest.len() - 1],
+            _ => return None,
+        }
+        // A chain of accesses goes on through what each is a member of
+        match rest.last() {
+            Some((token, _)) if is_access(token) => rest = &rest[..rest.len() - 1],
+            _ => break,
+        }
+    }
+    let start = tokens[rest.len()].1.start;
+    Some((start..end, *access == Token::DoubleColon))
+}
+
+/// Where the brackets that the last of `tokens` closes are opened.
+fn group_start(tokens: &[(Token, Span)]) -> Option<usize> {
+    let mut depth = 0;
+    for (at, (token, _)) in tokens.iter().enumerate().rev() {
+        match token {
+            Token::Ctrl(')' | ']') => depth += 1,
+            Token::Ctrl('(' | '[') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(at);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether the text between the last token and the cursor leaves the cursor in a comment.
+fn is_comment(gap: &str) -> bool {
+    let line = gap.rsplit('\n').next().unwrap_or_default();
+    let block = gap.rfind("/*");
+    (block.is_some() && block > gap.rfind("*/")) || line.contains("//") || line.contains('#')
+}
+
+/// Where the parenthesis that `tokens` leave open is, if they do.
+fn opening(tokens: &[(Token, Span)]) -> Option<usize> {
+    let mut depth = 0;
+    for (at, (token, _)) in tokens.iter().enumerate().rev() {
+        match token {
+            Token::Ctrl(')') => depth += 1,
+            Token::Ctrl('(') if depth == 0 => return Some(at),
+            Token::Ctrl('(') => depth -= 1,
+            Token::Ctrl(';' | '{' | '}') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether a parenthesis after `tokens` would open the parameters of a function, as after
+/// `function name`, `function` or `fn`.
+fn is_parameter_list(tokens: &[(Token, Span)]) -> bool {
+    matches!(
+        tokens,
+        [.., (Token::Function, _), (Token::Ident(_), _)] | [.., (Token::Function | Token::Fn, _)]
+    )
+}
+
+/// Whether the last of `tokens` is in the parameters of a function, and not in the default
+/// value of one, where a type can be written next.
+fn in_parameters(tokens: &[(Token, Span)]) -> bool {
+    let Some(open) = opening(tokens) else {
+        return false;
+    };
+    match tokens.last() {
+        Some((Token::Op(op), _)) if op != "?" && op != "|" && op != "&" => return false,
+        _ => {}
+    }
+    is_parameter_list(&tokens[..open])
+}
+
+struct Completions<'a> {
+    context: &'a Context<'a>,
+    resolver: &'a Resolver<'a>,
+    imports: Option<Imports>,
+    text: &'a Rope,
+    encoding: PositionEncoding,
+    /// Whether functions and methods are completed as calls, with their parentheses
+    calls: bool,
+    found: Vec<CompletionItem>,
+}
+
+/// How a name is written where it's completed, and the import it needs there, if any.
+struct Written {
+    name: String,
+    import: Option<TextEdit>,
+}
+
+impl Written {
+    fn plain(name: &str) -> Self {
+        Written {
+            name: name.to_string(),
+            import: None,
+        }
+    }
+}
+
+/// The imports of the namespace the cursor is in, and where they go.
+struct Imports {
+    uses: Vec<(UseKind, String, Span)>,
+    /// Where the first import goes: after the `namespace` statement, or after the open tag and
+    /// the `declare` that must come first
+    start: usize,
+}
+
+impl Imports {
+    fn at(items: &[Item], tokens: &[(Token, Span)], offset: usize) -> Option<Self> {
+        let namespace = namespace_at(items, offset);
+        let uses = namespace
+            .map_or(items, |namespace| &namespace.items)
+            .iter()
+            .filter_map(|item| match item {
+                Item::Use(use_) => Some((use_.kind, use_.name.0.to_string(), use_.span.clone())),
+                _ => None,
+            })
+            .collect();
+        let (from, ends) = match namespace {
+            Some(namespace) => {
+                let end =
+                    namespace.name.as_ref().map_or(namespace.span.start, |(_, span)| span.end);
+                let from = tokens.partition_point(|(_, span)| span.start < end);
+                (from, &[Token::Ctrl(';'), Token::Ctrl('{')][..])
+            }
+            None => {
+                let open = tokens.iter().position(|(token, _)| *token == Token::OpenTag)?;
+                match tokens.get(open + 1) {
+                    Some((Token::Declare, _)) => (open + 1, &[Token::Ctrl(';')][..]),
+                    _ => (open, &[Token::OpenTag][..]),
+                }
+            }
+        };
+        let (_, end) = tokens[from..].iter().find(|(token, _)| ends.contains(token))?;
+        Some(Imports {
+            uses,
+            start: end.end,
+        })
+    }
+
+    /// The offset where the import of `name` as a `kind` goes and the text to insert there: in
+    /// order among the imports of its kind, which go classes first, then functions and then
+    /// constants.
+    fn edit(&self, kind: UseKind, name: &str, text: &Rope) -> (usize, String) {
+        let statement = match kind {
+            UseKind::Class => format!("use {};", name),
+            UseKind::Function => format!("use function {};", name),
+            UseKind::Const => format!("use const {};", name),
+        };
+        let rank = |kind: UseKind| kind as u8;
+        let key = (rank(kind), name.to_lowercase());
+        let key_of =
+            |(kind, name, _): &&(UseKind, String, Span)| (rank(*kind), name.to_lowercase());
+        let before = self.uses.iter().rfind(|used| key_of(used) < key);
+        let after = self.uses.iter().find(|used| key_of(used) > key);
+        // Next to the imports of its kind, rather than past the line between the kinds
+        let after = after.filter(|(other, _, _)| *other == kind || before.is_none());
+        match (before, after) {
+            (_, Some((_, _, span))) => {
+                let line = text.line_to_char(text.char_to_line(span.start));
+                (line, format!("{}\n", statement))
+            }
+            (Some((_, _, span)), None) => (span.end, format!("\n{}", statement)),
+            (None, None) => (self.start, format!("\n\n{}", statement)),
+        }
+    }
+}
+
+impl Completions<'_> {
+    /// The variables that can be read at `offset`, except the one being typed at `start`.
+    fn variables(&mut self, items: &[Item], offset: usize, start: usize, typed: bool) {
+        let mut names = Vec::new();
+        for ((name, span), scope) in variables(items) {
+            if span.start != start
+                && self.context.variable_scope(&name) == scope
+                && !names.contains(&name.to_string())
+            {
+                names.push(name.to_string());
+            }
+        }
+        let in_method = self.context.chain.iter().any(|node| matches!(node, Node::Method(_)));
+        if in_method && self.context.class.is_some() && !names.iter().any(|name| name == "this") {
+            names.push("this".to_string());
+        }
+        let superglobals = SUPERGLOBALS.iter().map(|name| name.to_string());
+        names.extend(superglobals.filter(|name| !names.contains(name)).collect::<Vec<_>>());
+
+        for name in names {
+            let ty = self.resolver.variable_type(self.context, &name, offset);
+            let label = format!("${}", name);
+            self.found.push(CompletionItem {
+                filter_text: (!typed).then(|| name.clone()),
+                detail: ty,
+                kind: Some(CompletionItemKind::VARIABLE),
+                ..item(label)
+            });
+        }
+    }
+
+    /// The members of what's accessed before `start` that can be accessed from where it is.
+    fn members(
+        &mut self,
+        items: &[Item],
+        tokens: &[(Token, Span)],
+        text: &Rope,
+        start: usize,
+    ) -> Option<()> {
+        let before = &tokens[..tokens.partition_point(|(_, span)| span.end <= start)];
+        let (receiver, is_static) = receiver(before)?;
+        // The statement being typed ends at the cursor, and so isn't found there, but at what
+        // it's accessed on
+        let context = Context::at(items, receiver.start);
+        let named = match &before[before.len() - 2..] {
+            [(Token::Ident(name), span), _] if span.start == receiver.start => Some(name.as_ref()),
+            [(Token::Static, span), _] if span.start == receiver.start => Some("static"),
+            _ => None,
+        };
+        let class = match named.filter(|_| is_static) {
+            Some(name) => context.class(name)?,
+            None => {
+                // What's accessed is parsed where it's written, so that the variables in it are
+                // looked up at their offsets
+                let source = text.slice(receiver.clone()).to_string();
+                let padding = receiver.start.checked_sub("<?php".len())?;
+                let source = format!("<?php{}{};", " ".repeat(padding), source);
+                let document = Document::new(&source);
+                let chain = node_at(document.items()?, receiver.end - 1);
+                let expr = chain.into_iter().find_map(|node| match node {
+                    Node::Expr(expr)
+                        if receiver.start <= expr.1.start && expr.1.end <= receiver.end =>
+                    {
+                        Some(expr)
+                    }
+                    _ => None,
+                })?;
+                let ty = self.resolver.type_of(&context, expr)?;
+                class_of(&ty)?.to_string()
+            }
+        };
+        // `parent::method()` and the like call methods of the object, not static ones
+        let forwarding = named.is_some_and(|name| {
+            ["parent", "self", "static"].iter().any(|keyword| name.eq_ignore_ascii_case(keyword))
+        });
+
+        for (owner, member) in self.resolver.members(&class) {
+            if !self.is_visible(&owner, member) {
+                continue;
+            }
+            let instance = !member.modifiers.contains(&Modifier::Static);
+            let accessible = match member.kind {
+                DeclarationKind::Method => {
+                    let magic = member.name.starts_with("__");
+                    match is_static {
+                        true => !instance || forwarding,
+                        false => !magic,
+                    }
+                }
+                DeclarationKind::Property => is_static != instance,
+                _ => is_static,
+            };
+            if !accessible {
+                continue;
+            }
+            let label = match (member.kind, is_static) {
+                (DeclarationKind::Property, true) => format!("${}", member.name),
+                _ => member.name.clone(),
+            };
+            let found = CompletionItem {
+                kind: Some(completion_kind(member.kind)),
+                data: data(member),
+                ..item(label)
+            };
+            let found = match member.kind {
+                DeclarationKind::Method => self.call(found, has_parameters(member)),
+                _ => found,
+            };
+            self.found.push(found);
+        }
+
+        // What every enum has, and `Foo::class`
+        let declared = self.resolver.find(&Target::Class(class.clone()));
+        let declared = declared.first().copied();
+        if let Some(enum_) = declared.filter(|d| d.kind == DeclarationKind::Class(ClassKind::Enum))
+        {
+            let backed = enum_.signature.contains(':');
+            let members: &[(_, _, _)] = match is_static {
+                true => &[
+                    ("cases", "public static function cases(): array", false),
+                    (
+                        "from",
+                        "public static function from(int|string $value): static",
+                        true,
+                    ),
+                    (
+                        "tryFrom",
+                        "public static function tryFrom(int|string $value): ?static",
+                        true,
+                    ),
+                ],
+                false => &[
+                    ("name", "public readonly string $name", false),
+                    ("value", "public readonly int|string $value", true),
+                ],
+            };
+            for (name, signature, only_backed) in members {
+                if backed || !only_backed {
+                    let found = CompletionItem {
+                        kind: Some(match is_static {
+                            true => CompletionItemKind::METHOD,
+                            false => CompletionItemKind::PROPERTY,
+                        }),
+                        detail: Some(signature.to_string()),
+                        ..item(name.to_string())
+                    };
+                    // Of the methods, only `cases` takes no parameters
+                    let found = match is_static {
+                        true => self.call(found, *only_backed),
+                        false => found,
+                    };
+                    self.found.push(found);
+                }
+            }
+        }
+        if is_static {
+            self.found.push(CompletionItem {
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(class),
+                ..item("class".to_string())
+            });
+        }
+        Some(())
+    }
+
+    /// Whether a member of the class `owner` can be accessed from where the cursor is: a private
+    /// one only in its class, and a protected one in the classes related to its class.
+    fn is_visible(&self, owner: &str, member: &Declaration) -> bool {
+        let current = self.context.class.as_deref();
+        if member.modifiers.contains(&Modifier::Private) {
+            current.is_some_and(|current| current.eq_ignore_ascii_case(owner))
+        } else if member.modifiers.contains(&Modifier::Protected) {
+            current.is_some_and(|current| {
+                self.resolver.is_a(current, owner) || self.resolver.is_a(owner, current)
+            })
+        } else {
+            true
+        }
+    }
+
+    /// The keywords that can be written at the `position`.
+    fn keywords(&mut self, position: Position, tokens: &[(Token, Span)], start: usize) {
+        let chain = &self.context.chain;
+        let in_function = chain.iter().any(|node| {
+            matches!(
+                node,
+                Node::Func(_)
+                    | Node::Method(_)
+                    | Node::PropertyHook(_)
+                    | Node::Expr((Expr::Closure(..) | Expr::ArrowFn(..), _))
+            )
+        });
+        let in_loop = chain.iter().any(|node| {
+            matches!(
+                node,
+                Node::Expr((
+                    Expr::While(..) | Expr::For(..) | Expr::Foreach(..) | Expr::Switch(..),
+                    _
+                ))
+            )
+        });
+        let after_block = tokens[..tokens.partition_point(|(_, span)| span.end <= start)]
+            .last()
+            .is_some_and(|(token, _)| *token == Token::Ctrl('}'));
+
+        let mut keywords: Vec<&str> = Vec::new();
+        match position {
+            Position::ClassBody { modified } => {
+                keywords.extend(MEMBER_KEYWORDS);
+                if matches!(chain.last(), Some(Node::Class(class)) if class.kind == ClassKind::Enum)
+                {
+                    keywords.push("case");
+                }
+                if modified {
+                    self.types();
+                }
+            }
+            Position::Statement => {
+                keywords.extend(STATEMENT_KEYWORDS.iter().filter(|keyword| match **keyword {
+                    "declare" | "namespace" | "use" => !in_function,
+                    "yield" => in_function,
+                    "break" | "continue" => in_loop,
+                    _ => true,
+                }));
+                if after_block {
+                    keywords.extend(["catch", "else", "elseif", "finally"]);
+                }
+                keywords.extend(EXPRESSION_KEYWORDS);
+            }
+            Position::Expression => keywords.extend(EXPRESSION_KEYWORDS),
+            Position::Type => self.types(),
+            _ => {}
+        }
+        if matches!(position, Position::Statement | Position::Expression)
+            && self.context.class.is_some()
+        {
+            keywords.extend(["parent", "self"]);
+        }
+        keywords.sort_unstable();
+        keywords.dedup();
+        for keyword in keywords {
+            self.keyword(keyword);
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) {
+        self.found.push(CompletionItem {
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..item(keyword.to_string())
+        });
+    }
+
+    /// The types that aren't classes, as in `int`.
+    fn types(&mut self) {
+        for ty in builtins::TYPES {
+            if self.context.class.is_some() || !matches!(*ty, "parent" | "self" | "static") {
+                self.keyword(ty);
+            }
+        }
+    }
+
+    /// The classes, functions and constants that can be named at the `position`, as they're
+    /// written in the scope.
+    fn names(&mut self, position: Position) {
+        let scope = &self.context.scope;
+        let declarations = self.resolver.declarations.iter().map(|(_, declaration)| *declaration);
+        for declaration in declarations.filter(|declaration| !declaration.is_member()) {
+            if !is_wanted(position, declaration.kind) {
+                continue;
+            }
+            let written = match position {
+                Position::Import(_) => Written::plain(&declaration.name),
+                _ => self.written(use_kind(declaration.kind), &declaration.name),
+            };
+            self.declaration(declaration, written);
+        }
+
+        // What PHP declares is global
+        let builtins: [(&[&str], _); 3] = [
+            (builtins::CLASSES, DeclarationKind::Class(ClassKind::Class)),
+            (builtins::FUNCTIONS, DeclarationKind::Function),
+            (builtins::CONSTANTS, DeclarationKind::Constant),
+        ];
+        for (names, kind) in builtins {
+            if !is_builtin_wanted(position, kind) {
+                continue;
+            }
+            for name in names {
+                let written = match position {
+                    Position::Import(_) => Written::plain(name),
+                    _ => self.written(use_kind(kind), name),
+                };
+                self.name(name, written, completion_kind(kind));
+            }
+        }
+
+        // Imports of namespaces, as in `use App\Models;`
+        for (kind, alias, name) in scope.uses() {
+            if kind == UseKind::Class
+                && !matches!(position, Position::Import(_))
+                && !self.found.iter().any(|found| found.label == alias)
+            {
+                self.found.push(CompletionItem {
+                    detail: Some(name.to_string()),
+                    kind: Some(CompletionItemKind::MODULE),
+                    ..item(alias.to_string())
+                });
+            }
+        }
+    }
+
+    /// The names declared in `namespace`, and the namespaces in it, for the segment after a
+    /// qualifier.
+    fn qualified(&mut self, position: Position, namespace: &str) {
+        let declarations = self.resolver.declarations.iter().map(|(_, declaration)| *declaration);
+        let mut namespaces: Vec<&str> = Vec::new();
+        for declaration in declarations.filter(|declaration| !declaration.is_member()) {
+            let (declared_in, _) = declaration.name.rsplit_once('\\').unwrap_or(("", ""));
+            if declared_in.eq_ignore_ascii_case(namespace) {
+                if is_wanted(position, declaration.kind) {
+                    self.declaration(declaration, Written::plain(declaration.short_name()));
+                }
+                continue;
+            }
+            let nested = match namespace.is_empty() {
+                true => Some(declared_in),
+                false => declared_in
+                    .get(..namespace.len())
+                    .filter(|head| head.eq_ignore_ascii_case(namespace))
+                    .and_then(|_| declared_in[namespace.len()..].strip_prefix('\\')),
+            };
+            let Some(segment) = nested.and_then(|nested| nested.split('\\').next()) else {
+                continue;
+            };
+            if !segment.is_empty() && !namespaces.contains(&segment) {
+                namespaces.push(segment);
+            }
+        }
+        for segment in namespaces {
+            self.found.push(CompletionItem {
+                kind: Some(CompletionItemKind::MODULE),
+                ..item(segment.to_string())
+            });
+        }
+        let class = DeclarationKind::Class(ClassKind::Class);
+        if namespace.is_empty() && is_builtin_wanted(position, class) {
+            for name in builtins::CLASSES {
+                self.name(name, Written::plain(name), CompletionItemKind::CLASS);
+            }
+        }
+    }
+
+    fn declaration(&mut self, declaration: &Declaration, written: Written) {
+        let namespace = declaration.name.rsplit_once('\\').map(|(namespace, _)| namespace);
+        let found = CompletionItem {
+            label_details: namespace.map(|namespace| CompletionItemLabelDetails {
+                detail: None,
+                description: Some(namespace.to_string()),
+            }),
+            data: data(declaration),
+            ..self.named(
+                declaration.short_name(),
+                written,
+                completion_kind(declaration.kind),
+            )
+        };
+        let found = match declaration.kind {
+            DeclarationKind::Function => self.call(found, has_parameters(declaration)),
+            _ => found,
+        };
+        self.found.push(found);
+    }
+
+    fn name(&mut self, name: &str, written: Written, kind: CompletionItemKind) {
+        let item = self.named(name, written, kind);
+        // What built-in functions take isn't known, so the cursor goes between the parentheses
+        let item = match kind {
+            CompletionItemKind::FUNCTION => self.call(item, true),
+            _ => item,
+        };
+        self.found.push(item);
+    }
+
+    /// `item` as a call, as a snippet that leaves the cursor between the parentheses if there are
+    /// `parameters` to write, or past them.
+    fn call(&self, item: CompletionItem, parameters: bool) -> CompletionItem {
+        if !self.calls {
+            return item;
+        }
+        let text = item.insert_text.as_deref().unwrap_or(&item.label);
+        let text = text.replace('\\', "\\\\").replace('$', "\\$").replace('}', "\\}");
+        CompletionItem {
+            insert_text: Some(match parameters {
+                true => format!("{}($0)", text),
+                false => format!("{}()$0", text),
+            }),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..item
+        }
+    }
+
+    /// An item for the name `name`, which is written as `written`.
+    fn named(&self, name: &str, written: Written, kind: CompletionItemKind) -> CompletionItem {
+        let Written { name: text, import } = written;
+        CompletionItem {
+            kind: Some(kind),
+            filter_text: (text != name).then(|| name.to_string()),
+            insert_text: (text != name).then_some(text),
+            additional_text_edits: import.map(|import| vec![import]),
+            ..item(name.to_string())
+        }
+    }
+
+    /// How the class, function or constant `name` is written where the cursor is: as what it's
+    /// imported as, or by its own name in its namespace. Anything else is imported, unless its
+    /// name stands for something else there and it's written fully qualified. Functions and
+    /// constants fall back to the global ones, so those are never imported.
+    fn written(&self, kind: UseKind, name: &str) -> Written {
+        let scope = &self.context.scope;
+        let imported = scope.uses().find(|(use_kind, _, imported)| {
+            *use_kind == kind && imported.eq_ignore_ascii_case(name)
+        });
+        if let Some((_, alias, _)) = imported {
+            return Written::plain(alias);
+        }
+        let namespace = name.rsplit_once('\\').map(|(namespace, _)| namespace);
+        let local = match (namespace, &scope.namespace) {
+            (None, _) => kind != UseKind::Class || scope.namespace.is_none(),
+            (Some(namespace), Some(current)) => namespace.eq_ignore_ascii_case(current),
+            (Some(_), None) => false,
+        };
+        let short = last_segment(name);
+        if local {
+            return Written::plain(short);
+        }
+        let is_taken = scope
+            .uses()
+            .any(|(use_kind, alias, _)| use_kind == kind && alias.eq_ignore_ascii_case(short))
+            || self.resolver.declarations.iter().any(|(_, declaration)| {
+                !declaration.is_member()
+                    && use_kind(declaration.kind) == kind
+                    && declaration.name.eq_ignore_ascii_case(&scope.qualify(short))
+            });
+        let import = self.imports.as_ref().filter(|_| !is_taken).and_then(|imports| {
+            let (at, text) = imports.edit(kind, name, self.text);
+            let range = span_to_range(&(at..at), self.text, self.encoding)?;
+            Some(TextEdit::new(range, text))
+        });
+        match import {
+            Some(import) => Written {
+                name: short.to_string(),
+                import: Some(import),
+            },
+            None => Written::plain(&format!("\\{}", name)),
+        }
+    }
+
+    /// The best of what was found for the `query`, first, each replacing the `range` it's typed
+    /// in.
+    fn list(self, query: &str, range: tower_lsp::lsp_types::Range) -> CompletionList {
+        let mut found = Vec::new();
+        for item in self.found {
+            // Every variable starts with a `$`, which says nothing of how well it matches
+            let filter = item.filter_text.as_deref().unwrap_or(&item.label);
+            let Some(score) = fuzzy::score(
+                query.trim_start_matches('$'),
+                filter.trim_start_matches('$'),
+            ) else {
+                continue;
+            };
+            let duplicate = found.iter().any(|(_, other): &(i32, CompletionItem)| {
+                other.label == item.label
+                    && other.kind == item.kind
+                    && other.insert_text == item.insert_text
+            });
+            if !duplicate {
+                found.push((score, item));
+            }
+        }
+        found.sort_by_key(|(score, item)| (Reverse(*score), priority(item.kind)));
+        let is_incomplete = found.len() > MAX_COMPLETIONS;
+        found.truncate(MAX_COMPLETIONS);
+        let items = found.into_iter().enumerate().map(|(rank, (_, item))| {
+            let new_text = item.insert_text.clone().unwrap_or_else(|| item.label.clone());
+            CompletionItem {
+                sort_text: Some(format!("{:04}", rank)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                insert_text: None,
+                ..item
+            }
+        });
+        CompletionList {
+            is_incomplete,
+            items: items.collect(),
+        }
+    }
+}
+
+/// What a completion item names, which resolving the item looks up.
+#[derive(Serialize, Deserialize)]
+struct Data {
+    name: String,
+    class: Option<String>,
+}
+
+fn data(declaration: &Declaration) -> Option<Value> {
+    let data = Data {
+        name: declaration.name.clone(),
+        class: declaration.class.clone(),
+    };
+    serde_json::to_value(data).ok()
+}
+
+/// Fills in what a completion item leaves out until it's chosen: the signature, documentation
+/// and deprecation of what it names.
+pub fn resolve(mut item: CompletionItem, resolver: &Resolver, markdown: bool) -> CompletionItem {
+    let data = item.data.clone().and_then(|data| serde_json::from_value::<Data>(data).ok());
+    let declaration = data.and_then(|data| {
+        resolver
+            .declarations
+            .iter()
+            .map(|(_, declaration)| *declaration)
+            .find(|declaration| {
+                declaration.name == data.name
+                    && declaration.class == data.class
+                    && Some(completion_kind(declaration.kind)) == item.kind
+            })
+    });
+    if let Some(declaration) = declaration {
+        item.detail = Some(declaration.signature.clone());
+        let documentation = hover::documentation(declaration, markdown);
+        item.documentation =
+            (!documentation.is_empty()).then_some(Documentation::MarkupContent(MarkupContent {
+                kind: match markdown {
+                    true => MarkupKind::Markdown,
+                    false => MarkupKind::PlainText,
+                },
+                value: documentation,
+            }));
+        if declaration.doc.as_deref().is_some_and(phpdoc::is_deprecated) {
+            item.tags = Some(vec![CompletionItemTag::DEPRECATED]);
+        }
+    }
+
+    item
+}
+
+/// Whether the function or method `declaration` takes parameters.
+fn has_parameters(declaration: &Declaration) -> bool {
+    let signature = &declaration.signature;
+    let rest = signature.find('(').map(|at| signature[at + 1..].trim_start());
+    !rest.is_some_and(|rest| rest.starts_with(')'))
+}
+
+fn item(label: String) -> CompletionItem {
+    CompletionItem {
+        label,
+        ..CompletionItem::default()
+    }
+}
+
+/// Variables first, then names, then keywords.
+fn priority(kind: Option<CompletionItemKind>) -> u8 {
+    match kind {
+        Some(CompletionItemKind::VARIABLE) => 0,
+        Some(CompletionItemKind::KEYWORD) => 2,
+        _ => 1,
+    }
+}
+
+/// Whether what's declared as `kind` can be named at the `position`.
+fn is_wanted(position: Position, kind: DeclarationKind) -> bool {
+    match (position, kind) {
+        (Position::Class(kinds), DeclarationKind::Class(kind)) => kinds.contains(&kind),
+        (Position::Type, DeclarationKind::Class(kind)) => kind != ClassKind::Trait,
+        (Position::Import(use_kind), kind) => use_kind == self::use_kind(kind),
+        (Position::Statement | Position::Expression, DeclarationKind::Class(kind)) => {
+            kind != ClassKind::Trait
+        }
+        (
+            Position::Statement | Position::Expression,
+            DeclarationKind::Function | DeclarationKind::Constant,
+        ) => true,
+        _ => false,
+    }
+}
+
+/// Whether what PHP declares as `kind` can be named at the `position`. The built-in classes are
+/// classes and interfaces both.
+fn is_builtin_wanted(position: Position, kind: DeclarationKind) -> bool {
+    match kind {
+        DeclarationKind::Class(_) => [ClassKind::Class, ClassKind::Interface]
+            .into_iter()
+            .any(|kind| is_wanted(position, DeclarationKind::Class(kind))),
+        kind => is_wanted(position, kind),
+    }
+}
+
+fn use_kind(kind: DeclarationKind) -> UseKind {
+    match kind {
+        DeclarationKind::Function => UseKind::Function,
+        DeclarationKind::Constant => UseKind::Const,
+        _ => UseKind::Class,
+    }
+}
+
+fn completion_kind(kind: DeclarationKind) -> CompletionItemKind {
+    match kind {
+        DeclarationKind::Function => CompletionItemKind::FUNCTION,
+        DeclarationKind::Class(ClassKind::Interface) => CompletionItemKind::INTERFACE,
+        DeclarationKind::Class(ClassKind::Enum) => CompletionItemKind::ENUM,
+        DeclarationKind::Class(_) => CompletionItemKind::CLASS,
+        DeclarationKind::Constant | DeclarationKind::ClassConstant => CompletionItemKind::CONSTANT,
+        DeclarationKind::Method => CompletionItemKind::METHOD,
+        DeclarationKind::Property => CompletionItemKind::PROPERTY,
+        DeclarationKind::EnumCase => CompletionItemKind::ENUM_MEMBER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The completions at the end of the last `needle` in `src`, with what `files` declare.
+    fn complete(files: &[&Document], src: &str, needle: &str) -> Vec<CompletionItem> {
+        complete_with(files, src, needle, false)
+    }
+
+    /// The completions of `complete`, as calls where they're functions and methods if
+    /// `snippets` are taken.
+    fn complete_with(
+        files: &[&Document],
+        src: &str,
+        needle: &str,
+        snippets: bool,
+    ) -> Vec<CompletionItem> {
+        let document = Document::new(src);
+        let (items, text) = (document.items().unwrap_or_default(), document.text());
+        let mut declarations = files.iter().map(|file| file.declarations()).collect::<Vec<_>>();
+        declarations.push(document.declarations());
+        let resolver = Resolver::new(
+            declarations.iter().map(|declarations| ("file:///test.php", declarations.as_slice())),
+        );
+        let offset = text.byte_to_char(src.rfind(needle).unwrap() + needle.len());
+        let encoding = PositionEncoding::Utf16;
+        let tokens = document.tokens();
+        let list = completions(items, tokens, text, &resolver, offset, encoding, snippets);
+        list.map(|list| list.items).unwrap_or_default()
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<String> {
+        items.iter().map(|item| item.label.clone()).collect()
+    }
+
+    #[test]
+    fn test_completions_fit_the_position() {
+        let models = Document::new(
+            "<?php\nnamespace App\\Models;\n\n/** @deprecated */\nclass User {}\ninterface HasName {}\nfunction user_count(): int {}\n",
+        );
+        let src = r#"<?php
+namespace App;
+
+use App\Models\User;
+
+function greet(string $name, int $times) {
+    $greeting = "Hi";
+    $f = function () use ($greeting) { $local = 1; };
+    // $
+    $
+}
+"#;
+        let at = |src: &str, needle: &str| complete(&[&models], src, needle);
+
+        // The variables of the function, but not those of the closure, as it's being typed
+        let variables = at(src, "    $");
+        let variables = labels(&variables);
+        assert_eq!(variables[..3], ["$name", "$times", "$greeting"]);
+        assert!(variables.contains(&"$f".to_string()) && variables.contains(&"$_GET".to_string()));
+        assert!(!variables.contains(&"$local".to_string()));
+        assert!(at(src, "// $").is_empty());
+
+        // Classes after `new`, as they're written here
+        let src = src.replace("    $\n", "    $user = new Us\n");
+        let classes = at(&src, "new Us");
+        let user = classes.iter().find(|item| item.label == "User").unwrap();
+        assert_eq!(user.kind, Some(CompletionItemKind::CLASS));
+        assert!(user.data.is_some() && user.detail.is_none());
+        assert!(!labels(&classes).contains(&"HasName".to_string()));
+
+        // Functions, constants and keywords at the start of a statement
+        let src = src.replace("new Us", "us");
+        let names = at(&src, "us");
+        let count = names.iter().find(|item| item.label == "user_count").unwrap();
+        assert_eq!(count.kind, Some(CompletionItemKind::FUNCTION));
+        let Some(CompletionTextEdit::Edit(edit)) = &count.text_edit else {
+            panic!("no edit");
+        };
+        assert_eq!(edit.new_text, "user_count");
+        assert!(
+            labels(&at(&src.replace("$user = us", "ret"), "ret")).contains(&"return".to_string())
+        );
+
+        // Segments of qualified names, and types in parameters
+        let src = src.replace("us\n", "Models\\\n");
+        assert_eq!(
+            labels(&at(&src, "Models\\")),
+            ["User", "HasName", "user_count"]
+        );
+        let src = src.replace("Models\\\n", "$f = fn(Has\n");
+        assert_eq!(labels(&at(&src, "fn(Has")), ["HasName"]);
+    }
+
+    #[test]
+    fn test_resolve_fills_in_the_chosen_item() {
+        let src = "<?php\n/** Counts users. @deprecated */\nfunction user_count(): int {}\nclass User { public function rename(string $name) {} }\n$x = us";
+        let document = Document::new(src);
+        let declarations = document.declarations();
+        let resolver = Resolver::new([("file:///test.php", declarations.as_slice())]);
+        let found = complete(&[], src, "= us");
+        let chosen = |label: &str| found.iter().find(|item| item.label == label).unwrap().clone();
+
+        let count = resolve(chosen("user_count"), &resolver, true);
+        assert_eq!(count.detail.as_deref(), Some("function user_count(): int"));
+        assert_eq!(count.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+        assert!(matches!(
+            &count.documentation,
+            Some(Documentation::MarkupContent(content)) if content.value.contains("Counts users.")
+        ));
+        // What's inserted is settled before the item is resolved
+        assert_eq!(count.text_edit, chosen("user_count").text_edit);
+        let user = resolve(chosen("User"), &resolver, false);
+        assert_eq!(user.detail.as_deref(), Some("class User"));
+    }
+
+    #[test]
+    fn test_functions_and_methods_are_completed_as_calls() {
+        let src = "<?php\nfunction user_count(): int {}\nclass User { public function rename(string $name) {} }\n$x = us";
+        let new_text = |found: &[CompletionItem], label: &str| {
+            let item = found.iter().find(|item| item.label == label).unwrap();
+            match &item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => {
+                    (edit.new_text.clone(), item.insert_text_format)
+                }
+                _ => panic!("no edit"),
+            }
+        };
+        let snippet = |text: &str| (text.to_string(), Some(InsertTextFormat::SNIPPET));
+
+        // Without parameters, the cursor goes past the parentheses, and built-ins are taken to
+        // have some. Classes aren't called.
+        let found = complete_with(&[], src, "= us", true);
+        assert_eq!(new_text(&found, "user_count"), snippet("user_count()$0"));
+        assert_eq!(new_text(&found, "User"), ("User".to_string(), None));
+        assert_eq!(new_text(&found, "usort"), snippet("usort($0)"));
+
+        let method = src.replace("$x = us", "function f(User $user) {\n    $user->re\n}");
+        let found = complete_with(&[], &method, "->re", true);
+        assert_eq!(new_text(&found, "rename"), snippet("rename($0)"));
+
+        // Clients that don't take snippets get the name alone, and so do imports
+        let found = complete_with(&[], &method, "->re", false);
+        assert_eq!(new_text(&found, "rename"), ("rename".to_string(), None));
+        let import =
+            "<?php\nnamespace App;\nfunction user_count() {}\nnamespace B;\nuse function App\\us";
+        let found = complete_with(&[], import, "App\\us", true);
+        assert_eq!(
+            new_text(&found, "user_count"),
+            ("user_count".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_members_follow_types_visibility_and_inheritance() {
+        let src = r#"<?php
+namespace App;
+
+trait Greets {
+    private function greet() {}
+    public static function make(): static {}
+}
+class Model {
+    const TABLE = 'models';
+    protected $table;
+    public function save(): bool {}
+    private function secret() {}
+}
+class User extends Model {
+    use Greets;
+    public ?Post $post;
+    public function __construct() {}
+    public function posts(): Post {}
+    public function test() {
+        $this->
+    }
+}
+class Post { public function title(): string {} }
+enum Suit: string { case Hearts = 'H'; }
+function outside(User $user) {
+    $user->
+}
+"#;
+        let at = |needle: &str, with: &str| {
+            let src = src.replacen("$user->\n", &format!("{}\n", with), 1);
+            let mut found = labels(&complete(&[], &src, needle));
+            found.sort();
+            found
+        };
+
+        // Inside the class, what it uses and the protected members of its parent too
+        assert_eq!(
+            at("$this->", "$user->"),
+            ["greet", "make", "post", "posts", "save", "table", "test"]
+        );
+        assert_eq!(
+            at("$user->", "$user->"),
+            ["make", "post", "posts", "save", "test"]
+        );
+        assert_eq!(at("posts()->", "$user->posts()->"), ["title"]);
+        assert_eq!(at("post?->ti", "$user->post?->ti"), ["title"]);
+        assert_eq!(at("User::", "User::"), ["TABLE", "class", "make"]);
+        assert_eq!(
+            at("Suit::", "Suit::"),
+            ["Hearts", "cases", "class", "from", "tryFrom"]
+        );
+        assert_eq!(at("$nobody->", "$nobody->"), Vec::<String>::new());
+
+        // `parent::` calls the methods of the object
+        let parent = src.replace("$this->\n", "parent::\n");
+        let found = labels(&complete(&[], &parent, "parent::"));
+        assert!(found.contains(&"save".to_string()) && !found.contains(&"secret".to_string()));
+
+        // Right at the end of what's being typed, with nothing after it to end the statement
+        let typing = |rest: &str, needle: &str| {
+            let src = format!("<?php\nclass Post {{ public $title; }}\n{}", rest);
+            labels(&complete(&[], &src, needle))
+        };
+        let title = ["title".to_string()];
+        assert_eq!(
+            typing("function f() { $p = new Post(); $p-> }", "$p->"),
+            title
+        );
+        assert_eq!(typing("$p = new Post();\n$p->", "$p->"), title);
+        assert_eq!(typing("$p = new Post();\necho $p->ti", "$p->ti"), title);
+    }
+
+    #[test]
+    fn test_unimported_names_are_imported_in_order() {
+        let models = Document::new(
+            "<?php\nnamespace App\\Models;\n\nclass User {}\nfunction user_count() {}\nconst USERS = 1;\n",
+        );
+        let src = r#"<?php
+declare(strict_types=1);
+
+namespace App;
+
+use App\Models\Post;
+use Zed\Thing;
+use function App\helpers\format;
+
+function main() {
+    $x = Us
+}
+"#;
+        // The import that completing `label` adds, as the line it's inserted at and its text
+        let import = |src: &str, label: &str| {
+            let found = complete(&[&models], src, "Us");
+            let item = found.iter().find(|item| item.label == label).unwrap();
+            let edits = item.additional_text_edits.as_deref().unwrap_or_default();
+            let edits = edits.iter().map(|edit| (edit.range.start.line, edit.new_text.clone()));
+            edits.collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            import(src, "User"),
+            [(6, "use App\\Models\\User;\n".to_string())]
+        );
+        assert_eq!(
+            import(src, "user_count"),
+            [(7, "\nuse function App\\Models\\user_count;".to_string())]
+        );
+        assert_eq!(
+            import(src, "USERS"),
+            [(7, "\nuse const App\\Models\\USERS;".to_string())]
+        );
+
+        // At the end of a namespace without braces, which runs on past its last statement
+        let end = "<?php\nnamespace App;\n\nuse Foo\\Bar;\n\n$x = Us";
+        assert_eq!(
+            import(end, "User"),
+            [(3, "use App\\Models\\User;\n".to_string())]
+        );
+
+        // After the `declare` of a file without imports or namespaces
+        let bare = "<?php\ndeclare(strict_types=1);\n\n$x = Us\n";
+        assert_eq!(
+            import(bare, "User"),
+            [(1, "\n\nuse App\\Models\\User;".to_string())]
+        );
+
+        // A name that's taken is written fully qualified instead
+        let taken = src.replace("function main", "class User {}\nfunction main");
+        let found = complete(&[&models], &taken, "Us");
+        let user = found.iter().find(|item| item.label == "User" && item.data.is_some());
+        let qualified = found.iter().find(|item| {
+            matches!(
+                &item.text_edit,
+                Some(CompletionTextEdit::Edit(edit)) if edit.new_text == "\\App\\Models\\User"
+            )
+        });
+        assert!(user.is_some() && qualified.unwrap().additional_text_edits.is_none());
+    }
+}
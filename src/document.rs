@@ -0,0 +1,612 @@
+//! A parsed document that is parsed again incrementally as it's edited.
+//!
+//! An edit is relexed from the token before it to the token after it, and only the top-level
+//! declarations it touches are parsed again, together with the statements next to them, which
+//! run on into each other. Declarations in a namespace count as top-level. Whenever an edit could
+//! change more than that, as when it opens a comment or a PHP tag, the whole document is parsed
+//! again instead.
+
+use crate::chumsky::{
+    find_duplicates, is_declaration_token, lex_php, parse_file, parse_items, semantic_tokens,
+    Class, Const, Duplicate, EnumCase, Expr, Func, ImCompleteSemanticToken, Item, MatchArm, Member,
+    Method, Namespace, Param, Property, PropertyHook, Span, Spanned, SwitchCase, Token, Type, Use,
+};
+use crate::position::{position_to_offset, PositionEncoding};
+use crate::resolve::{self, Declaration};
+use crate::semantic_token;
+use crate::version::{self, PhpVersion};
+use crate::visitor::{
+    walk_class_mut, walk_const_mut, walk_enum_case_mut, walk_expr_mut, walk_func_mut,
+    walk_match_arm_mut, walk_member_mut, walk_method_mut, walk_namespace_mut, walk_param_mut,
+    walk_property_hook_mut, walk_property_mut, walk_switch_case_mut, VisitorMut,
+};
+use chumsky::error::{Simple, SimpleReason};
+use chumsky::Error;
+use ropey::Rope;
+use std::sync::Arc;
+use tower_lsp::lsp_types::TextDocumentContentChangeEvent;
+
+/// How much of a document was parsed again after an edit.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reparsed {
+    /// The items in this span, as it is after the edit
+    Region(Span),
+    Full,
+}
+
+/// A parsed document. Cloning one is cheap, so that requests can work on a snapshot of it while
+/// it's edited: the text is a rope, and the tokens and items are shared until a copy is edited.
+#[derive(Clone, Debug)]
+pub struct Document {
+    text: Rope,
+    tokens: Arc<Vec<(Token, Span)>>,
+    items: Option<Arc<Vec<Item>>>,
+    /// Lexing and parsing errors, in source order
+    errors: Vec<Simple<String>>,
+    /// Whether the tokens lexed without errors, so that any part of them can be lexed again
+    incremental: bool,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        let (tokens, items, lex_errors, parse_errors) = parse_file(text);
+        let incremental = tokens.is_some() && items.is_some() && lex_errors.is_empty();
+
+        let mut errors = lex_errors.into_iter().chain(parse_errors).collect::<Vec<_>>();
+        errors.sort_by_key(|error| error.span().start);
+
+        Document {
+            text: Rope::from_str(text),
+            tokens: Arc::new(tokens.unwrap_or_default()),
+            items: items.map(Arc::new),
+            errors,
+            incremental,
+        }
+    }
+
+    pub fn text(&self) -> &Rope {
+        &self.text
+    }
+
+    pub fn tokens(&self) -> &[(Token, Span)] {
+        &self.tokens
+    }
+
+    pub fn items(&self) -> Option<&[Item]> {
+        self.items.as_deref().map(Vec::as_slice)
+    }
+
+    /// Lexing and parsing errors, followed by syntax that is newer than the `version` targeted.
+    pub fn errors(&self, version: PhpVersion) -> Vec<Simple<String>> {
+        let newer = self.items().map(|items| version::check(items, version));
+        self.errors.iter().cloned().chain(newer.unwrap_or_default()).collect()
+    }
+
+    pub fn duplicates(&self) -> Vec<Duplicate> {
+        self.items().map(find_duplicates).unwrap_or_default()
+    }
+
+    /// What the document declares, which is nothing while it doesn't parse.
+    pub fn declarations(&self) -> Vec<Declaration> {
+        let declarations = self.items().map(|items| resolve::declarations(items, &self.text));
+        declarations.unwrap_or_default()
+    }
+
+    /// The semantic tokens of the keywords, literals and operators, and of the names, which are
+    /// left out while the document doesn't parse.
+    pub fn semantic_tokens(&self) -> Vec<ImCompleteSemanticToken> {
+        let names = self.items().map(|items| semantic_token::from_ast(items, &self.text));
+        semantic_token::merge(semantic_tokens(&self.tokens), names.unwrap_or_default())
+    }
+
+    /// Replaces the characters in `range` with `text`, and parses again what that could change.
+    pub fn edit(&mut self, range: Span, text: &str) -> Reparsed {
+        self.text.remove(range.clone());
+        self.text.insert(range.start, text);
+        let delta = text.chars().count() as isize - range.len() as isize;
+
+        // An unclosed delimiter is reported where the input ran out, far from its cause
+        let unclosed = self
+            .errors
+            .iter()
+            .any(|error| matches!(error.reason(), SimpleReason::Unclosed { .. }));
+        match (self.incremental && !unclosed).then(|| self.reparse(range, delta)).flatten() {
+            Some(region) => Reparsed::Region(region),
+            None => {
+                *self = Document::new(&self.text.to_string());
+                Reparsed::Full
+            }
+        }
+    }
+
+    /// Replaces the whole text, as sent by clients that don't send edits, by editing only the
+    /// characters between what the old and new texts start and end with.
+    pub fn replace(&mut self, text: &str) -> Reparsed {
+        let (old_len, len) = (self.text.len_chars(), text.chars().count());
+        let prefix = self.text.chars().zip(text.chars()).take_while(|(a, b)| a == b).count();
+        let suffix = self
+            .text
+            .chars_at(old_len)
+            .reversed()
+            .zip(text.chars().rev())
+            .take(old_len.min(len) - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if prefix == old_len && old_len == len {
+            return Reparsed::Region(prefix..prefix);
+        }
+        let inserted = text.chars().skip(prefix).take(len - prefix - suffix).collect::<String>();
+        self.edit(prefix..old_len - suffix, &inserted)
+    }
+
+    /// Applies the changes of a `didChange` notification in order, each to the text the one
+    /// before it left. A change without a range replaces the whole text, and the columns of a
+    /// range count code units of the `encoding`.
+    pub fn apply_changes(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        encoding: PositionEncoding,
+    ) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = position_to_offset(range.start, &self.text, encoding);
+                    let end = position_to_offset(range.end, &self.text, encoding).max(start);
+                    self.edit(start..end, &change.text);
+                }
+                None => {
+                    self.replace(&change.text);
+                }
+            }
+        }
+    }
+
+    /// Lexes and parses again around an edit of `range`, which changed the length of the text by
+    /// `delta`, or returns `None` if the whole document has to be parsed again.
+    fn reparse(&mut self, range: Span, delta: isize) -> Option<Span> {
+        let old_len = (self.text.len_chars() as isize - delta) as usize;
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+
+        // The tokens that the edit touches, and the whitespace and comments around them. Tokens
+        // right before them, with no space in between, could grow into them. An edit past the
+        // last token touches none.
+        let mut first = self.tokens.partition_point(|(_, span)| span.end < range.start);
+        while first > 0
+            && first < self.tokens.len()
+            && self.tokens[first - 1].1.end == self.tokens[first].1.start
+        {
+            first -= 1;
+        }
+        let mut last = self.tokens.partition_point(|(_, span)| span.start <= range.end);
+        let start = self.tokens[..first].last()?.1.end;
+        // Relexing goes on up to a token that lexes as it did before, or to the end, since a string
+        // or a comment left open would go on past the next token, and tokens can merge
+        let (tokens, end) = loop {
+            let next = self.tokens.get(last);
+            let end = next.map_or(old_len, |(_, span)| span.end);
+            let tokens = lex_php(&self.text.slice(start..shift(end)).to_string(), start)?;
+            match next {
+                Some((token, span))
+                    if tokens.last() != Some(&(token.clone(), shift_span(span, delta))) =>
+                {
+                    last += 1
+                }
+                _ => break (tokens, end),
+            }
+        };
+        let last = (last + 1).min(self.tokens.len());
+        if !self.tokens[first..last].iter().chain(&tokens).all(|(token, _)| lexes_alone(token)) {
+            return None;
+        }
+
+        let count = tokens.len();
+        let all = Arc::make_mut(&mut self.tokens);
+        all.splice(first..last, tokens);
+        for (_, span) in &mut all[first + count..] {
+            *span = shift(span.start)..shift(span.end);
+        }
+
+        // Without a leading open tag, the items start with inline HTML that can't be parsed alone
+        let container = match self.tokens.first() {
+            Some((Token::OpenTag, span)) => span.end..old_len,
+            _ => 0..old_len,
+        };
+        let mut reparse = Reparse {
+            tokens: &self.tokens,
+            errors: &mut self.errors,
+            edit: start..end,
+            delta,
+            old_len,
+            label: None,
+        };
+        reparse.items(Arc::make_mut(self.items.as_mut()?), container)
+    }
+}
+
+/// Tokens that lex the same whatever comes after them. Tags switch between PHP and HTML,
+/// `__halt_compiler` ends the code, and an unclosed `/*` lexes as an operator only until there's
+/// a `*/` after it.
+fn lexes_alone(token: &Token) -> bool {
+    match token {
+        Token::OpenTag
+        | Token::OpenTagWithEcho
+        | Token::CloseTag
+        | Token::InlineHtml(_)
+        | Token::HaltCompiler => false,
+        Token::Op(op) => !op.contains("/*"),
+        _ => true,
+    }
+}
+
+/// Parses the items around an edit again, in place.
+struct Reparse<'a> {
+    /// The tokens after the edit
+    tokens: &'a [(Token, Span)],
+    errors: &'a mut Vec<Simple<String>>,
+    /// The span relexed, as it was before the edit
+    edit: Span,
+    delta: isize,
+    old_len: usize,
+    /// The label of the parser that the items are nested in, which labels their errors
+    label: Option<&'static str>,
+}
+
+impl Reparse<'_> {
+    fn shift(&self, offset: usize) -> usize {
+        (offset as isize + self.delta) as usize
+    }
+
+    /// Parses again the `items` around the edit, which spans of the items are before the edit,
+    /// within `container` as it was before the edit. Returns the span parsed again.
+    fn items(&mut self, items: &mut Vec<Item>, container: Span) -> Option<Span> {
+        let extents = extents(items, container.end);
+        let mut first = extents.partition_point(|extent| extent.end < self.edit.start);
+        let mut last = extents.partition_point(|extent| extent.start <= self.edit.end);
+
+        // An edit in the body of a namespace only touches the items in it
+        if last == first + 1 {
+            if let Item::Namespace(namespace) = &mut items[first] {
+                if let Some((body, braced)) = self.body(namespace, extents[first].end) {
+                    if body.start <= self.edit.start && self.edit.end <= body.end {
+                        self.label = Some("namespace");
+                        let region = self.items(&mut namespace.items, body.clone())?;
+                        namespace.span.end = match braced {
+                            true => self.shift(namespace.span.end),
+                            false => {
+                                namespace.items.last().map_or(body.start, |item| item.span().end)
+                            }
+                        };
+                        let mut shift = Shift(self.delta);
+                        items[last..].iter_mut().for_each(|item| shift.visit_item_mut(item));
+                        return Some(region);
+                    }
+                }
+            }
+        }
+
+        let (start, end) = loop {
+            // Statements run on into the statements next to them
+            while first > 0 && matches!(items[first - 1], Item::Stmt(_)) {
+                first -= 1;
+            }
+            while last < items.len() && matches!(items[last], Item::Stmt(_)) {
+                last += 1;
+            }
+            let start = match first {
+                0 => container.start,
+                _ => extents[first - 1].end,
+            };
+            let end = match items.get(last) {
+                Some(_) => extents[last].start,
+                None => container.end,
+            };
+
+            // The region must end where the items around it would have stopped parsing anyway
+            if start > container.start && !self.ends_item(start) {
+                first -= 1;
+            } else if end < container.end && !self.ends_item(self.shift(end)) {
+                last += 1;
+            } else {
+                break (start, end);
+            }
+        };
+
+        let region = start..self.shift(end);
+        let tokens = &self.tokens[self.tokens.partition_point(|(_, span)| span.start < region.start)
+            ..self.tokens.partition_point(|(_, span)| span.start < region.end)];
+        if !parses_alone(tokens) {
+            return None;
+        }
+        let (new_items, errors) = parse_items(tokens.to_vec(), region.end);
+        let new_items = new_items?;
+        // Recovering from an error skips tokens up to the next declaration, past the region
+        let next =
+            self.tokens.get(self.tokens.partition_point(|(_, span)| span.start < region.end));
+        if !errors.is_empty() && next.is_some_and(|(token, _)| !is_declaration_token(token)) {
+            return None;
+        }
+
+        // Errors at the end of the input belong to the region that reaches it
+        let old_len = self.old_len;
+        self.errors.retain(|error| {
+            let at = error.span().start;
+            at < start || (at >= end && end < old_len)
+        });
+        for error in self.errors.iter_mut().filter(|error| error.span().start >= end) {
+            *error = shift_error(error, self.delta);
+        }
+        self.errors.extend(errors.into_iter().map(|error| match self.label {
+            Some(label) => error.with_label(label),
+            None => error,
+        }));
+        self.errors.sort_by_key(|error| error.span().start);
+
+        let mut shift = Shift(self.delta);
+        items[last..].iter_mut().for_each(|item| shift.visit_item_mut(item));
+        items.splice(first..last, new_items);
+        Some(region)
+    }
+
+    /// The span of a namespace's body, after its name, as it was before the edit, and whether
+    /// it's in braces. A namespace without braces lasts until `end`.
+    fn body(&self, namespace: &Namespace, end: usize) -> Option<(Span, bool)> {
+        let header = self.tokens.partition_point(|(_, span)| span.start < namespace.span.start);
+        self.tokens[header..].iter().find_map(|(token, span)| match token {
+            Token::Ctrl(';') => Some((span.end..end, false)),
+            Token::Ctrl('{') => Some((span.end..namespace.span.end - 1, true)),
+            _ => None,
+        })
+    }
+
+    /// Whether the token before `offset` ends an item, so that parsing stops there.
+    fn ends_item(&self, offset: usize) -> bool {
+        let before = self.tokens.partition_point(|(_, span)| span.end <= offset);
+        matches!(
+            self.tokens[..before].last(),
+            Some((Token::Ctrl(';' | '}'), _))
+        )
+    }
+}
+
+/// The spans of `items`, where a namespace reaches up to the next item, or to `end`, as one
+/// without braces does.
+fn extents(items: &[Item], end: usize) -> Vec<Span> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            Item::Namespace(namespace) => {
+                namespace.span.start..items.get(i + 1).map_or(end, |next| next.span().start)
+            }
+            item => item.span().clone(),
+        })
+        .collect()
+}
+
+/// Whether `tokens` parse the same on their own as they do among the rest of the document.
+/// Brackets left open would reach past them, a namespace would take in the items after it, and
+/// tags switch between PHP and HTML.
+fn parses_alone(tokens: &[(Token, Span)]) -> bool {
+    let mut open = Vec::new();
+    for (token, _) in tokens {
+        match token {
+            Token::Ctrl(c @ ('(' | '[' | '{')) => open.push(*c),
+            Token::Ctrl(')') if open.pop() != Some('(') => return false,
+            Token::Ctrl(']') if open.pop() != Some('[') => return false,
+            Token::Ctrl('}') if open.pop() != Some('{') => return false,
+            Token::Namespace => return false,
+            token if !lexes_alone(token) => return false,
+            _ => {}
+        }
+    }
+    open.is_empty()
+}
+
+fn shift_span(span: &Span, delta: isize) -> Span {
+    (span.start as isize + delta) as usize..(span.end as isize + delta) as usize
+}
+
+fn shift_error(error: &Simple<String>, delta: isize) -> Simple<String> {
+    let span = shift_span(&error.span(), delta);
+    let expected = error.expected().cloned();
+    let found = error.found().cloned();
+    let shifted = match error.reason() {
+        SimpleReason::Unexpected => Simple::expected_input_found(span, expected, found),
+        SimpleReason::Unclosed {
+            span: unclosed,
+            delimiter,
+        } => {
+            // The closing delimiter is among the tokens expected
+            let closing = error.expected().flatten().next().cloned().unwrap_or_default();
+            Simple::unclosed_delimiter(
+                shift_span(unclosed, delta),
+                delimiter.clone(),
+                span.clone(),
+                closing,
+                found.clone(),
+            )
+            .merge(Simple::expected_input_found(span, expected, found))
+        }
+        SimpleReason::Custom(message) => Simple::custom(span, message),
+    };
+    match error.label() {
+        Some(label) => shifted.with_label(label),
+        None => shifted,
+    }
+}
+
+/// Moves every span of the AST by `delta` characters.
+struct Shift(isize);
+
+impl Shift {
+    fn span(&self, span: &mut Span) {
+        *span = shift_span(span, self.0);
+    }
+
+    fn spans<'a, T: 'a>(&self, spanned: impl IntoIterator<Item = &'a mut Spanned<T>>) {
+        spanned.into_iter().for_each(|(_, span)| self.span(span));
+    }
+
+    fn ty(&self, ty: &mut Type) {
+        match ty {
+            Type::Named((_, span)) => self.span(span),
+            Type::Nullable(ty) => self.ty(ty),
+            Type::Union(types) | Type::Intersection(types) => {
+                types.iter_mut().for_each(|ty| self.ty(ty))
+            }
+        }
+    }
+}
+
+impl VisitorMut for Shift {
+    fn visit_func_mut(&mut self, func: &mut Func) {
+        self.spans([&mut func.name]);
+        self.span(&mut func.span);
+        walk_func_mut(self, func);
+    }
+
+    fn visit_class_mut(&mut self, class: &mut Class) {
+        self.spans(&mut class.modifiers);
+        self.spans([&mut class.name]);
+        self.spans(class.extends.iter_mut().chain(&mut class.implements));
+        self.span(&mut class.span);
+        walk_class_mut(self, class);
+    }
+
+    fn visit_member_mut(&mut self, member: &mut Member) {
+        if let Member::TraitUse(name) = member {
+            self.spans([name]);
+        }
+        walk_member_mut(self, member);
+    }
+
+    fn visit_method_mut(&mut self, method: &mut Method) {
+        self.spans(&mut method.modifiers);
+        self.spans([&mut method.name]);
+        self.span(&mut method.span);
+        walk_method_mut(self, method);
+    }
+
+    fn visit_property_mut(&mut self, property: &mut Property) {
+        self.spans(&mut property.modifiers);
+        self.spans([&mut property.name]);
+        self.span(&mut property.span);
+        walk_property_mut(self, property);
+    }
+
+    fn visit_property_hook_mut(&mut self, hook: &mut PropertyHook) {
+        self.spans([&mut hook.name]);
+        self.span(&mut hook.span);
+        walk_property_hook_mut(self, hook);
+    }
+
+    fn visit_enum_case_mut(&mut self, case: &mut EnumCase) {
+        self.spans([&mut case.name]);
+        self.span(&mut case.span);
+        walk_enum_case_mut(self, case);
+    }
+
+    fn visit_const_mut(&mut self, constant: &mut Const) {
+        self.spans(&mut constant.modifiers);
+        self.spans([&mut constant.name]);
+        self.span(&mut constant.span);
+        walk_const_mut(self, constant);
+    }
+
+    fn visit_use_mut(&mut self, use_: &mut Use) {
+        self.spans([&mut use_.name].into_iter().chain(&mut use_.alias));
+        self.span(&mut use_.span);
+    }
+
+    fn visit_namespace_mut(&mut self, namespace: &mut Namespace) {
+        self.spans(&mut namespace.name);
+        self.span(&mut namespace.span);
+        walk_namespace_mut(self, namespace);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        self.spans(&mut param.modifiers);
+        self.spans([&mut param.name]);
+        self.span(&mut param.span);
+        walk_param_mut(self, param);
+    }
+
+    fn visit_type_mut(&mut self, (ty, span): &mut Spanned<Type>) {
+        self.ty(ty);
+        self.span(span);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Spanned<Expr>) {
+        match &mut expr.0 {
+            Expr::Local(name)
+            | Expr::Name(name)
+            | Expr::Var(name, ..)
+            | Expr::Goto(name)
+            | Expr::Label(name)
+            | Expr::Prop(_, name)
+            | Expr::StaticProp(_, name)
+            | Expr::ClassConst(_, name)
+            | Expr::NullsafeProp(_, name) => self.spans([name]),
+            Expr::Call(_, args) | Expr::New(_, Some(args)) => self.spans([args]),
+            Expr::Global(names) | Expr::Closure(_, names, ..) => self.spans(names),
+            Expr::Static(vars) => self.spans(vars.iter_mut().map(|(name, _)| name)),
+            Expr::Declare(directives) => self.spans(directives.iter_mut().map(|(name, _)| name)),
+            _ => {}
+        }
+        self.span(&mut expr.1);
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        self.span(&mut arm.span);
+        walk_match_arm_mut(self, arm);
+    }
+
+    fn visit_switch_case_mut(&mut self, case: &mut SwitchCase) {
+        self.span(&mut case.span);
+        walk_switch_case_mut(self, case);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_reparses_only_the_declaration_it_touches() {
+        let src = "<?php\nnamespace App;\n\nclass A {\n    function a() { return 1; }\n}\n\nclass B {\n    function b() { return 2; }\n}\n";
+        let mut document = Document::new(src);
+
+        // From the end of `A` to the end of the namespace, which takes in `B`
+        let at = src.find('2').unwrap();
+        let a = src.find("}\n\nclass B").unwrap() + 1;
+        assert_eq!(
+            document.edit(at..at + 1, "20"),
+            Reparsed::Region(a..src.len() + 1)
+        );
+        assert!(document.errors(PhpVersion::default()).is_empty());
+
+        // A comment left open could reach anywhere
+        assert_eq!(document.edit(at..at, "/*"), Reparsed::Full);
+        assert_eq!(document.text().to_string(), src.replace('2', "/*20"));
+    }
+
+    #[test]
+    fn test_snapshot_shares_until_edited() {
+        let src = "<?php\nfunction a() { return 1; }\nfunction b() { return 2; }\n";
+        let mut document = Document::new(src);
+        let snapshot = document.clone();
+        assert!(Arc::ptr_eq(&document.tokens, &snapshot.tokens));
+
+        let at = src.find('2').unwrap();
+        document.edit(at..at + 1, "3");
+        assert!(!Arc::ptr_eq(&document.tokens, &snapshot.tokens));
+        assert_eq!(snapshot.text().to_string(), src);
+        assert_eq!(snapshot.tokens(), Document::new(src).tokens());
+        assert_eq!(
+            document.tokens(),
+            Document::new(&src.replace('2', "3")).tokens()
+        );
+    }
+}
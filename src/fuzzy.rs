@@ -0,0 +1,78 @@
+//! Fuzzy matching of names, where the characters of a query match in order but not necessarily
+//! next to each other, so that `UsrCtrl` finds `UserController`.
+
+/// How well `query` matches `candidate`, or `None` if it doesn't: ignoring case, every character
+/// of the query must be in the candidate, in order. Matches at the start of words, as the humps
+/// of camel case, and runs of consecutive matches score higher, and longer candidates lower.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    let query = query.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect::<Vec<_>>();
+    let chars = candidate.chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let hump = |j: usize| {
+        j == 0
+            || matches!(chars[j - 1], '\\' | '_' | '-' | ':' | '$' | ' ')
+            || (chars[j].is_uppercase() && chars[j - 1].is_lowercase())
+            || (chars[j].is_alphabetic() && chars[j - 1].is_ascii_digit())
+    };
+    let matches = |q: char, j: usize| chars[j].to_lowercase().next() == Some(q);
+    let points = |j: usize| 1 + if hump(j) { 6 } else { 0 };
+
+    // The best score of the query so far with its last character matched at each position
+    let mut best: Vec<Option<i32>> = (0..chars.len())
+        .map(|j| matches(query[0], j).then(|| points(j) - (j as i32 / 2).min(2)))
+        .collect();
+    for &q in &query[1..] {
+        let mut next = vec![None; chars.len()];
+        let mut before = None::<i32>;
+        for j in 1..chars.len() {
+            // The best match of the query so far that ends before the one at `j - 1`
+            if j >= 2 {
+                before = before.max(best[j - 2]);
+            }
+            if !matches(q, j) {
+                continue;
+            }
+            let consecutive = best[j - 1].map(|score| score + points(j) + 6);
+            let gap = before.map(|score| score + points(j) - 2);
+            next[j] = consecutive.max(gap);
+        }
+        best = next;
+    }
+    let score = best.into_iter().flatten().max()?;
+    Some(score - (chars.len() - query.len()) as i32 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camel_humps_rank_higher() {
+        assert!(score("UsrCtrl", "UserController").is_some());
+        assert!(score("usrctrl", "UserController").is_some());
+        assert_eq!(score("CtrlUsr", "UserController"), None);
+        assert_eq!(score("", "User"), Some(0));
+
+        // Humps and consecutive matches beat scattered ones, and shorter names longer ones
+        fn rank<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+            let mut ranked = candidates.to_vec();
+            ranked.sort_by_key(|candidate| std::cmp::Reverse(score(query, candidate)));
+            ranked
+        }
+        assert_eq!(
+            rank("uc", &["sugar_cane", "UserController"]),
+            ["UserController", "sugar_cane"]
+        );
+        assert_eq!(
+            rank("user", &["UserRepository", "User", "AbusedUrl"]),
+            ["User", "UserRepository", "AbusedUrl"]
+        );
+        assert_eq!(
+            rank("mu", &["App\\Models\\User", "Menu"]),
+            ["App\\Models\\User", "Menu"]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Hovers: the signature of what the name under the cursor declares or refers to, its type and
+//! its doc comment.
+
+use crate::chumsky::{Item, Span};
+use crate::phpdoc;
+use crate::resolve::{Context, Declaration, DeclarationKind, Resolver, Target};
+use crate::visitor::Node;
+use ropey::Rope;
+
+/// What to show when hovering `offset`, as markdown or as plain text, and the span of the name
+/// it's about.
+pub fn hover(
+    items: &[Item],
+    text: &Rope,
+    resolver: &Resolver,
+    offset: usize,
+    markdown: bool,
+) -> Option<(String, Span)> {
+    let reference = resolver.reference_at(items, offset)?;
+    let contents = match &reference.target {
+        Target::Variable { name, .. } => {
+            let context = Context::at(items, offset);
+            let ty = resolver.variable_type(&context, name, offset);
+            match context.chain.last() {
+                Some(Node::Param(param)) => {
+                    // A parameter is shown as declared, with what its function documents of it
+                    let source = text.slice(param.span.clone()).to_string();
+                    let doc = context.chain.iter().rev().find_map(|node| {
+                        let start = match node {
+                            Node::Func(func) => func.span.start,
+                            Node::Method(method) => method.span.start,
+                            _ => return None,
+                        };
+                        phpdoc::doc_comment(text, start)
+                    });
+                    let doc = doc.map(|doc| phpdoc::parse(&doc));
+                    let description = doc
+                        .as_ref()
+                        .and_then(|doc| doc.param(name))
+                        .map(|tag| tag.description.as_str());
+                    render(&collapse(&source), None, description, markdown)
+                }
+                _ => {
+                    let signature = match ty {
+                        Some(ty) => format!("${}: {}", name, ty),
+                        None => format!("${}", name),
+                    };
+                    render(&signature, None, None, markdown)
+                }
+            }
+        }
+        target => {
+            let declaration = *resolver.find(target).first()?;
+            let documentation = documentation(declaration, markdown);
+            render(&declaration.signature, Some(&documentation), None, markdown)
+        }
+    };
+    Some((contents, reference.span))
+}
+
+/// What's known of a declaration besides its signature: its class and type, and its doc
+/// comment.
+pub fn documentation(declaration: &Declaration, markdown: bool) -> String {
+    let doc = declaration.doc.as_deref().map(phpdoc::parse);
+    let doc = doc.map(|doc| doc.render(markdown));
+    [details(declaration, markdown), doc.unwrap_or_default()]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// The class a member is in and the type of a declaration, which its signature may not say,
+/// or say with names that aren't fully qualified.
+fn details(declaration: &Declaration, markdown: bool) -> String {
+    let code = |text: &str| match markdown {
+        true => format!("`{}`", text),
+        false => text.to_string(),
+    };
+    let mut lines = Vec::new();
+    if let Some(class) = &declaration.class {
+        lines.push(format!("in {}", code(class)));
+    }
+    if let Some(ty) = &declaration.ty {
+        match declaration.kind {
+            DeclarationKind::Function | DeclarationKind::Method => {
+                lines.push(format!("returns {}", code(ty)))
+            }
+            DeclarationKind::Property => lines.push(format!("of type {}", code(ty))),
+            _ => {}
+        }
+    }
+    lines.join(if markdown { "  \n" } else { "\n" })
+}
+
+fn render(
+    signature: &str,
+    details: Option<&str>,
+    description: Option<&str>,
+    markdown: bool,
+) -> String {
+    let signature = match markdown {
+        true => format!("```php\n{}\n```", signature),
+        false => signature.to_string(),
+    };
+    [Some(signature.as_str()), details, description]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(if markdown { "\n\n" } else { "\n" })
+}
+
+fn collapse(source: &str) -> String {
+    source.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::resolve::declarations;
+
+    #[test]
+    fn test_hover_shows_signature_type_and_doc() {
+        let src = r#"<?php
+namespace App;
+
+class User {
+    /**
+     * Greets someone.
+     *
+     * @param string $who Who to greet
+     * @return string
+     * @deprecated Use welcome()
+     */
+    public function greet($who) {
+        $user = new User();
+        return $user->greet($who);
+    }
+}
+"#;
+        let document = Document::new(src);
+        let (items, text) = (document.items().unwrap(), document.text());
+        let declarations = declarations(items, text);
+        let resolver = Resolver::new([("file:///user.php", declarations.as_slice())]);
+        let at = |needle: &str, markdown| {
+            let offset = text.byte_to_char(src.find(needle).unwrap());
+            hover(items, text, &resolver, offset, markdown).map(|(contents, _)| contents)
+        };
+
+        let method = at("greet($who);", true).unwrap();
+        assert!(
+            method.starts_with("```php\npublic function greet($who)\n```"),
+            "{}",
+            method
+        );
+        assert!(
+            method.contains("in `App\\User`  \nreturns `string`"),
+            "{}",
+            method
+        );
+        assert!(method.contains("Greets someone."), "{}", method);
+        assert!(
+            method.contains("*@param* `string` `$who` — Who to greet"),
+            "{}",
+            method
+        );
+        assert!(
+            method.contains("**Deprecated** Use welcome()"),
+            "{}",
+            method
+        );
+
+        assert_eq!(at("$user->", false).as_deref(), Some("$user: App\\User"));
+        assert_eq!(at("$who) {", false).as_deref(), Some("$who\nWho to greet"));
+        assert_eq!(at("User {", false).as_deref(), Some("class User"));
+    }
+}
@@ -0,0 +1,468 @@
+//! A hand-written lexer over the bytes of the source, for when lexing has to be fast. It lexes
+//! as the combinator lexer in [`crate::chumsky`] does, but without allocating: tokens borrow
+//! their text from the source, and their spans are byte offsets.
+
+use crate::chumsky::{Quote, Span, Spanned, Token};
+use crate::symbol::Symbol;
+use chumsky::error::Simple;
+use chumsky::Error;
+
+/// A token that borrows its text from the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lexeme<'a> {
+    Num(&'a str),
+    Str(Quote, &'a str),
+    Op(&'a str),
+    Ident(&'a str),
+    InlineHtml(&'a str),
+    /// Keywords, punctuation and tags, which have no text to borrow
+    Token(Token),
+}
+
+impl Lexeme<'_> {
+    pub fn to_token(&self) -> Token {
+        match self {
+            Lexeme::Num(num) => Token::Num(num.to_string()),
+            Lexeme::Str(quote, str) => Token::Str(*quote, str.to_string()),
+            Lexeme::Op(op) => Token::Op(op.to_string()),
+            Lexeme::Ident(ident) => Token::Ident(Symbol::intern(ident)),
+            Lexeme::InlineHtml(html) => Token::InlineHtml(html.to_string()),
+            Lexeme::Token(token) => token.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Html,
+    Php,
+    /// After `__halt_compiler`, with this many of the `();` after it left to lex
+    Halting(u8),
+    Done,
+}
+
+/// Lexes a whole file, which starts out as inline HTML until the first open tag.
+pub struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    mode: Mode,
+    errors: Vec<Span>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+            mode: Mode::Html,
+            errors: Vec::new(),
+        }
+    }
+
+    /// A lexer of code that starts out as PHP rather than as inline HTML.
+    pub fn in_php(src: &'a str) -> Self {
+        Self {
+            mode: Mode::Php,
+            ..Self::new(src)
+        }
+    }
+
+    /// The characters lexed so far that start no token. Of those that run on to the end of the
+    /// file, only the first is an error, as the combinator lexer reports an unclosed string where
+    /// the input ends.
+    pub fn errors(&self) -> &[Span] {
+        &self.errors
+    }
+
+    fn html(&mut self) -> Option<Spanned<Lexeme<'a>>> {
+        let start = self.pos;
+        let mut end = start;
+        self.pos = loop {
+            match find(&self.bytes[end..], b"<?") {
+                Some(at) if self.bytes[end + at..].starts_with(b"<?php") => break end + at,
+                Some(at) if self.bytes[end + at..].starts_with(b"<?=") => break end + at,
+                Some(at) => end += at + 2,
+                None => break self.bytes.len(),
+            }
+        };
+        self.mode = Mode::Php;
+        match self.pos > start {
+            true => Some((
+                Lexeme::InlineHtml(&self.src[start..self.pos]),
+                start..self.pos,
+            )),
+            false => self.php(),
+        }
+    }
+
+    fn php(&mut self) -> Option<Spanned<Lexeme<'a>>> {
+        self.skip_trivia();
+        let start = self.pos;
+        if let Some(end) = self.halt_compiler() {
+            self.pos = end;
+            self.mode = Mode::Halting(3);
+            return Some((Lexeme::Token(Token::HaltCompiler), start..end));
+        }
+        // The close tag swallows a single newline directly after it, as PHP does
+        if self.bytes[start..].starts_with(b"?>") {
+            self.pos += if self.bytes.get(start + 2) == Some(&b'\n') {
+                3
+            } else {
+                2
+            };
+            self.mode = Mode::Html;
+            return Some((Lexeme::Token(Token::CloseTag), start..self.pos));
+        }
+
+        // Characters that start no token are skipped, and the token after them spans them too
+        let mut error = None;
+        while self.pos < self.bytes.len() {
+            if let Some(lexeme) = self.token() {
+                self.errors.extend(error);
+                return Some((lexeme, start..self.pos));
+            }
+            let len = self.char_len(self.pos);
+            error.get_or_insert(self.pos..self.pos + len);
+            self.pos += len;
+        }
+        self.errors.extend(error);
+        None
+    }
+
+    /// Lexes the token at the current position, if one starts there.
+    fn token(&mut self) -> Option<Lexeme<'a>> {
+        let start = self.pos;
+        let rest = &self.bytes[start..];
+        let lexeme = match rest[0] {
+            b'0'..=b'9' => {
+                self.pos += match rest[0] {
+                    b'0' => 1,
+                    _ => digits(rest),
+                };
+                if self.bytes.get(self.pos) == Some(&b'.')
+                    && digits(&self.bytes[self.pos + 1..]) > 0
+                {
+                    self.pos += 1 + digits(&self.bytes[self.pos + 1..]);
+                }
+                Lexeme::Num(&self.src[start..self.pos])
+            }
+            quote @ (b'"' | b'\'') => {
+                let len = rest[1..].iter().position(|&b| b == quote)?;
+                self.pos += len + 2;
+                let quote = match quote {
+                    b'"' => Quote::Double,
+                    _ => Quote::Single,
+                };
+                Lexeme::Str(quote, &self.src[start + 1..start + 1 + len])
+            }
+            _ if rest.starts_with(b"<?php") => {
+                self.pos += 5;
+                Lexeme::Token(Token::OpenTag)
+            }
+            _ if rest.starts_with(b"<?=") => {
+                self.pos += 3;
+                Lexeme::Token(Token::OpenTagWithEcho)
+            }
+            b'+' | b'-' | b'*' | b'/' | b'!' | b'=' | b'<' | b'>' => {
+                self.pos += rest.iter().take_while(|&&b| b"+-*/!=<>".contains(&b)).count();
+                Lexeme::Op(&self.src[start..self.pos])
+            }
+            b'\\' if rest.get(1).is_some_and(|&b| is_ident_start(b)) => self.name(),
+            b if is_ident_start(b) => self.name(),
+            _ if rest.starts_with(b"::") => {
+                self.pos += 2;
+                Lexeme::Token(Token::DoubleColon)
+            }
+            _ if rest.starts_with(b"?->") => {
+                self.pos += 3;
+                Lexeme::Token(Token::NullsafeArrow)
+            }
+            c @ (b'(' | b')' | b'{' | b'}' | b'[' | b']' | b',' | b':' | b'?' | b'|' | b';'
+            | b'.' | b'\\' | b'&') => {
+                self.pos += 1;
+                Lexeme::Token(Token::Ctrl(c as char))
+            }
+            b'$' => {
+                self.pos += rest.iter().take_while(|&&b| b == b'$').count();
+                Lexeme::Token(Token::Dollar)
+            }
+            _ => return None,
+        };
+        Some(lexeme)
+    }
+
+    /// Lexes an identifier or a keyword. Qualified names, as in `App\Models\User` or `\strlen`,
+    /// are a single identifier.
+    fn name(&mut self) -> Lexeme<'a> {
+        let start = self.pos;
+        let mut qualified = self.bytes[start] == b'\\';
+        if qualified {
+            self.pos += 1;
+        }
+        self.pos += ident(&self.bytes[self.pos..]);
+        while self.bytes.get(self.pos) == Some(&b'\\')
+            && self.bytes.get(self.pos + 1).is_some_and(|&b| is_ident_start(b))
+        {
+            self.pos += 1 + ident(&self.bytes[self.pos + 1..]);
+            qualified = true;
+        }
+
+        let name = &self.src[start..self.pos];
+        match qualified {
+            false => keyword(name).map_or(Lexeme::Ident(name), Lexeme::Token),
+            true => Lexeme::Ident(name),
+        }
+    }
+
+    /// The end of `__halt_compiler` at the current position, if `();` follows it.
+    fn halt_compiler(&self) -> Option<usize> {
+        let end = self.pos + ident(&self.bytes[self.pos..]);
+        if &self.bytes[self.pos..end] != b"__halt_compiler" {
+            return None;
+        }
+        let mut at = end;
+        for c in [b'(', b')', b';'] {
+            while self.whitespace_len(at) > 0 {
+                at += self.whitespace_len(at);
+            }
+            if self.bytes.get(at) != Some(&c) {
+                return None;
+            }
+            at += 1;
+        }
+        Some(end)
+    }
+
+    /// Skips whitespace and comments. Single line comments end at a newline or at the `?>` close
+    /// tag, and an unclosed `/*` is left to lex as an operator.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.bytes[self.pos..];
+            let comment = match rest {
+                [b'#', ..] => 1,
+                [b'/', b'/', ..] => 2,
+                [b'/', b'*', rest @ ..] => match find(rest, b"*/") {
+                    Some(len) => {
+                        self.pos += len + 4;
+                        continue;
+                    }
+                    None => return,
+                },
+                _ => match self.whitespace_len(self.pos) {
+                    0 => return,
+                    len => {
+                        self.pos += len;
+                        continue;
+                    }
+                },
+            };
+            self.pos += comment;
+            while self.pos < self.bytes.len()
+                && self.bytes[self.pos] != b'\n'
+                && !self.bytes[self.pos..].starts_with(b"?>")
+            {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// The length of the whitespace character at `at`, or 0 if there's none.
+    fn whitespace_len(&self, at: usize) -> usize {
+        match self.bytes.get(at) {
+            Some(b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c') => 1,
+            Some(b) if b.is_ascii() => 0,
+            Some(_) => match self.src[at..].chars().next() {
+                Some(c) if c.is_whitespace() => c.len_utf8(),
+                _ => 0,
+            },
+            None => 0,
+        }
+    }
+
+    fn char_len(&self, at: usize) -> usize {
+        self.src[at..].chars().next().map_or(1, char::len_utf8)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Spanned<Lexeme<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.mode {
+            Mode::Html => self.html(),
+            Mode::Php => self.php(),
+            // The `();` after `__halt_compiler` were checked already, and are all that's left
+            Mode::Halting(left) => {
+                self.skip_trivia();
+                let start = self.pos;
+                let lexeme = self.token()?;
+                self.mode = match left {
+                    1 => Mode::Done,
+                    _ => Mode::Halting(left - 1),
+                };
+                Some((lexeme, start..self.pos))
+            }
+            Mode::Done => None,
+        }
+    }
+}
+
+/// Lexes a whole file, returning its tokens and the characters that start none.
+pub fn lex(src: &str) -> (Vec<Spanned<Lexeme<'_>>>, Vec<Span>) {
+    let mut lexer = Lexer::new(src);
+    let tokens = lexer.by_ref().collect();
+    (tokens, lexer.errors)
+}
+
+/// Lexes with `lexer` into the tokens that the parser takes, their spans in characters counted
+/// from `offset`, and the errors of the characters that start no token.
+pub(crate) fn tokens(mut lexer: Lexer, offset: usize) -> (Vec<(Token, Span)>, Vec<Simple<String>>) {
+    let src = lexer.src;
+    let mut chars = CharOffsets::new(src, offset);
+    let tokens = lexer.by_ref().map(|(lexeme, span)| (lexeme.to_token(), chars.span(span)));
+    let tokens = tokens.collect();
+
+    let mut chars = CharOffsets::new(src, offset);
+    let errors = lexer.errors.iter().map(|span| {
+        let found = src[span.clone()].to_string();
+        Simple::expected_input_found(chars.span(span.clone()), Vec::new(), Some(found))
+    });
+    (tokens, errors.collect())
+}
+
+/// Counts the characters up to byte offsets of a source, which go in order.
+struct CharOffsets<'a> {
+    src: &'a str,
+    byte: usize,
+    char: usize,
+}
+
+impl<'a> CharOffsets<'a> {
+    fn new(src: &'a str, offset: usize) -> Self {
+        Self {
+            src,
+            byte: 0,
+            char: offset,
+        }
+    }
+
+    fn at(&mut self, byte: usize) -> usize {
+        self.char += self.src[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.char
+    }
+
+    fn span(&mut self, span: Span) -> Span {
+        self.at(span.start)..self.at(span.end)
+    }
+}
+
+/// The keyword that an identifier spells, in any case as PHP keywords are case-insensitive.
+pub(crate) fn keyword(ident: &str) -> Option<Token> {
+    // None is longer than `include_once` and `require_once`
+    let mut lower = [0; 12];
+    let lower = lower.get_mut(..ident.len())?;
+    lower.copy_from_slice(ident.as_bytes());
+    lower.make_ascii_lowercase();
+
+    Some(match &*lower {
+        b"fn" => Token::Fn,
+        b"function" => Token::Function,
+        b"echo" => Token::Echo,
+        b"print" => Token::Print,
+        b"if" => Token::If,
+        b"else" => Token::Else,
+        b"elseif" => Token::ElseIf,
+        b"endif" => Token::EndIf,
+        b"while" => Token::While,
+        b"endwhile" => Token::EndWhile,
+        b"for" => Token::For,
+        b"endfor" => Token::EndFor,
+        b"foreach" => Token::Foreach,
+        b"endforeach" => Token::EndForeach,
+        b"as" => Token::As,
+        b"switch" => Token::Switch,
+        b"endswitch" => Token::EndSwitch,
+        b"case" => Token::Case,
+        b"default" => Token::Default,
+        b"break" => Token::Break,
+        b"continue" => Token::Continue,
+        b"return" => Token::Return,
+        b"global" => Token::Global,
+        b"static" => Token::Static,
+        b"unset" => Token::Unset,
+        b"isset" => Token::Isset,
+        b"empty" => Token::Empty,
+        b"declare" => Token::Declare,
+        b"goto" => Token::Goto,
+        b"exit" => Token::Exit,
+        b"die" => Token::Die,
+        b"include" => Token::Include,
+        b"include_once" => Token::IncludeOnce,
+        b"require" => Token::Require,
+        b"require_once" => Token::RequireOnce,
+        b"true" => Token::Bool(true),
+        b"false" => Token::Bool(false),
+        b"null" => Token::Null,
+        b"class" => Token::Class,
+        b"interface" => Token::Interface,
+        b"trait" => Token::Trait,
+        b"extends" => Token::Extends,
+        b"implements" => Token::Implements,
+        b"public" => Token::Public,
+        b"protected" => Token::Protected,
+        b"private" => Token::Private,
+        b"abstract" => Token::Abstract,
+        b"final" => Token::Final,
+        b"const" => Token::Const,
+        b"new" => Token::New,
+        b"namespace" => Token::Namespace,
+        b"use" => Token::Use,
+        _ => return None,
+    })
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+/// The length of the identifier at the start of `bytes`, or 0 if there's none.
+fn ident(bytes: &[u8]) -> usize {
+    match bytes.first() {
+        Some(&b) if is_ident_start(b) => {
+            bytes.iter().take_while(|b| b.is_ascii_alphanumeric() || **b == b'_').count()
+        }
+        _ => 0,
+    }
+}
+
+fn digits(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|b| b.is_ascii_digit()).count()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexer_borrows_text_with_byte_spans() {
+        let src = "<p>é</p><?php $x = \"naïve\" . App\\Model::NAME; ?>\n";
+        let (tokens, errors) = lex(src);
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0], (Lexeme::InlineHtml("<p>é</p>"), 0..9));
+        assert_eq!(tokens[2], (Lexeme::Token(Token::Dollar), 15..16));
+        assert_eq!(tokens[5], (Lexeme::Str(Quote::Double, "naïve"), 20..28));
+        assert_eq!(tokens[7], (Lexeme::Ident("App\\Model"), 31..40));
+        assert_eq!(
+            tokens.last(),
+            Some(&(Lexeme::Token(Token::CloseTag), 48..51))
+        );
+    }
+}
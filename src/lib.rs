@@ -1,2 +1,19 @@
-// pub mod semantic_token;
-pub mod chumsky;
\ No newline at end of file
+pub mod builtins;
+pub mod chumsky;
+pub mod completion;
+pub mod document;
+pub mod fuzzy;
+pub mod hover;
+pub mod lexer;
+pub mod outline;
+pub mod phpdoc;
+pub mod position;
+pub mod printer;
+pub mod references;
+pub mod rename;
+pub mod resolve;
+pub mod semantic_token;
+pub mod symbol;
+pub mod version;
+pub mod visitor;
+pub mod workspace;
@@ -1,44 +1,191 @@
-use std::collections::HashMap;
-
 use dashmap::DashMap;
-use phantom_language_server::chumsky::{parser, Func, ImCompleteSemanticToken, ParserResult};
-use ropey::Rope;
+use phantom_language_server::chumsky::{ImCompleteSemanticToken, LEGEND_MODIFIER, LEGEND_TYPE};
+use phantom_language_server::completion;
+use phantom_language_server::document::Document;
+use phantom_language_server::hover;
+use phantom_language_server::outline;
+use phantom_language_server::phpdoc;
+use phantom_language_server::position::{
+    offset_to_position, position_to_offset, span_to_range, PositionEncoding,
+};
+use phantom_language_server::references;
+use phantom_language_server::rename;
+use phantom_language_server::resolve::{self, Declaration, Resolver, Target};
+use phantom_language_server::semantic_token;
+use phantom_language_server::version::PhpVersion;
+use phantom_language_server::workspace;
 use serde_json::Value;
-use tower_lsp::jsonrpc::Result;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tower::ServiceExt;
+use tower_lsp::jsonrpc::{self, Result};
 use tower_lsp::lsp_types::*;
 
 #[allow(unused)]
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
 use tower_lsp::lsp_types::{
-    Diagnostic, DiagnosticSeverity, InitializeParams, InitializeResult, Position, Range,
-    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Diagnostic, InitializeParams, InitializeResult, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind,
 };
 
 #[allow(unused)]
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    ast_map: DashMap<String, HashMap<String, Func>>,
-    document_map: DashMap<String, Rope>,
+    document_map: Arc<DashMap<String, Document>>,
+    /// The version of each open document, as of its last change
+    version_map: DashMap<String, i32>,
     semantic_token_map: DashMap<String, Vec<ImCompleteSemanticToken>>,
+    /// The semantic tokens last sent for each document, which deltas are taken from
+    sent_semantic_tokens: DashMap<String, SemanticTokens>,
+    semantic_tokens_result_id: AtomicU64,
     diagnostics_map: DashMap<String, Vec<Diagnostic>>,
-}
-
-struct TextDocumentItem {
-    uri: Url,
-    text: String,
-    version: i32,
+    php_version: RwLock<PhpVersion>,
+    /// What the columns of positions count, as negotiated with the client
+    position_encoding: RwLock<PositionEncoding>,
+    /// How hovers are written, as the client prefers
+    markup_kind: RwLock<MarkupKind>,
+    /// How the documentation of completions is written, as the client prefers
+    completion_markup_kind: RwLock<MarkupKind>,
+    /// Whether completions may insert snippets
+    snippet_support: RwLock<bool>,
+    /// Whether workspace symbols may leave their ranges to be resolved
+    resolve_symbol_ranges: RwLock<bool>,
+    /// Whether the client lets us register to be told of changes to files
+    watch_files: RwLock<bool>,
+    /// The workspace folders, whose PHP files are indexed
+    workspace_folders: RwLock<Vec<PathBuf>>,
+    /// The indexed PHP files of the workspace folders, as they are on disk
+    workspace_map: Arc<DashMap<String, Document>>,
+    /// What each open or indexed file declares
+    declaration_map: Arc<DashMap<String, Arc<Vec<Declaration>>>>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Clients configure the target as `{ "phpVersion": "8.1" }`
+        if let Some(version) = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.get("phpVersion"))
+            .and_then(Value::as_str)
+        {
+            match version.parse() {
+                Ok(version) => *self.php_version.write().unwrap() = version,
+                Err(err) => self.client.log_message(MessageType::WARNING, err).await,
+            }
+        }
+
+        let encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        *self.position_encoding.write().unwrap() = encoding;
+
+        // Hovers are markdown unless the client prefers plain text, or doesn't take markdown
+        let formats = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_deref());
+        *self.markup_kind.write().unwrap() = match formats {
+            Some([first, ..]) => first.clone(),
+            _ => MarkupKind::Markdown,
+        };
+        // And so is the documentation of completions, which clients prefer on their own
+        let completion_item = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref());
+        let formats = completion_item.and_then(|item| item.documentation_format.as_deref());
+        *self.completion_markup_kind.write().unwrap() = match formats {
+            Some([first, ..]) => first.clone(),
+            _ => MarkupKind::Markdown,
+        };
+        *self.snippet_support.write().unwrap() =
+            completion_item.and_then(|item| item.snippet_support).unwrap_or(false);
+        let resolved = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.symbol.as_ref())
+            .and_then(|symbol| symbol.resolve_support.as_ref())
+            .map(|support| support.properties.as_slice());
+        *self.resolve_symbol_ranges.write().unwrap() =
+            resolved.is_some_and(|properties| properties.iter().any(|p| p == "location.range"));
+        *self.watch_files.write().unwrap() = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+
+        #[allow(deprecated)]
+        let folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        *self.workspace_folders.write().unwrap() =
+            folders.iter().filter_map(|uri| uri.to_file_path().ok()).collect();
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
+                position_encoding: Some(encoding.kind()),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
+                        SemanticTokensRegistrationOptions {
+                            text_document_registration_options: TextDocumentRegistrationOptions {
+                                document_selector: Some(vec![DocumentFilter {
+                                    language: Some("php".to_string()),
+                                    scheme: Some("file".to_string()),
+                                    pattern: None,
+                                }]),
+                            },
+                            semantic_tokens_options: SemanticTokensOptions {
+                                work_done_progress_options: WorkDoneProgressOptions::default(),
+                                legend: SemanticTokensLegend {
+                                    token_types: LEGEND_TYPE.into(),
+                                    token_modifiers: LEGEND_MODIFIER.into(),
+                                },
+                                range: Some(true),
+                                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            },
+                            static_registration_options: StaticRegistrationOptions::default(),
+                        },
+                    ),
+                ),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(
+                        completion::TRIGGER_CHARACTERS.iter().map(|c| c.to_string()).collect(),
+                    ),
+                    resolve_provider: Some(true),
+                    ..CompletionOptions::default()
+                }),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Right(WorkspaceSymbolOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
                 ..ServerCapabilities::default()
             },
             ..InitializeResult::default()
@@ -47,6 +194,25 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         self.client.log_message(MessageType::INFO, "initialized!").await;
+        self.index_workspace();
+
+        // The index follows files that change outside the editor, as on a `git checkout`
+        if *self.watch_files.read().unwrap() {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![FileSystemWatcher {
+                    glob_pattern: GlobPattern::String("**/*.php".to_string()),
+                    kind: None,
+                }],
+            };
+            let registration = Registration {
+                id: "watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(err) = self.client.register_capability(vec![registration]).await {
+                self.client.log_message(MessageType::WARNING, err).await;
+            }
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -61,8 +227,28 @@ impl LanguageServer for Backend {
         self.client.log_message(MessageType::INFO, "configuration changed!").await;
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
         self.client.log_message(MessageType::INFO, "watched files have changed!").await;
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let index = self.index();
+        let reindex = move || {
+            for change in params.changes {
+                let Ok(path) = change.uri.to_file_path() else {
+                    continue;
+                };
+                if !folders.iter().any(|folder| workspace::is_php_file(folder, &path)) {
+                    continue;
+                }
+                if change.typ == FileChangeType::DELETED {
+                    index.remove(change.uri.as_str());
+                } else {
+                    index.add(&path);
+                }
+            }
+        };
+        if let Err(err) = tokio::task::spawn_blocking(reindex).await {
+            self.client.log_message(MessageType::ERROR, err).await;
+        }
     }
 
     async fn execute_command(&self, _: ExecuteCommandParams) -> Result<Option<Value>> {
@@ -90,22 +276,26 @@ impl LanguageServer for Backend {
         // self.diagnostics_map.insert(uri.clone(), diagnostics.clone());
         // self.client.publish_diagnostics(params.text_document.uri, diagnostics, None).await;
 
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: params.text_document.text,
-            version: params.text_document.version,
-        })
+        let text = params.text_document.text;
+        self.on_change(
+            params.text_document.uri,
+            params.text_document.version,
+            |document| {
+                document.replace(&text);
+            },
+        )
         .await
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
         self.client.log_message(MessageType::INFO, "file changed!").await;
 
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
+        let encoding = *self.position_encoding.read().unwrap();
+        self.on_change(
+            params.text_document.uri,
+            params.text_document.version,
+            |document| document.apply_changes(&params.content_changes, encoding),
+        )
         .await
 
         // let uri: String = params.text_document.uri.to_string();
@@ -122,20 +312,425 @@ impl LanguageServer for Backend {
         self.client.log_message(MessageType::INFO, "file saved!").await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
         self.client.log_message(MessageType::INFO, "file closed!").await;
+        let uri = params.text_document.uri;
+        let key = uri.to_string();
+        self.document_map.remove(&key);
+        self.version_map.remove(&key);
+        self.semantic_token_map.remove(&key);
+        self.sent_semantic_tokens.remove(&key);
+
+        // The file is as it is on disk again, if it's one of the workspace's
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let index = self.index();
+        let path = uri.to_file_path().ok();
+        match path.filter(|path| folders.iter().any(|folder| path.starts_with(folder))) {
+            Some(path) if index.add(&path) => {}
+            _ => index.remove(&key),
+        }
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri.to_string();
+        Ok(self.semantic_tokens(&uri).map(SemanticTokensResult::Tokens))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri = params.text_document.uri.to_string();
+        let previous = self
+            .sent_semantic_tokens
+            .get(&uri)
+            .filter(|sent| sent.result_id.as_ref() == Some(&params.previous_result_id))
+            .map(|sent| sent.data.clone());
+        let Some(tokens) = self.semantic_tokens(&uri) else {
+            return Ok(None);
+        };
+
+        // Without the tokens the delta is from, all of them are sent again
+        Ok(Some(match previous {
+            Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                edits: semantic_token::delta(&previous, &tokens.data),
+                result_id: tokens.result_id,
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(tokens),
+        }))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let uri = params.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let data = || -> Option<Vec<SemanticToken>> {
+            let document = self.document_map.get(&uri)?.clone();
+            let rope = document.text();
+            let range = position_to_offset(params.range.start, rope, encoding)
+                ..position_to_offset(params.range.end, rope, encoding);
+            let tokens = self.semantic_token_map.get(&uri)?;
+            Some(semantic_token::encode(
+                semantic_token::in_range(&tokens, range),
+                rope,
+                encoding,
+            ))
+        }();
+        Ok(data.map(|data| {
+            SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data,
+            })
+        }))
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let kind = self.markup_kind.read().unwrap().clone();
+        let Some(document) = self.document(&uri) else {
+            return Ok(None);
+        };
+        let (Some(items), rope) = (document.items(), document.text()) else {
+            return Ok(None);
+        };
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(position.position, rope, encoding);
+        let markdown = kind == MarkupKind::Markdown;
+        let Some((value, span)) = hover::hover(items, rope, &resolver, offset, markdown) else {
+            return Ok(None);
+        };
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind, value }),
+            range: span_to_range(&span, rope, encoding),
+        }))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let encoding = *self.position_encoding.read().unwrap();
+        let Some(document) = self.document(uri.as_str()) else {
+            return Ok(None);
+        };
+        let (Some(items), rope) = (document.items(), document.text()) else {
+            return Ok(None);
+        };
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(position.position, rope, encoding);
+        let Some(reference) = resolver.reference_at(items, offset) else {
+            return Ok(None);
+        };
+        let origin = span_to_range(&reference.span, rope, encoding);
+        let links = match &reference.target {
+            // A variable is defined where it first occurs in its scope
+            Target::Variable { name, scope } => resolve::variable_spans(items, name, *scope)
+                .first()
+                .and_then(|span| {
+                    let range = span_to_range(span, rope, encoding)?;
+                    Some(LocationLink {
+                        origin_selection_range: origin,
+                        target_uri: uri.clone(),
+                        target_range: range,
+                        target_selection_range: range,
+                    })
+                })
+                .into_iter()
+                .collect(),
+            target => resolver
+                .locate(target)
+                .into_iter()
+                .filter_map(|(target_uri, declaration)| {
+                    let target = self.document(target_uri)?;
+                    let rope = target.text();
+                    Some(LocationLink {
+                        origin_selection_range: origin,
+                        target_uri: Url::parse(target_uri).ok()?,
+                        target_range: span_to_range(&declaration.span, rope, encoding)?,
+                        target_selection_range: span_to_range(
+                            &declaration.name_span,
+                            rope,
+                            encoding,
+                        )?,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        };
+        Ok((!links.is_empty()).then_some(GotoDefinitionResponse::Link(links)))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri;
+        let encoding = *self.position_encoding.read().unwrap();
+        let Some(document) = self.document(uri.as_str()) else {
+            return Ok(None);
+        };
+        let Some(items) = document.items() else {
+            return Ok(None);
+        };
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(position.position, document.text(), encoding);
+        let Some(reference) = resolver.reference_at(items, offset) else {
+            return Ok(None);
+        };
+        let target = reference.target;
+        let include_declaration = params.context.include_declaration;
+        let declarations = resolver.locate(&target);
+
+        // Variables are local to their document, and anything else is looked for in every file
+        let documents = match target {
+            Target::Variable { .. } => vec![(uri.to_string(), document.clone())],
+            _ => self.documents(),
+        };
+        let mut locations = Vec::new();
+        for (file, document) in &documents {
+            let Some(items) = document.items() else {
+                continue;
+            };
+            let mut spans = references::references(items, document.text(), &resolver, &target);
+            if !include_declaration {
+                match target {
+                    // A variable is declared where it first occurs
+                    Target::Variable { .. } if !spans.is_empty() => {
+                        spans.remove(0);
+                    }
+                    _ => spans.retain(|span| {
+                        !declarations
+                            .iter()
+                            .any(|(uri, declaration)| uri == file && declaration.name_span == *span)
+                    }),
+                }
+            }
+            let Ok(file) = Url::parse(file) else {
+                continue;
+            };
+            locations.extend(spans.iter().filter_map(|span| {
+                let range = span_to_range(span, document.text(), encoding)?;
+                Some(Location::new(file.clone(), range))
+            }));
+        }
+        Ok(Some(locations))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let uri = params.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let Some(document) = self.document(&uri) else {
+            return Ok(None);
+        };
+        let Some(items) = document.items() else {
+            return Ok(None);
+        };
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(params.position, document.text(), encoding);
+        let Some(reference) = resolver.reference_at(items, offset) else {
+            return Ok(None);
+        };
+        let (span, name) = renamed_name(&resolver, document.text(), &reference)?;
+        Ok(
+            span_to_range(&span, document.text(), encoding).map(|range| {
+                PrepareRenameResponse::RangeWithPlaceholder {
+                    range,
+                    placeholder: name,
+                }
+            }),
+        )
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let new_name = params.new_name.strip_prefix('$').unwrap_or(&params.new_name);
+        if !rename::is_valid_name(new_name) {
+            let message = format!("`{}` isn't a valid name", params.new_name);
+            return Err(jsonrpc::Error::invalid_params(message));
+        }
+        let Some(document) = self.document(&uri) else {
+            return Ok(None);
+        };
+        let Some(items) = document.items() else {
+            return Ok(None);
+        };
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(position.position, document.text(), encoding);
+        let Some(reference) = resolver.reference_at(items, offset) else {
+            return Ok(None);
+        };
+        let (_, old_name) = renamed_name(&resolver, document.text(), &reference)?;
+
+        // Variables are local to their document, and anything else is renamed in every file
+        let target = reference.target;
+        let documents = match target {
+            Target::Variable { .. } => vec![(uri, document.clone())],
+            _ => self.documents(),
+        };
+        let mut changes = Vec::new();
+        for (file, document) in &documents {
+            let (Some(items), text) = (document.items(), document.text()) else {
+                continue;
+            };
+            let mut spans = references::references(items, text, &resolver, &target);
+            if let Target::Variable { name, scope } = &target {
+                let tokens = document.tokens();
+                spans.extend(rename::interpolated(items, tokens, text, name, *scope));
+            }
+            let edits = rename::edits(text, &spans, &old_name, new_name)
+                .into_iter()
+                .filter_map(|(span, new_text)| {
+                    let range = span_to_range(&span, text, encoding)?;
+                    Some(OneOf::Left(TextEdit::new(range, new_text)))
+                })
+                .collect::<Vec<_>>();
+            let (false, Ok(uri)) = (edits.is_empty(), Url::parse(file)) else {
+                continue;
+            };
+            // Files that aren't open are edited as they are on disk, which has no version
+            let version = self.version_map.get(file).map(|version| *version);
+            changes.push(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits,
+            });
+        }
+        Ok(Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(changes)),
+            ..WorkspaceEdit::default()
+        }))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let Some(document) = self.document(&uri) else {
+            return Ok(None);
+        };
+        let Some(items) = document.items() else {
+            return Ok(None);
+        };
+        let symbols = outline::document_symbols(items, document.text(), encoding);
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    async fn symbol_resolve(&self, symbol: WorkspaceSymbol) -> Result<WorkspaceSymbol> {
+        // Only a symbol that was found without its range needs resolving
+        let OneOf::Right(WorkspaceLocation { uri }) = &symbol.location else {
+            return Ok(symbol);
+        };
+        let Some(declarations) = self.declaration_map.get(uri.as_str()).map(|d| d.clone()) else {
+            return Ok(symbol);
+        };
+        let declaration = declarations.iter().find(|declaration| {
+            workspace::symbol_name(declaration) == symbol.name
+                && workspace::symbol_kind(declaration) == symbol.kind
+                && workspace::container_name(declaration) == symbol.container_name
+        });
+        let location = declaration.and_then(|declaration| self.location(uri.as_str(), declaration));
+        Ok(match location {
+            Some(location) => WorkspaceSymbol {
+                location: OneOf::Left(location),
+                ..symbol
+            },
+            None => symbol,
+        })
     }
 
-    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
-        Ok(Some(CompletionResponse::Array(vec![
-            CompletionItem::new_simple("Hello".to_string(), "Some detail".to_string()),
-            CompletionItem::new_simple("Bye".to_string(), "More detail".to_string()),
-            CompletionItem::new_simple("seila".to_string(), "More detail".to_string()),
-        ])))
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri.to_string();
+        let encoding = *self.position_encoding.read().unwrap();
+        let Some(document) = self.document(&uri) else {
+            return Ok(None);
+        };
+        // What's being typed often doesn't parse, and then there's only its tokens to go by
+        let (items, rope) = (document.items().unwrap_or_default(), document.text());
+        let files = self.declarations();
+        let resolver = resolver(&files);
+
+        let offset = position_to_offset(position.position, rope, encoding);
+        let (tokens, snippets) = (document.tokens(), *self.snippet_support.read().unwrap());
+        let list =
+            completion::completions(items, tokens, rope, &resolver, offset, encoding, snippets);
+        Ok(list.map(CompletionResponse::List))
+    }
+
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        let markdown = *self.completion_markup_kind.read().unwrap() == MarkupKind::Markdown;
+        let files = self.declarations();
+        Ok(completion::resolve(item, &resolver(&files), markdown))
     }
 }
 
 impl Backend {
+    /// Answers `workspace/symbol`, which is routed here rather than to `LanguageServer::symbol`,
+    /// as that can only answer with symbol information, which is located in full. Clients that
+    /// resolve the ranges of workspace symbols get only the files of what's found, and ranges
+    /// are found on `workspaceSymbol/resolve`.
+    async fn workspace_symbols(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<WorkspaceSymbolResponse>> {
+        let files = self.declarations();
+        let found = workspace::search(
+            files.iter().map(|(uri, declarations)| (uri.as_str(), declarations.as_slice())),
+            &params.query,
+        );
+        // Workspace symbols came with resolving them, so clients that can't resolve them get
+        // symbol information
+        if !*self.resolve_symbol_ranges.read().unwrap() {
+            let symbols = found.into_iter().filter_map(|(uri, declaration)| {
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: workspace::symbol_name(declaration),
+                    kind: workspace::symbol_kind(declaration),
+                    tags: is_deprecated(declaration).then(|| vec![SymbolTag::DEPRECATED]),
+                    deprecated: None,
+                    location: self.location(uri, declaration)?,
+                    container_name: workspace::container_name(declaration),
+                })
+            });
+            return Ok(Some(WorkspaceSymbolResponse::Flat(symbols.collect())));
+        }
+        let symbols = found.into_iter().filter_map(|(uri, declaration)| {
+            Some(WorkspaceSymbol {
+                name: workspace::symbol_name(declaration),
+                kind: workspace::symbol_kind(declaration),
+                tags: is_deprecated(declaration).then(|| vec![SymbolTag::DEPRECATED]),
+                container_name: workspace::container_name(declaration),
+                location: OneOf::Right(WorkspaceLocation {
+                    uri: Url::parse(uri).ok()?,
+                }),
+                data: None,
+            })
+        });
+        Ok(Some(WorkspaceSymbolResponse::Nested(symbols.collect())))
+    }
+
     // async fn check_syntax(&self, uri: &str) -> Vec<Diagnostic> {
     //     let document = self.document_map.get(uri);
 
@@ -166,17 +761,101 @@ impl Backend {
     //     }
     // }
 
-    async fn on_change(&self, params: TextDocumentItem) {
-        let rope = ropey::Rope::from_str(&params.text);
-        self.document_map.insert(params.uri.to_string(), rope.clone());
+    /// The open document at `uri`, or else the indexed file.
+    fn document(&self, uri: &str) -> Option<Document> {
+        let document = self.document_map.get(uri).map(|document| document.clone());
+        document.or_else(|| self.workspace_map.get(uri).map(|document| document.clone()))
+    }
+
+    /// Every open document, and the indexed files that aren't open, with their URIs.
+    fn documents(&self) -> Vec<(String, Document)> {
+        let entry = |entry: dashmap::mapref::multiple::RefMulti<String, Document>| {
+            (entry.key().clone(), entry.value().clone())
+        };
+        let open = self.document_map.iter().map(entry).collect::<Vec<_>>();
+        let indexed = self.workspace_map.iter().map(entry).collect::<Vec<_>>();
+        let is_open = |uri: &String| open.iter().any(|(open, _)| open == uri);
+        let indexed = indexed.into_iter().filter(|(uri, _)| !is_open(uri)).collect::<Vec<_>>();
+        open.into_iter().chain(indexed).collect()
+    }
+
+    /// What every open and indexed file declares, with the URIs of the files.
+    fn declarations(&self) -> Vec<(String, Arc<Vec<Declaration>>)> {
+        let files = self.declaration_map.iter();
+        files.map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+    }
+
+    /// Where the name of a declaration in the file at `uri` is.
+    fn location(&self, uri: &str, declaration: &Declaration) -> Option<Location> {
+        let encoding = *self.position_encoding.read().unwrap();
+        let document = self.document(uri)?;
+        let range = span_to_range(&declaration.name_span, document.text(), encoding)?;
+        Some(Location::new(Url::parse(uri).ok()?, range))
+    }
+
+    /// The maps that indexing writes to, to hand to the task it runs in.
+    fn index(&self) -> Index {
+        Index {
+            open: self.document_map.clone(),
+            files: self.workspace_map.clone(),
+            declarations: self.declaration_map.clone(),
+        }
+    }
+
+    /// Parses the PHP files of the workspace folders that aren't open, in a task of its own so
+    /// that large workspaces don't hold up requests meanwhile.
+    fn index_workspace(&self) {
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let (index, client) = (self.index(), self.client.clone());
+        tokio::spawn(async move {
+            let indexed = tokio::task::spawn_blocking(move || {
+                let files = folders.iter().flat_map(|folder| workspace::php_files(folder));
+                files.filter(|path| index.add(path)).count()
+            })
+            .await;
+            match indexed {
+                Ok(indexed) => {
+                    let message = format!("indexed {} files", indexed);
+                    client.log_message(MessageType::INFO, message).await;
+                }
+                Err(err) => client.log_message(MessageType::ERROR, err).await,
+            }
+        });
+    }
+
+    /// Encodes the semantic tokens of a document, and keeps them to take deltas from.
+    fn semantic_tokens(&self, uri: &str) -> Option<SemanticTokens> {
+        let document = self.document_map.get(uri)?.clone();
+        let encoding = *self.position_encoding.read().unwrap();
+        let tokens = &self.semantic_token_map.get(uri)?;
+        let data = semantic_token::encode(tokens, document.text(), encoding);
+        let result_id = self.semantic_tokens_result_id.fetch_add(1, Ordering::Relaxed);
 
-        let ParserResult {
-            ast,
-            parse_errors,
-            semantic_tokens,
-        } = parser(&params.text);
+        let tokens = SemanticTokens {
+            result_id: Some(result_id.to_string()),
+            data,
+        };
+        self.sent_semantic_tokens.insert(uri.to_string(), tokens.clone());
+        Some(tokens)
+    }
 
-        let diagnostics = parse_errors
+    /// Makes a `change` to the document at `uri`, which starts out empty when it isn't open, and
+    /// publishes what follows from it.
+    async fn on_change(&self, uri: Url, version: i32, change: impl FnOnce(&mut Document)) {
+        let php_version = *self.php_version.read().unwrap();
+        let encoding = *self.position_encoding.read().unwrap();
+        // Only what the change touched is parsed again, and the rest works on a snapshot so that
+        // other requests can read the document meanwhile
+        let document = {
+            let mut document =
+                self.document_map.entry(uri.to_string()).or_insert_with(|| Document::new(""));
+            change(&mut document);
+            document.clone()
+        };
+        let rope = document.text();
+
+        let mut diagnostics = document
+            .errors(php_version)
             .into_iter()
             .filter_map(|item| {
                 let (message, span) = match item.reason() {
@@ -212,8 +891,8 @@ impl Backend {
                     // let start_line = rope.try_char_to_line(span.start)?;
                     // let first_char = rope.try_line_to_char(start_line)?;
                     // let start_column = span.start - first_char;
-                    let start_position = offset_to_position(span.start, &rope)?;
-                    let end_position = offset_to_position(span.end, &rope)?;
+                    let start_position = offset_to_position(span.start, rope, encoding)?;
+                    let end_position = offset_to_position(span.end, rope, encoding)?;
                     // let end_line = rope.try_char_to_line(span.end)?;
                     // let first_char = rope.try_line_to_char(end_line)?;
                     // let end_column = span.end - first_char;
@@ -225,20 +904,129 @@ impl Backend {
             })
             .collect::<Vec<_>>();
 
-        self.client
-            .publish_diagnostics(params.uri.clone(), diagnostics, Some(params.version))
-            .await;
+        diagnostics.extend(document.duplicates().into_iter().filter_map(|duplicate| {
+            let range = Range::new(
+                offset_to_position(duplicate.name.1.start, rope, encoding)?,
+                offset_to_position(duplicate.name.1.end, rope, encoding)?,
+            );
+            let first = Range::new(
+                offset_to_position(duplicate.first.start, rope, encoding)?,
+                offset_to_position(duplicate.first.end, rope, encoding)?,
+            );
+            Some(Diagnostic {
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), first),
+                    message: format!("'{}' first declared here", duplicate.name.0),
+                }]),
+                ..Diagnostic::new_simple(range, duplicate.message())
+            })
+        }));
+
+        self.client.publish_diagnostics(uri.clone(), diagnostics, Some(version)).await;
 
-        if let Some(ast) = ast {
-            self.ast_map.insert(params.uri.to_string(), ast);
-        }
         // self.client
         //     .log_message(MessageType::INFO, &format!("{:?}", semantic_tokens))
         //     .await;
-        self.semantic_token_map.insert(params.uri.to_string(), semantic_tokens);
+        self.semantic_token_map.insert(uri.to_string(), document.semantic_tokens());
+        self.declaration_map.insert(uri.to_string(), Arc::new(document.declarations()));
+        self.version_map.insert(uri.to_string(), version);
     }
 }
 
+/// The span of the name that a rename starts from, and the name, or why it can't be renamed.
+fn renamed_name(
+    resolver: &Resolver,
+    text: &ropey::Rope,
+    reference: &resolve::Reference,
+) -> Result<(std::ops::Range<usize>, String)> {
+    let refuse = |reason: String| {
+        let message = format!("This can't be renamed: {}", reason);
+        jsonrpc::Error::invalid_params(message)
+    };
+    if let Some(reason) = rename::refusal(resolver, &reference.target) {
+        return Err(refuse(reason));
+    }
+    let declared = match &reference.target {
+        Target::Variable { name, .. } | Target::Member { name, .. } => name.to_string(),
+        target => match resolver.find(target).first() {
+            Some(declaration) => declaration.short_name().to_string(),
+            None => return Err(refuse("its declaration wasn't found".to_string())),
+        },
+    };
+    let span = rename::name_span(text, &reference.span);
+    let written = text.slice(span.clone()).to_string();
+    match written.eq_ignore_ascii_case(&declared) {
+        true => Ok((span, written)),
+        false => Err(refuse(format!(
+            "`{}` stands for `{}` here",
+            written, declared
+        ))),
+    }
+}
+
+/// Whether the doc comment of a declaration marks it `@deprecated`.
+fn is_deprecated(declaration: &Declaration) -> bool {
+    declaration.doc.as_deref().is_some_and(phpdoc::is_deprecated)
+}
+
+/// A resolver of the names that `files` declare.
+fn resolver(files: &[(String, Arc<Vec<Declaration>>)]) -> Resolver<'_> {
+    Resolver::new(files.iter().map(|(uri, declarations)| (uri.as_str(), declarations.as_slice())))
+}
+
+/// The method that `workspace/symbol` requests are routed to.
+const WORKSPACE_SYMBOLS: &str = "phantom/workspaceSymbol";
+
+/// The indexed files of the workspace, as `Backend` shares them with the tasks that index them.
+struct Index {
+    open: Arc<DashMap<String, Document>>,
+    files: Arc<DashMap<String, Document>>,
+    declarations: Arc<DashMap<String, Arc<Vec<Declaration>>>>,
+}
+
+impl Index {
+    /// Parses the file at `path` into the index, unless it's open, whose declarations are those
+    /// of the open document. Returns whether it was indexed.
+    fn add(&self, path: &std::path::Path) -> bool {
+        let (Ok(uri), Ok(text)) = (Url::from_file_path(path), std::fs::read_to_string(path)) else {
+            return false;
+        };
+        let uri = uri.to_string();
+        let document = Document::new(&text);
+        if self.open.contains_key(&uri) {
+            return false;
+        }
+        self.declarations.insert(uri.clone(), Arc::new(document.declarations()));
+        self.files.insert(uri, document);
+        true
+    }
+
+    /// Drops the file at `uri` from the index, keeping what it declares if it's open.
+    fn remove(&self, uri: &str) {
+        self.files.remove(uri);
+        if !self.open.contains_key(uri) {
+            self.declarations.remove(uri);
+        }
+    }
+}
+
+/// Routes a `workspace/symbol` request to `Backend::workspace_symbols`. tower-lsp registers its
+/// own methods first, so a custom method can't take over one of them.
+fn route_workspace_symbols(request: jsonrpc::Request) -> jsonrpc::Request {
+    if request.method() != "workspace/symbol" {
+        return request;
+    }
+    let (_, id, params) = request.into_parts();
+    let mut routed = jsonrpc::Request::build(WORKSPACE_SYMBOLS);
+    if let Some(id) = id {
+        routed = routed.id(id);
+    }
+    if let Some(params) = params {
+        routed = routed.params(params);
+    }
+    routed.finish()
+}
+
 #[tokio::main]
 async fn main() {
     #[cfg(feature = "runtime-agnostic")]
@@ -251,24 +1039,31 @@ async fn main() {
 
     let (service, socket) = LspService::build(|client| Backend {
         client,
-        ast_map: DashMap::new(),
-        document_map: DashMap::new(),
+        document_map: Arc::new(DashMap::new()),
+        version_map: DashMap::new(),
         semantic_token_map: DashMap::new(),
+        sent_semantic_tokens: DashMap::new(),
+        semantic_tokens_result_id: AtomicU64::new(0),
         diagnostics_map: DashMap::new(),
+        php_version: RwLock::new(PhpVersion::default()),
+        position_encoding: RwLock::new(PositionEncoding::default()),
+        markup_kind: RwLock::new(MarkupKind::Markdown),
+        completion_markup_kind: RwLock::new(MarkupKind::Markdown),
+        snippet_support: RwLock::new(false),
+        resolve_symbol_ranges: RwLock::new(false),
+        watch_files: RwLock::new(false),
+        workspace_folders: RwLock::new(Vec::new()),
+        workspace_map: Arc::new(DashMap::new()),
+        declaration_map: Arc::new(DashMap::new()),
     })
+    .custom_method(WORKSPACE_SYMBOLS, Backend::workspace_symbols)
     .finish();
+    let service = service.map_request(route_workspace_symbols);
 
     serde_json::json!({"test": 20});
     Server::new(stdin, stdout, socket).serve(service).await;
 }
 
-fn offset_to_position(offset: usize, rope: &Rope) -> Option<Position> {
-    let line = rope.try_char_to_line(offset).ok()?;
-    let first_char_of_line = rope.try_line_to_char(line).ok()?;
-    let column = offset - first_char_of_line;
-    Some(Position::new(line as u32, column as u32))
-}
-
 // fn main() {
 //     // let src = "$x = 5; // This is a comment
 //     //     echo $x;
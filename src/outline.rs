@@ -0,0 +1,267 @@
+//! The outline of a document: its namespaces, classes and functions, and the members of its
+//! classes, nested as they're declared.
+
+use crate::chumsky::{Class, ClassKind, Item, Member, Span};
+use crate::phpdoc;
+use crate::position::{span_to_range, PositionEncoding};
+use crate::resolve::is_promoted;
+use ropey::Rope;
+use tower_lsp::lsp_types::{DocumentSymbol, SymbolKind, SymbolTag};
+
+/// The document symbols of `items`, whose ranges count columns in the `encoding`.
+pub fn document_symbols(
+    items: &[Item],
+    text: &Rope,
+    encoding: PositionEncoding,
+) -> Vec<DocumentSymbol> {
+    let outline = Outline { text, encoding };
+    items.iter().filter_map(|item| outline.item(item)).collect()
+}
+
+struct Outline<'a> {
+    text: &'a Rope,
+    encoding: PositionEncoding,
+}
+
+impl Outline<'_> {
+    fn item(&self, item: &Item) -> Option<DocumentSymbol> {
+        match item {
+            Item::Func(func) => {
+                let detail = self.between(func.name.1.end, func.body.1.start);
+                self.symbol(
+                    &func.name.0,
+                    SymbolKind::FUNCTION,
+                    detail,
+                    &func.span,
+                    &func.name.1,
+                    None,
+                )
+            }
+            Item::Class(class) => self.class(class),
+            Item::Const(constant) => self.symbol(
+                &constant.name.0,
+                SymbolKind::CONSTANT,
+                None,
+                &constant.span,
+                &constant.name.1,
+                None,
+            ),
+            Item::Namespace(namespace) => {
+                let children = namespace.items.iter().filter_map(|item| self.item(item)).collect();
+                let (name, selection) = match &namespace.name {
+                    Some((name, span)) => (name.to_string(), span.clone()),
+                    None => ("\\".to_string(), namespace.span.start..namespace.span.start),
+                };
+                self.symbol(
+                    &name,
+                    SymbolKind::NAMESPACE,
+                    None,
+                    &namespace.span,
+                    &selection,
+                    Some(children),
+                )
+            }
+            Item::Use(_) | Item::Stmt(_) => None,
+        }
+    }
+
+    fn class(&self, class: &Class) -> Option<DocumentSymbol> {
+        let (kind, detail) = match class.kind {
+            ClassKind::Class => (SymbolKind::CLASS, None),
+            ClassKind::Interface => (SymbolKind::INTERFACE, None),
+            // There's no kind for traits
+            ClassKind::Trait => (SymbolKind::CLASS, Some("trait".to_string())),
+            ClassKind::Enum => (SymbolKind::ENUM, None),
+        };
+        let mut members = Vec::new();
+        for member in &class.members {
+            let symbol = match member {
+                Member::Method(method) => {
+                    let kind = match method.name.0.eq_ignore_ascii_case("__construct") {
+                        true => SymbolKind::CONSTRUCTOR,
+                        false => SymbolKind::METHOD,
+                    };
+                    let end = method.body.as_ref().map_or(method.span.end, |(_, span)| span.start);
+                    let detail = self.between(method.name.1.end, end);
+                    // Promoted parameters are properties of the class, nested in the constructor
+                    // that declares them since that's where their ranges lie
+                    let promoted = method
+                        .args
+                        .iter()
+                        .filter(|param| is_promoted(param))
+                        .filter_map(|param| {
+                            let detail = param
+                                .ty
+                                .as_ref()
+                                .and_then(|(_, span)| self.between(span.start, span.end));
+                            self.symbol(
+                                &format!("${}", param.name.0),
+                                SymbolKind::PROPERTY,
+                                detail,
+                                &param.span,
+                                &param.name.1,
+                                None,
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    self.symbol(
+                        &method.name.0,
+                        kind,
+                        detail,
+                        &method.span,
+                        &method.name.1,
+                        (!promoted.is_empty()).then_some(promoted),
+                    )
+                }
+                Member::Property(property) => {
+                    let detail = property
+                        .ty
+                        .as_ref()
+                        .and_then(|(_, span)| self.between(span.start, span.end));
+                    let name = format!("${}", property.name.0);
+                    self.symbol(
+                        &name,
+                        SymbolKind::PROPERTY,
+                        detail,
+                        &property.span,
+                        &property.name.1,
+                        None,
+                    )
+                }
+                Member::Const(constant) => self.symbol(
+                    &constant.name.0,
+                    SymbolKind::CONSTANT,
+                    None,
+                    &constant.span,
+                    &constant.name.1,
+                    None,
+                ),
+                Member::Case(case) => self.symbol(
+                    &case.name.0,
+                    SymbolKind::ENUM_MEMBER,
+                    None,
+                    &case.span,
+                    &case.name.1,
+                    None,
+                ),
+                Member::TraitUse(_) => None,
+            };
+            members.extend(symbol);
+        }
+        members.sort_by_key(|symbol| (symbol.range.start.line, symbol.range.start.character));
+        self.symbol(
+            &class.name.0,
+            kind,
+            detail,
+            &class.span,
+            &class.name.1,
+            Some(members),
+        )
+    }
+
+    /// The source between two offsets, with its whitespace collapsed, as in the parameters and
+    /// return type between the name of a function and its body.
+    fn between(&self, start: usize, end: usize) -> Option<String> {
+        let source = self.text.get_slice(start..end.max(start))?.to_string();
+        let source = source.split_whitespace().collect::<Vec<_>>().join(" ");
+        let source = source.trim_end_matches([';', '{']).trim();
+        (!source.is_empty()).then(|| source.to_string())
+    }
+
+    fn symbol(
+        &self,
+        name: &str,
+        kind: SymbolKind,
+        detail: Option<String>,
+        span: &Span,
+        name_span: &Span,
+        children: Option<Vec<DocumentSymbol>>,
+    ) -> Option<DocumentSymbol> {
+        let deprecated = phpdoc::doc_comment(self.text, span.start)
+            .is_some_and(|doc| phpdoc::is_deprecated(&doc));
+        #[allow(deprecated)]
+        Some(DocumentSymbol {
+            name: name.to_string(),
+            detail,
+            kind,
+            tags: deprecated.then(|| vec![SymbolTag::DEPRECATED]),
+            deprecated: None,
+            range: span_to_range(span, self.text, self.encoding)?,
+            selection_range: span_to_range(name_span, self.text, self.encoding)?,
+            children,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn test_symbols_nest_as_declared() {
+        let src = r#"<?php
+namespace App;
+
+function main(): void {}
+
+enum Suit: string {
+    case Hearts = 'H';
+    const Wild = self::Hearts;
+}
+
+/** @deprecated */
+class User {
+    public function __construct(private int $id) {}
+    public ?string $name;
+}
+"#;
+        let document = Document::new(src);
+        let symbols = document_symbols(
+            document.items().unwrap(),
+            document.text(),
+            PositionEncoding::Utf16,
+        );
+        let outline = |symbols: &[DocumentSymbol]| {
+            symbols
+                .iter()
+                .map(|symbol| format!("{:?} {}", symbol.kind, symbol.name))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(outline(&symbols), ["Namespace App"]);
+        let children = symbols[0].children.as_deref().unwrap();
+        assert_eq!(
+            outline(children),
+            ["Function main", "Enum Suit", "Class User"]
+        );
+        assert_eq!(children[0].detail.as_deref(), Some("(): void"));
+        assert_eq!(
+            outline(children[1].children.as_deref().unwrap()),
+            ["EnumMember Hearts", "Constant Wild"]
+        );
+        let user = &children[2];
+        assert_eq!(user.tags, Some(vec![SymbolTag::DEPRECATED]));
+        let members = user.children.as_deref().unwrap();
+        assert_eq!(
+            outline(members),
+            ["Constructor __construct", "Property $name"]
+        );
+        assert_eq!(members[1].detail.as_deref(), Some("?string"));
+        // Promoted parameters nest in the constructor, within whose range they are
+        let promoted = members[0].children.as_deref().unwrap();
+        assert_eq!(outline(promoted), ["Property $id"]);
+        assert!(
+            members[0].range.start <= promoted[0].range.start
+                && promoted[0].range.end <= members[0].range.end
+        );
+
+        // The name is selected within the declaration
+        assert_eq!(user.selection_range.start.line, 11);
+        assert_eq!(user.selection_range.start.character, 6);
+        assert!(
+            user.range.start <= user.selection_range.start
+                && user.selection_range.end <= user.range.end
+        );
+    }
+}
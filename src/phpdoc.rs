@@ -0,0 +1,211 @@
+//! PHPDoc comments, the `/** ... */` comments that document the declaration after them.
+
+use ropey::Rope;
+
+/// The doc comment right before the declaration that starts at `start`, with only whitespace
+/// between them.
+pub fn doc_comment(text: &Rope, start: usize) -> Option<String> {
+    let mut end = start.min(text.len_chars());
+    while end > 0 && text.char(end - 1).is_whitespace() {
+        end -= 1;
+    }
+    if end < 4 || text.slice(end - 2..end) != "*/" {
+        return None;
+    }
+
+    // Look back for the `/*` that opens it
+    let mut at = end - 2;
+    let mut chars = text.chars_at(at).reversed();
+    let mut next = None;
+    loop {
+        let c = chars.next()?;
+        at -= 1;
+        if c == '/' && next == Some('*') {
+            break;
+        }
+        next = Some(c);
+    }
+    let comment = text.slice(at..end).to_string();
+    comment.starts_with("/**").then_some(comment)
+}
+
+/// Whether a doc comment marks what it documents as `@deprecated`.
+pub fn is_deprecated(comment: &str) -> bool {
+    comment.split('@').skip(1).any(|tag| {
+        tag.strip_prefix("deprecated")
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric()))
+    })
+}
+
+/// A parsed doc comment: its description and its tags, as in `@param int $n The count`.
+#[derive(Debug, Default, PartialEq)]
+pub struct DocBlock {
+    pub description: String,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Tag {
+    /// The name without the `@`, as in `param`
+    pub name: String,
+    /// The type of `@param`, `@return`, `@throws`, `@var` and `@property` tags
+    pub ty: Option<String>,
+    /// The variable of `@param`, `@var` and `@property` tags, with its `$`
+    pub variable: Option<String>,
+    pub description: String,
+}
+
+/// The tags that start with a type.
+const TYPED: &[&str] = &[
+    "param",
+    "return",
+    "throws",
+    "var",
+    "property",
+    "property-read",
+];
+
+pub fn parse(comment: &str) -> DocBlock {
+    let body = comment.trim_start_matches("/**").trim_end_matches("*/");
+    let mut doc = DocBlock::default();
+    for line in body.lines() {
+        let line = line.trim();
+        let line =
+            line.strip_prefix('*').map_or(line, |line| line.strip_prefix(' ').unwrap_or(line));
+        match (line.strip_prefix('@'), doc.tags.last_mut()) {
+            (Some(tag), _) => doc.tags.push(parse_tag(tag)),
+            (None, Some(tag)) if !line.trim().is_empty() => {
+                tag.description = format!("{} {}", tag.description, line.trim()).trim().to_string();
+            }
+            (None, Some(_)) => {}
+            (None, None) => {
+                doc.description.push_str(line.trim_end());
+                doc.description.push('\n');
+            }
+        }
+    }
+    doc.description = doc.description.trim().to_string();
+    doc
+}
+
+fn parse_tag(tag: &str) -> Tag {
+    let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    rest = rest.trim_start();
+    let mut ty = None;
+    if TYPED.contains(&name) && !rest.starts_with('$') && !rest.is_empty() {
+        // A type ends at whitespace outside of brackets, as in `array<string, int>`
+        let mut depth = 0i32;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '<' | '(' | '{' | '[' => depth += 1,
+                    '>' | ')' | '}' | ']' => depth -= 1,
+                    _ => {}
+                }
+                c.is_whitespace() && depth <= 0
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        ty = Some(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    let mut variable = None;
+    if TYPED.contains(&name) && rest.starts_with('$') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        variable = Some(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    Tag {
+        name: name.to_string(),
+        ty,
+        variable,
+        description: rest.trim().to_string(),
+    }
+}
+
+impl DocBlock {
+    pub fn tag(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+
+    /// The `@param` tag of the parameter `$name`.
+    pub fn param(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| {
+            tag.name == "param"
+                && tag.variable.as_deref().and_then(|v| v.strip_prefix('$')) == Some(name)
+        })
+    }
+
+    /// Renders the description, then the `@param`, `@return`, `@throws` and `@deprecated` tags,
+    /// as markdown or as plain text.
+    pub fn render(&self, markdown: bool) -> String {
+        let code = |text: &str| match markdown {
+            true => format!("`{}`", text),
+            false => text.to_string(),
+        };
+        let mut lines = Vec::new();
+        for tag in &self.tags {
+            let mut line = match (tag.name.as_str(), markdown) {
+                ("deprecated", true) => "**Deprecated**".to_string(),
+                ("param" | "return" | "throws", true) => format!("*@{}*", tag.name),
+                ("deprecated" | "param" | "return" | "throws", false) => format!("@{}", tag.name),
+                _ => continue,
+            };
+            for part in [&tag.ty, &tag.variable].into_iter().flatten() {
+                line = format!("{} {}", line, code(part));
+            }
+            if !tag.description.is_empty() {
+                let dash = if tag.ty.is_some() || tag.variable.is_some() {
+                    " —"
+                } else {
+                    ""
+                };
+                line = format!("{}{} {}", line, dash, tag.description);
+            }
+            lines.push(line);
+        }
+
+        let tags = lines.join(if markdown { "  \n" } else { "\n" });
+        [self.description.as_str(), tags.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_comment_is_the_one_right_before() {
+        let text = Rope::from_str("<?php\n/* a */\n/** b\n * @deprecated */\n  function f() {}");
+        let start = text.to_string().find("function").unwrap();
+
+        let comment = doc_comment(&text, start).unwrap();
+        assert_eq!(comment, "/** b\n * @deprecated */");
+        assert!(is_deprecated(&comment));
+        assert_eq!(doc_comment(&text, 8), None);
+        assert!(!is_deprecated("/** @deprecatedly */"));
+    }
+
+    #[test]
+    fn test_parse_and_render_tags() {
+        let doc = parse(
+            "/**\n * Sums the numbers.\n *\n * @param array<string, int> $xs The numbers,\n *   all of them\n * @return int\n * @throws \\RangeException When empty\n * @deprecated Use total()\n */",
+        );
+        assert_eq!(doc.description, "Sums the numbers.");
+        let xs = doc.param("xs").unwrap();
+        assert_eq!(xs.ty.as_deref(), Some("array<string, int>"));
+        assert_eq!(xs.description, "The numbers, all of them");
+        assert_eq!(doc.tag("return").unwrap().ty.as_deref(), Some("int"));
+
+        assert_eq!(
+            doc.render(true),
+            "Sums the numbers.\n\n*@param* `array<string, int>` `$xs` — The numbers, all of them  \n\
+             *@return* `int`  \n*@throws* `\\RangeException` — When empty  \n**Deprecated** Use total()"
+        );
+        assert!(doc.render(false).ends_with("@deprecated Use total()"));
+    }
+}
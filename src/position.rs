@@ -0,0 +1,181 @@
+//! Conversions between LSP positions, as lines and columns, and offsets in a document's text,
+//! which count characters. Columns count code units of the encoding negotiated with the client.
+
+use ropey::{Rope, RopeSlice};
+use std::ops::Range as Span;
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
+
+/// What the columns of positions count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Bytes
+    Utf8,
+    /// UTF-16 code units, which the LSP takes when no other encoding is negotiated
+    #[default]
+    Utf16,
+    /// Characters
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The first of the encodings a client supports, in its order of preference, that is known
+    /// here, or UTF-16, which every client supports.
+    pub fn negotiate(supported: Option<&[PositionEncodingKind]>) -> Self {
+        supported
+            .into_iter()
+            .flatten()
+            .find_map(|kind| match kind.as_str() {
+                "utf-8" => Some(PositionEncoding::Utf8),
+                "utf-16" => Some(PositionEncoding::Utf16),
+                "utf-32" => Some(PositionEncoding::Utf32),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// How many code units the characters of `text` before `offset` take.
+    pub fn units(self, text: RopeSlice, offset: usize) -> usize {
+        match self {
+            PositionEncoding::Utf8 => text.char_to_byte(offset),
+            PositionEncoding::Utf16 => text.char_to_utf16_cu(offset),
+            PositionEncoding::Utf32 => offset,
+        }
+    }
+
+    /// The offset of the character at `units` code units into `text`, or of the character they
+    /// fall in.
+    fn offset(self, text: RopeSlice, units: usize) -> usize {
+        match self {
+            PositionEncoding::Utf8 => text.byte_to_char(units),
+            PositionEncoding::Utf16 => text.utf16_cu_to_char(units),
+            PositionEncoding::Utf32 => units,
+        }
+    }
+}
+
+/// The offset of `position` in `rope`. As the LSP asks, a column past the end of its line is the
+/// end of the line, and a line past the end of the text is the end of the text.
+pub fn position_to_offset(position: Position, rope: &Rope, encoding: PositionEncoding) -> usize {
+    let Some(line) = rope.get_line(position.line as usize) else {
+        return rope.len_chars();
+    };
+    let line_start = rope.line_to_char(position.line as usize);
+    let mut len = line.len_chars();
+    while len > 0 && matches!(line.char(len - 1), '\n' | '\r') {
+        len -= 1;
+    }
+    let column = (position.character as usize).min(encoding.units(line, len));
+    line_start + encoding.offset(line, column)
+}
+
+pub fn offset_to_position(
+    offset: usize,
+    rope: &Rope,
+    encoding: PositionEncoding,
+) -> Option<Position> {
+    let line = rope.try_char_to_line(offset).ok()?;
+    let first_char_of_line = rope.try_line_to_char(line).ok()?;
+    let column = encoding.units(
+        rope.slice(first_char_of_line..),
+        offset - first_char_of_line,
+    );
+    Some(Position::new(line as u32, column as u32))
+}
+
+/// The range of the characters in `span`.
+pub fn span_to_range(span: &Span<usize>, rope: &Rope, encoding: PositionEncoding) -> Option<Range> {
+    Some(Range::new(
+        offset_to_position(span.start, rope, encoding)?,
+        offset_to_position(span.end, rope, encoding)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_count_code_units_of_the_encoding() {
+        let rope = Rope::from_str("<?php\necho 'Olá, mundo! 👋' . $x;\n");
+        // `$x` is after an accent, which is two bytes, and an emoji, which is two UTF-16 units
+        let x = rope.to_string().find('$').map(|byte| rope.byte_to_char(byte)).unwrap();
+
+        for (encoding, column) in [
+            (PositionEncoding::Utf8, 27),
+            (PositionEncoding::Utf16, 24),
+            (PositionEncoding::Utf32, 23),
+        ] {
+            let position = offset_to_position(x, &rope, encoding).unwrap();
+            assert_eq!(position, Position::new(1, column), "{:?}", encoding);
+            assert_eq!(
+                position_to_offset(position, &rope, encoding),
+                x,
+                "{:?}",
+                encoding
+            );
+        }
+
+        // Within the emoji is the emoji, and past the end of a line is its end
+        let emoji = x - 5;
+        let within = Position::new(1, 19);
+        assert_eq!(
+            position_to_offset(within, &rope, PositionEncoding::Utf16),
+            emoji
+        );
+        let past = Position::new(1, 99);
+        assert_eq!(
+            position_to_offset(past, &rope, PositionEncoding::Utf8),
+            x + 3
+        );
+    }
+
+    #[test]
+    fn test_only_lsp_line_breaks_start_lines() {
+        // A line separator, a next line and a form feed in a string don't break the line
+        let rope = Rope::from_str("<?php\n$a = '\u{2028}\u{85}\u{c}'; $b = 1;\r$c;\r\n$d;");
+        let at = |needle: &str| rope.byte_to_char(rope.to_string().find(needle).unwrap());
+        let encoding = PositionEncoding::Utf16;
+
+        assert_eq!(
+            offset_to_position(at("$b"), &rope, encoding),
+            Some(Position::new(1, 12))
+        );
+        assert_eq!(
+            offset_to_position(at("$c"), &rope, encoding),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(
+            offset_to_position(at("$d"), &rope, encoding),
+            Some(Position::new(3, 0))
+        );
+        assert_eq!(
+            position_to_offset(Position::new(1, 12), &rope, encoding),
+            at("$b")
+        );
+    }
+
+    #[test]
+    fn test_negotiates_the_first_known_encoding() {
+        let kinds = [
+            PositionEncodingKind::new("utf-7"),
+            PositionEncodingKind::UTF8,
+        ];
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&kinds)),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[])),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+    }
+}
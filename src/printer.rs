@@ -0,0 +1,884 @@
+//! Prints an AST back to PHP source, such that parsing the output gives back the same AST.
+//!
+//! Comments, whitespace and the choice between equivalent syntaxes, like `elseif` or the
+//! alternative `endif` syntax, aren't kept in the AST, so the output is in a single style.
+
+use crate::chumsky::{
+    BinaryOp, Class, ClassKind, Const, EnumCase, Expr, Func, IncludeKind, Item, MatchArm, Member,
+    Method, Modifier, Namespace, Param, Property, PropertyHook, Spanned, SwitchCase, Type, UnaryOp,
+    Use, UseKind, Value,
+};
+
+/// Prints a PHP file, starting with its open tag.
+pub fn print(items: &[Item]) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        indent: 0,
+    };
+
+    let items = match items {
+        // Inline HTML before the open tag
+        [Item::Stmt((Expr::InlineHtml(html), _)), items @ ..] => {
+            printer.out.push_str(html);
+            items
+        }
+        items => items,
+    };
+    printer.out.push_str("<?php");
+    printer.newline();
+    printer.items(items);
+
+    // A file that ends in inline HTML needs no open tag after it
+    let out = printer.out.trim_end();
+    match out.strip_suffix("<?php") {
+        Some(out) if !out.is_empty() => out.to_string(),
+        _ => format!("{}\n", out),
+    }
+}
+
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+/// How tightly an expression binds, from assignments to atoms.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Assign,
+    Comparison,
+    Relational,
+    Concat,
+    Sum,
+    Product,
+    Prefix,
+    Postfix,
+    Call,
+    Atom,
+}
+
+fn prec(expr: &Expr) -> Prec {
+    match expr {
+        Expr::Binary(_, op, _) => match op {
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Identical | BinaryOp::NotIdentical => {
+                Prec::Comparison
+            }
+            BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => Prec::Relational,
+            BinaryOp::Concat => Prec::Concat,
+            BinaryOp::Add | BinaryOp::Sub => Prec::Sum,
+            BinaryOp::Mul | BinaryOp::Div => Prec::Product,
+        },
+        Expr::Unary(UnaryOp::PostInc | UnaryOp::PostDec, _) => Prec::Postfix,
+        Expr::Unary(..) => Prec::Prefix,
+        Expr::Call(..)
+        | Expr::Prop(..)
+        | Expr::NullsafeProp(..)
+        | Expr::StaticProp(..)
+        | Expr::ClassConst(..) => Prec::Call,
+        // `new Foo()->bar()` only parses since PHP 8.4
+        Expr::New(..) => Prec::Prefix,
+        // Statements spelled like expressions take everything after them as their operand, and
+        // functions can't be called or operated on without parentheses
+        Expr::Assign(..)
+        | Expr::Echo(_)
+        | Expr::Print(_)
+        | Expr::Return(_)
+        | Expr::Global(_)
+        | Expr::Static(_)
+        | Expr::Goto(_)
+        | Expr::Exit(_)
+        | Expr::Include(..)
+        | Expr::Break(_)
+        | Expr::Continue(_)
+        | Expr::Closure(..)
+        | Expr::ArrowFn(..) => Prec::Assign,
+        _ => Prec::Atom,
+    }
+}
+
+/// Statements that are parsed as blocks rather than expressions, and need no semicolon.
+fn is_block(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Then(..)
+            | Expr::Var(..)
+            | Expr::If(..)
+            | Expr::While(..)
+            | Expr::For(..)
+            | Expr::Foreach(..)
+            | Expr::Switch(..)
+            | Expr::Label(_)
+            | Expr::InlineHtml(_)
+    )
+}
+
+/// A single block statement, or blocks following each other, which the parser folds together.
+fn is_block_chain(expr: &Expr) -> bool {
+    match expr {
+        Expr::Then(a, b) => is_block_chain(&a.0) && is_single_block(&b.0),
+        expr => is_single_block(expr),
+    }
+}
+
+fn is_single_block(expr: &Expr) -> bool {
+    is_block(expr) && !matches!(expr, Expr::Then(..) | Expr::Var(..))
+}
+
+/// Whether the first of the statements is a block, which would join the blocks before it.
+fn starts_with_block(expr: &Expr) -> bool {
+    match expr {
+        Expr::Then(a, _) => starts_with_block(&a.0),
+        expr => is_single_block(expr),
+    }
+}
+
+fn is_empty(expr: &Expr) -> bool {
+    matches!(expr, Expr::Value(Value::Null))
+}
+
+impl Printer {
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    fn items(&mut self, items: &[Item]) {
+        // A namespace without a name needs braces, and then so do all the others
+        let braced = items
+            .iter()
+            .any(|item| matches!(item, Item::Namespace(Namespace { name: None, .. })));
+
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                // Declarations are set apart by a blank line
+                if [item, &items[i - 1]]
+                    .iter()
+                    .any(|item| matches!(item, Item::Func(_) | Item::Class(_)))
+                {
+                    self.newline();
+                }
+                self.newline();
+            }
+            match item {
+                Item::Func(func) => self.func(func),
+                Item::Class(class) => self.class(class),
+                Item::Const(constant) => self.constant(constant),
+                Item::Use(use_) => self.use_(use_),
+                Item::Namespace(namespace) => self.namespace(namespace, braced),
+                Item::Stmt(stmt) => self.stmts(stmt),
+            }
+        }
+    }
+
+    fn func(&mut self, func: &Func) {
+        self.write(&format!("function {}", func.name.0));
+        self.params(&func.args);
+        self.ret(&func.ret);
+        self.write(" ");
+        self.body(&func.body);
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.modifiers(&class.modifiers);
+        self.write(match class.kind {
+            ClassKind::Class => "class ",
+            ClassKind::Interface => "interface ",
+            ClassKind::Trait => "trait ",
+            ClassKind::Enum => "enum ",
+        });
+        self.write(&class.name.0);
+        if let Some(backing) = &class.backing {
+            self.write(": ");
+            self.ty(&backing.0);
+        }
+        for (keyword, names) in [
+            (" extends ", &class.extends),
+            (" implements ", &class.implements),
+        ] {
+            if !names.is_empty() {
+                self.write(keyword);
+                self.write(
+                    &names.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", "),
+                );
+            }
+        }
+
+        self.write(" {");
+        self.indent += 1;
+        for member in &class.members {
+            self.newline();
+            match member {
+                Member::Method(method) => self.method(method),
+                Member::Property(property) => self.property(property),
+                Member::Const(constant) => self.constant(constant),
+                Member::TraitUse((name, _)) => self.write(&format!("use {};", name)),
+                Member::Case(case) => self.enum_case(case),
+            }
+        }
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    fn method(&mut self, method: &Method) {
+        self.modifiers(&method.modifiers);
+        self.write(&format!("function {}", method.name.0));
+        self.params(&method.args);
+        self.ret(&method.ret);
+        match &method.body {
+            Some(body) => {
+                self.write(" ");
+                self.body(body);
+            }
+            None => self.write(";"),
+        }
+    }
+
+    fn property(&mut self, property: &Property) {
+        self.modifiers(&property.modifiers);
+        if let Some((ty, _)) = &property.ty {
+            self.ty(ty);
+            self.write(" ");
+        }
+        self.write(&format!("${}", property.name.0));
+        if let Some(default) = &property.default {
+            self.write(" = ");
+            self.expr(default, Prec::Assign);
+        }
+        if property.hooks.is_empty() {
+            self.write(";");
+            return;
+        }
+
+        self.write(" {");
+        self.indent += 1;
+        for hook in &property.hooks {
+            self.newline();
+            self.hook(hook);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    fn hook(&mut self, hook: &PropertyHook) {
+        self.write(&hook.name.0);
+        if !hook.args.is_empty() {
+            self.params(&hook.args);
+        }
+        match &hook.body {
+            Some(body) if !is_block(&body.0) && !is_empty(&body.0) => {
+                self.write(" => ");
+                self.expr(body, Prec::Assign);
+                self.write(";");
+            }
+            Some(body) => {
+                self.write(" ");
+                self.body(body);
+            }
+            None => self.write(";"),
+        }
+    }
+
+    fn enum_case(&mut self, case: &EnumCase) {
+        self.write(&format!("case {}", case.name.0));
+        if let Some(value) = &case.value {
+            self.write(" = ");
+            self.expr(value, Prec::Assign);
+        }
+        self.write(";");
+    }
+
+    fn constant(&mut self, constant: &Const) {
+        self.modifiers(&constant.modifiers);
+        self.write(&format!("const {} = ", constant.name.0));
+        self.expr(&constant.value, Prec::Assign);
+        self.write(";");
+    }
+
+    fn use_(&mut self, use_: &Use) {
+        self.write(match use_.kind {
+            UseKind::Class => "use ",
+            UseKind::Function => "use function ",
+            UseKind::Const => "use const ",
+        });
+        self.write(&use_.name.0);
+        if let Some((alias, _)) = &use_.alias {
+            self.write(&format!(" as {}", alias));
+        }
+        self.write(";");
+    }
+
+    fn namespace(&mut self, namespace: &Namespace, braced: bool) {
+        self.write("namespace");
+        if let Some((name, _)) = &namespace.name {
+            self.write(&format!(" {}", name));
+        }
+        if !braced {
+            self.write(";");
+            self.newline();
+            self.items(&namespace.items);
+            return;
+        }
+
+        self.write(" {");
+        self.indent += 1;
+        self.newline();
+        self.items(&namespace.items);
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    fn modifiers(&mut self, modifiers: &[Spanned<Modifier>]) {
+        for (modifier, _) in modifiers {
+            self.write(match modifier {
+                Modifier::Public => "public ",
+                Modifier::Protected => "protected ",
+                Modifier::Private => "private ",
+                Modifier::Static => "static ",
+                Modifier::Abstract => "abstract ",
+                Modifier::Final => "final ",
+                Modifier::Readonly => "readonly ",
+            });
+        }
+    }
+
+    fn params(&mut self, params: &[Param]) {
+        self.write("(");
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.modifiers(&param.modifiers);
+            if let Some((ty, _)) = &param.ty {
+                self.ty(ty);
+                self.write(" ");
+            }
+            if param.by_ref {
+                self.write("&");
+            }
+            if param.variadic {
+                self.write("...");
+            }
+            self.write(&format!("${}", param.name.0));
+            if let Some(default) = &param.default {
+                self.write(" = ");
+                self.expr(default, Prec::Assign);
+            }
+        }
+        self.write(")");
+    }
+
+    fn ret(&mut self, ret: &Option<Spanned<Type>>) {
+        if let Some((ty, _)) = ret {
+            self.write(": ");
+            self.ty(ty);
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::Named((name, _)) => self.write(name),
+            Type::Nullable(ty) => {
+                self.write("?");
+                self.ty(ty);
+            }
+            Type::Union(types) => {
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        self.write("|");
+                    }
+                    match ty {
+                        Type::Intersection(_) => {
+                            self.write("(");
+                            self.ty(ty);
+                            self.write(")");
+                        }
+                        ty => self.ty(ty),
+                    }
+                }
+            }
+            Type::Intersection(types) => {
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        self.write("&");
+                    }
+                    self.ty(ty);
+                }
+            }
+        }
+    }
+
+    /// Prints statements in braces, as the body of a function or a control structure.
+    fn body(&mut self, body: &Spanned<Expr>) {
+        if is_empty(&body.0) {
+            self.write("{}");
+            return;
+        }
+        self.write("{");
+        self.indent += 1;
+        self.newline();
+        self.stmts(body);
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    /// Prints a chain of statements, as built by the parser.
+    fn stmts(&mut self, stmts: &Spanned<Expr>) {
+        match &stmts.0 {
+            // The empty statement after the last semicolon
+            Expr::Value(Value::Null) => {}
+            Expr::Var((name, _), value, rest) => {
+                self.write(&format!("${} = ", name));
+                self.expr(value, Prec::Assign);
+                self.write(";");
+                self.rest(rest);
+            }
+            // A statement ended by a close tag, as in `echo 1 ?>`
+            Expr::Then(a, rest) if matches!(&a.0, Expr::Then(stmt, html) if !is_block(&stmt.0) && matches!(html.0, Expr::InlineHtml(_))) =>
+            {
+                let Expr::Then(stmt, html) = &a.0 else {
+                    unreachable!()
+                };
+                self.stmt(stmt);
+                self.block(html);
+                self.rest(rest);
+            }
+            Expr::Then(a, rest) if !is_block(&a.0) => {
+                self.stmt(a);
+                self.write(";");
+                self.rest(rest);
+            }
+            // Blocks need no semicolon, but a lone one after them is an empty statement
+            Expr::Then(a, rest) => {
+                self.block(a);
+                match &rest.0 {
+                    rest if is_empty(rest) => self.write(";"),
+                    // Braces keep the statements apart from the blocks before them
+                    Expr::Then(..) if starts_with_block(&rest.0) => {
+                        self.write(" ");
+                        self.body(rest);
+                    }
+                    _ => {
+                        self.newline();
+                        self.stmts(rest);
+                    }
+                }
+            }
+            stmt if is_block(stmt) => self.block(stmts),
+            _ => self.stmt(stmts),
+        }
+    }
+
+    fn rest(&mut self, rest: &Spanned<Expr>) {
+        if !is_empty(&rest.0) {
+            self.newline();
+            self.stmts(rest);
+        }
+    }
+
+    /// Prints an expression statement, without its semicolon.
+    fn stmt(&mut self, stmt: &Spanned<Expr>) {
+        match stmt.0 {
+            // `function` at the start of a statement would be a declaration
+            Expr::Closure(..) => self.expr(stmt, Prec::Call),
+            _ => self.expr(stmt, Prec::Assign),
+        }
+    }
+
+    fn block(&mut self, block: &Spanned<Expr>) {
+        match &block.0 {
+            Expr::If(cond, then, else_) => {
+                self.write("if (");
+                self.expr(cond, Prec::Assign);
+                self.write(") ");
+                self.body(then);
+                match &else_.0 {
+                    Expr::Value(Value::Null) => {}
+                    Expr::If(..) => {
+                        self.write(" else ");
+                        self.block(else_);
+                    }
+                    _ => {
+                        self.write(" else ");
+                        self.body(else_);
+                    }
+                }
+            }
+            Expr::While(cond, body) => {
+                self.write("while (");
+                self.expr(cond, Prec::Assign);
+                self.write(") ");
+                self.body(body);
+            }
+            Expr::For(init, cond, step, body) => {
+                self.write("for (");
+                self.list(init);
+                self.write("; ");
+                self.list(cond);
+                self.write("; ");
+                self.list(step);
+                self.write(") ");
+                self.body(body);
+            }
+            Expr::Foreach(subject, key, value, body) => {
+                self.write("foreach (");
+                self.expr(subject, Prec::Assign);
+                self.write(" as ");
+                if let Some(key) = key {
+                    self.expr(key, Prec::Assign);
+                    self.write(" => ");
+                }
+                self.expr(value, Prec::Assign);
+                self.write(") ");
+                self.body(body);
+            }
+            Expr::Switch(subject, cases) => {
+                self.write("switch (");
+                self.expr(subject, Prec::Assign);
+                self.write(") {");
+                self.indent += 1;
+                for SwitchCase { test, body, .. } in cases {
+                    self.newline();
+                    match test {
+                        Some(test) => {
+                            self.write("case ");
+                            self.expr(test, Prec::Assign);
+                            self.write(":");
+                        }
+                        None => self.write("default:"),
+                    }
+                    if let Some(body) = body {
+                        self.indent += 1;
+                        self.newline();
+                        self.stmts(body);
+                        self.indent -= 1;
+                    }
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            }
+            Expr::Label((name, _)) => self.write(&format!("{}:", name)),
+            // The close tag swallows a newline after it, so one is added for the HTML to keep its own
+            Expr::InlineHtml(html) => {
+                self.write("?>\n");
+                self.write(html);
+                self.write("<?php");
+                self.newline();
+            }
+            Expr::Then(a, b) if is_block_chain(&block.0) => {
+                self.block(a);
+                self.newline();
+                self.block(b);
+            }
+            // Statements nested in a block of their own
+            _ => self.body(block),
+        }
+    }
+
+    fn list(&mut self, exprs: &[Spanned<Expr>]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(expr, Prec::Assign);
+        }
+    }
+
+    /// Prints an expression, in parentheses if it binds looser than `min`.
+    fn expr(&mut self, expr: &Spanned<Expr>, min: Prec) {
+        if prec(&expr.0) < min {
+            self.write("(");
+            self.expr(expr, Prec::Assign);
+            self.write(")");
+            return;
+        }
+
+        match &expr.0 {
+            Expr::Error => self.write("null"),
+            Expr::Value(value) => self.value(value),
+            Expr::List(items) => {
+                self.write("[");
+                self.list(items);
+                self.write("]");
+            }
+            Expr::Local((name, _)) => self.write(&format!("${}", name)),
+            Expr::Name((name, _)) => self.write(name),
+            Expr::Binary(a, op, b) => {
+                let prec = prec(&expr.0);
+                self.expr(a, prec);
+                self.write(match op {
+                    BinaryOp::Add => " + ",
+                    BinaryOp::Sub => " - ",
+                    BinaryOp::Mul => " * ",
+                    BinaryOp::Div => " / ",
+                    BinaryOp::Concat => " . ",
+                    BinaryOp::Eq => " == ",
+                    BinaryOp::NotEq => " != ",
+                    BinaryOp::Identical => " === ",
+                    BinaryOp::NotIdentical => " !== ",
+                    BinaryOp::Lt => " < ",
+                    BinaryOp::LtEq => " <= ",
+                    BinaryOp::Gt => " > ",
+                    BinaryOp::GtEq => " >= ",
+                });
+                // Binary operators are left associative
+                self.expr(b, next(prec));
+            }
+            Expr::Assign(target, value) => {
+                self.expr(target, Prec::Comparison);
+                self.write(" = ");
+                self.expr(value, Prec::Assign);
+            }
+            Expr::Unary(op @ (UnaryOp::PostInc | UnaryOp::PostDec), a) => {
+                self.expr(a, Prec::Call);
+                self.write(match op {
+                    UnaryOp::PostInc => "++",
+                    _ => "--",
+                });
+            }
+            Expr::Unary(op, a) => {
+                self.write(match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Neg => "-",
+                    UnaryOp::PreInc => "++",
+                    _ => "--",
+                });
+                // Operator characters next to each other would lex as a single operator
+                let start = self.out.len();
+                self.expr(a, Prec::Prefix);
+                if self.out[start..].starts_with(['+', '-', '*', '/', '!', '=', '<', '>']) {
+                    self.out.insert(start, ' ');
+                }
+            }
+            Expr::Call(f, (args, _)) => {
+                self.expr(f, Prec::Call);
+                self.write("(");
+                self.list(args);
+                self.write(")");
+            }
+            Expr::Prop(object, (name, _)) => {
+                self.expr(object, Prec::Call);
+                self.write(&format!("->{}", name));
+            }
+            Expr::NullsafeProp(object, (name, _)) => {
+                self.expr(object, Prec::Call);
+                self.write(&format!("?->{}", name));
+            }
+            Expr::StaticProp(class, (name, _)) => {
+                self.expr(class, Prec::Call);
+                self.write(&format!("::${}", name));
+            }
+            Expr::ClassConst(class, (name, _)) => {
+                self.expr(class, Prec::Call);
+                self.write(&format!("::{}", name));
+            }
+            Expr::New(class, args) => {
+                self.write("new ");
+                self.expr(class, Prec::Atom);
+                if let Some((args, _)) = args {
+                    self.write("(");
+                    self.list(args);
+                    self.write(")");
+                }
+            }
+            Expr::Echo(args) => {
+                self.write("echo ");
+                self.list(args);
+            }
+            Expr::Print(a) => {
+                self.write("print ");
+                self.expr(a, Prec::Assign);
+            }
+            Expr::Return(a) => self.keyword_operand("return", a.as_deref()),
+            Expr::Break(a) => self.keyword_operand("break", a.as_deref()),
+            Expr::Continue(a) => self.keyword_operand("continue", a.as_deref()),
+            Expr::Global(names) => {
+                let names = names.iter().map(|(name, _)| format!("${}", name)).collect::<Vec<_>>();
+                self.write(&format!("global {}", names.join(", ")));
+            }
+            Expr::Static(vars) => {
+                self.write("static ");
+                for (i, ((name, _), default)) in vars.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.write(&format!("${}", name));
+                    if let Some(default) = default {
+                        self.write(" = ");
+                        self.expr(default, Prec::Assign);
+                    }
+                }
+            }
+            Expr::Unset(args) => {
+                self.write("unset(");
+                self.list(args);
+                self.write(")");
+            }
+            Expr::Isset(args) => {
+                self.write("isset(");
+                self.list(args);
+                self.write(")");
+            }
+            Expr::Empty(a) => {
+                self.write("empty(");
+                self.expr(a, Prec::Assign);
+                self.write(")");
+            }
+            Expr::Declare(directives) => {
+                self.write("declare(");
+                for (i, ((name, _), value)) in directives.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.write(&format!("{} = ", name));
+                    self.expr(value, Prec::Assign);
+                }
+                self.write(")");
+            }
+            Expr::Goto((label, _)) => self.write(&format!("goto {}", label)),
+            Expr::Exit(a) => {
+                self.write("exit");
+                if let Some(a) = a {
+                    self.write("(");
+                    self.expr(a, Prec::Assign);
+                    self.write(")");
+                }
+            }
+            Expr::Include(kind, a) => {
+                self.write(match kind {
+                    IncludeKind::Include => "include ",
+                    IncludeKind::IncludeOnce => "include_once ",
+                    IncludeKind::Require => "require ",
+                    IncludeKind::RequireOnce => "require_once ",
+                });
+                self.expr(a, Prec::Assign);
+            }
+            Expr::HaltCompiler => self.write("__halt_compiler()"),
+            Expr::Match(subject, arms) => {
+                self.write("match (");
+                self.expr(subject, Prec::Assign);
+                self.write(") {");
+                self.indent += 1;
+                for MatchArm {
+                    conditions, body, ..
+                } in arms
+                {
+                    self.newline();
+                    match conditions {
+                        Some(conditions) => self.list(conditions),
+                        None => self.write("default"),
+                    }
+                    self.write(" => ");
+                    self.expr(body, Prec::Assign);
+                    self.write(",");
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            }
+            Expr::Closure(args, uses, ret, body) => {
+                self.write("function ");
+                self.params(args);
+                if !uses.is_empty() {
+                    let uses =
+                        uses.iter().map(|(name, _)| format!("${}", name)).collect::<Vec<_>>();
+                    self.write(&format!(" use ({})", uses.join(", ")));
+                }
+                self.ret(ret);
+                self.write(" ");
+                self.body(body);
+            }
+            Expr::ArrowFn(args, ret, body) => {
+                self.write("fn ");
+                self.params(args);
+                self.ret(ret);
+                self.write(" => ");
+                self.expr(body, Prec::Assign);
+            }
+            // Statements only appear in expressions when the AST was built by hand
+            Expr::Var(..)
+            | Expr::Then(..)
+            | Expr::If(..)
+            | Expr::While(..)
+            | Expr::For(..)
+            | Expr::Foreach(..)
+            | Expr::Switch(..)
+            | Expr::Label(_)
+            | Expr::InlineHtml(_) => self.stmts(expr),
+        }
+    }
+
+    fn keyword_operand(&mut self, keyword: &str, operand: Option<&Spanned<Expr>>) {
+        self.write(keyword);
+        if let Some(operand) = operand {
+            self.write(" ");
+            self.expr(operand, Prec::Assign);
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.write("null"),
+            Value::Bool(b) => self.write(if *b { "true" } else { "false" }),
+            Value::Num(n) => self.write(&n.to_string()),
+            // Strings are kept as written, in the quotes they were written in
+            Value::Str(quote, s) => {
+                let quote = quote.as_char();
+                self.write(&format!("{}{}{}", quote, s, quote))
+            }
+            Value::List(values) => {
+                self.write("[");
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.value(value);
+                }
+                self.write("]");
+            }
+            Value::Func(name) => self.write(name),
+        }
+    }
+}
+
+/// The precedence of the right operand of a left associative binary operator.
+fn next(prec: Prec) -> Prec {
+    match prec {
+        Prec::Assign => Prec::Comparison,
+        Prec::Comparison => Prec::Relational,
+        Prec::Relational => Prec::Concat,
+        Prec::Concat => Prec::Sum,
+        Prec::Sum => Prec::Product,
+        Prec::Product => Prec::Prefix,
+        Prec::Prefix => Prec::Postfix,
+        Prec::Postfix => Prec::Call,
+        Prec::Call | Prec::Atom => Prec::Atom,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parser;
+    use crate::version::PhpVersion;
+
+    #[test]
+    fn test_print_keeps_needed_parentheses_only() {
+        let src = "<?php echo ($a + $b) * $c, ($a - $b) - $c, $a - ($b - $c), - -1, (new A)->b(), !($a == $b);";
+        let ast = parser(src, PhpVersion::default()).ast.unwrap();
+
+        assert_eq!(
+            print(&ast),
+            "<?php\necho ($a + $b) * $c, $a - $b - $c, $a - ($b - $c), - -1, (new A)->b(), !($a == $b);\n"
+        );
+    }
+}
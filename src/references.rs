@@ -0,0 +1,203 @@
+//! References: the names in a file that refer to the same declaration as another name.
+//!
+//! The text is searched for the name, and for what it's imported as, and each match is resolved
+//! as the name under the cursor is, so that names that only read the same aren't references.
+
+use crate::chumsky::{Item, Span, UseKind};
+use crate::resolve::{last_segment, variable_spans, Declaration, MemberKind, Resolver, Target};
+use ropey::Rope;
+use std::ptr;
+
+/// The spans of the names in a file that refer to `target`, in source order, including the
+/// names of its declarations.
+pub fn references(items: &[Item], text: &Rope, resolver: &Resolver, target: &Target) -> Vec<Span> {
+    if let Target::Variable { name, scope } = target {
+        return variable_spans(items, name, *scope);
+    }
+    let declared = resolver.find(target);
+    let source = text.to_string().to_ascii_lowercase();
+
+    let mut spans: Vec<Span> = Vec::new();
+    for name in names(items, target) {
+        let name = name.to_ascii_lowercase();
+        for (at, _) in source.match_indices(&name) {
+            let before = source[..at].chars().next_back();
+            let after = source[at + name.len()..].chars().next();
+            if before.is_some_and(is_name_char) || after.is_some_and(is_name_char) {
+                continue;
+            }
+            let Some(reference) = resolver.reference_at(items, text.byte_to_char(at)) else {
+                continue;
+            };
+            if !spans.contains(&reference.span)
+                && refers_to(resolver, target, &declared, &reference.target)
+            {
+                spans.push(reference.span);
+            }
+        }
+    }
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The names `target` could be written as in a file: its own, and the aliases it's imported as.
+fn names(items: &[Item], target: &Target) -> Vec<String> {
+    let (kind, qualified): (_, &[String]) = match target {
+        Target::Class(name) => (UseKind::Class, std::slice::from_ref(name)),
+        Target::Function(names) => (UseKind::Function, names),
+        Target::Constant(names) => (UseKind::Const, names),
+        Target::Member { name, .. } | Target::Variable { name, .. } => {
+            return vec![name.to_string()]
+        }
+    };
+    let mut names = vec![last_segment(&qualified[0]).to_string()];
+    let items = items.iter().flat_map(|item| match item {
+        Item::Namespace(namespace) => namespace.items.as_slice(),
+        item => std::slice::from_ref(item),
+    });
+    for item in items {
+        let Item::Use(use_) = item else {
+            continue;
+        };
+        // Classes are imported with the namespaces they're in, as in `use App\Models;`
+        let imported = qualified.iter().any(|name| {
+            name.eq_ignore_ascii_case(&use_.name.0)
+                || name
+                    .to_ascii_lowercase()
+                    .starts_with(&format!("{}\\", use_.name.0.to_ascii_lowercase()))
+        });
+        if let (true, Some((alias, _))) = (
+            imported && (use_.kind == kind || use_.kind == UseKind::Class),
+            &use_.alias,
+        ) {
+            names.push(alias.to_string());
+        }
+    }
+    names
+}
+
+/// Whether a name resolved as `other` refers to what `target` does, which is what `declared`
+/// declares. Names that aren't declared anywhere known are compared by how they resolved.
+fn refers_to(
+    resolver: &Resolver,
+    target: &Target,
+    declared: &[&Declaration],
+    other: &Target,
+) -> bool {
+    let same_kind = match (target, other) {
+        (Target::Function(_), Target::Function(_))
+        | (Target::Class(_), Target::Class(_))
+        | (Target::Constant(_), Target::Constant(_)) => true,
+        (Target::Member { kind, .. }, Target::Member { kind: other, .. }) => kind == other,
+        _ => false,
+    };
+    if !same_kind {
+        return false;
+    }
+    if !declared.is_empty() {
+        let found = resolver.find(other);
+        return found
+            .iter()
+            .any(|found| declared.iter().any(|declared| ptr::eq(*found, *declared)));
+    }
+    match (target, other) {
+        // An unknown function or constant is the global one, which is the last tried
+        (Target::Function(names), Target::Function(others)) => {
+            names.last().zip(others.last()).is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+        }
+        (Target::Constant(names), Target::Constant(others)) => names.last() == others.last(),
+        (Target::Class(name), Target::Class(other)) => name.eq_ignore_ascii_case(other),
+        (
+            Target::Member { class, kind, name },
+            Target::Member {
+                class: other_class,
+                name: other,
+                ..
+            },
+        ) => {
+            let same_class = match (class, other_class) {
+                (Some(class), Some(other)) => class.eq_ignore_ascii_case(other),
+                _ => true,
+            };
+            let same_name = match kind {
+                MemberKind::Method => name.eq_ignore_ascii_case(other),
+                _ => name == other,
+            };
+            same_class && same_name
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn test_references_resolve_through_imports_and_types() {
+        let models = Document::new(
+            "<?php\nnamespace App\\Models;\n\nclass User { public function save() {} }\nclass Post { public function save() {} }\n",
+        );
+        let src = r#"<?php
+namespace App;
+
+use App\Models\User as Account;
+use App\Models\Post;
+
+function save(Account $user, Post $post) {
+    $user->save();
+    $post->save();
+    // save the User
+    return new Account();
+}
+save(new Account(), new Post());
+"#;
+        let main = Document::new(src);
+        let (items, text) = (main.items().unwrap(), main.text());
+        let (models_declarations, main_declarations) = (models.declarations(), main.declarations());
+        let resolver = Resolver::new([
+            ("file:///models.php", models_declarations.as_slice()),
+            ("file:///main.php", main_declarations.as_slice()),
+        ]);
+        let at = |needle: &str| {
+            let offset = text.byte_to_char(src.find(needle).unwrap());
+            let target = resolver.reference_at(items, offset).unwrap().target;
+            let spans = references(items, text, &resolver, &target);
+            spans.into_iter().map(|span| text.slice(span).to_string()).collect::<Vec<_>>()
+        };
+
+        // The class is imported under an alias, which its references in this file are written as
+        assert_eq!(
+            at("Account $user"),
+            [
+                "App\\Models\\User",
+                "Account",
+                "Account",
+                "Account",
+                "Account"
+            ]
+        );
+        // The method of the user is told apart from the method of the post and the function
+        assert_eq!(at("save();").len(), 1);
+        assert_eq!(at("save(Account"), ["save", "save"]);
+        assert_eq!(at("$post->"), ["$post", "$post"]);
+
+        let save = resolver.reference_at(items, text.byte_to_char(src.find("save();").unwrap()));
+        let in_models = references(
+            models.items().unwrap(),
+            models.text(),
+            &resolver,
+            &save.unwrap().target,
+        );
+        assert_eq!(in_models.len(), 1);
+        assert_eq!(
+            in_models[0].start,
+            models.text().to_string().find("save").unwrap()
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Renames: what can be renamed, and the edits that rename it where it's declared and referred
+//! to, which for a variable includes where it's interpolated into strings.
+
+use crate::builtins;
+use crate::chumsky::{Item, Span, Token};
+use crate::resolve::{last_segment, Context, Resolver, Target};
+use crate::workspace;
+use ropey::Rope;
+
+/// Whether `name` is a valid name for a variable, function, class, constant or member.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Why `target` can't be renamed, if it can't: built-ins, and what's declared in `vendor/`,
+/// belong to others, and names declared nowhere known can't be renamed everywhere they're used.
+/// Neither can a member accessed on something of an unknown type, which could be any class's.
+pub fn refusal(resolver: &Resolver, target: &Target) -> Option<String> {
+    let (builtin, name) = match target {
+        Target::Variable { name, .. } if *name == "this" => {
+            return Some("`$this` can't be renamed".to_string())
+        }
+        Target::Variable { .. } => return None,
+        Target::Function(names) => (
+            names.iter().any(|name| builtins::is_function(name)),
+            names.last(),
+        ),
+        Target::Class(name) => (
+            builtins::is_class(name) || builtins::is_type(name),
+            Some(name),
+        ),
+        Target::Constant(names) => (
+            names.iter().any(|name| builtins::is_constant(name)),
+            names.last(),
+        ),
+        Target::Member { class: None, .. } => {
+            return Some("the type of what it's accessed on isn't known".to_string())
+        }
+        Target::Member { .. } => (false, None),
+    };
+    let declarations = resolver.locate(target);
+    if let Some((uri, _)) = declarations.iter().find(|(uri, _)| workspace::is_vendored(uri)) {
+        return Some(format!("it's declared in a dependency, at {}", uri));
+    }
+    match (declarations.is_empty(), name) {
+        (true, Some(name)) if builtin => {
+            Some(format!("`{}` is built into PHP", last_segment(name)))
+        }
+        (true, _) => Some("its declaration wasn't found".to_string()),
+        (false, _) => None,
+    }
+}
+
+/// The part of a reference's span that is the name itself, without the qualifier of a
+/// qualified name and the `$` of a variable.
+pub fn name_span(text: &Rope, span: &Span) -> Span {
+    let source = text.slice(span.clone()).to_string();
+    let start = source.rfind('\\').map_or(0, |at| at + 1);
+    let start = start + usize::from(source[start..].starts_with('$'));
+    span.start + source[..start].chars().count()..span.end
+}
+
+/// The edits that rename the names at `spans` from `old` to `new`. Names that are written
+/// otherwise, like the alias a class is imported as or `self`, are left as they are.
+pub fn edits(text: &Rope, spans: &[Span], old: &str, new: &str) -> Vec<(Span, String)> {
+    spans
+        .iter()
+        .filter_map(|span| {
+            let name = name_span(text, span);
+            let written = text.slice(name.clone()).to_string();
+            written.eq_ignore_ascii_case(old).then(|| (name, new.to_string()))
+        })
+        .collect()
+}
+
+/// The spans of the variable `name` of the `scope` interpolated into double-quoted strings, as
+/// in `"Hi, $name"` or `"Hi, {$name}"`.
+pub fn interpolated(
+    items: &[Item],
+    tokens: &[(Token, Span)],
+    text: &Rope,
+    name: &str,
+    scope: Option<usize>,
+) -> Vec<Span> {
+    let mut spans = Vec::new();
+    for (_, span) in tokens.iter().filter(|(token, _)| matches!(token, Token::Str(..))) {
+        let source = text.slice(span.clone()).to_string();
+        if !source.starts_with('"') {
+            continue;
+        }
+        let found = source.match_indices('$').filter_map(|(at, _)| {
+            let rest = &source[at + 1..];
+            let escaped = source[..at].ends_with('\\');
+            let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+            (!escaped && &rest[..len] == name).then_some(at)
+        });
+        for at in found.collect::<Vec<_>>() {
+            let start = span.start + source[..at].chars().count();
+            if Context::at(items, start).variable_scope(name) == scope {
+                spans.push(start..start + 1 + name.chars().count());
+            }
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::resolve::variable_spans;
+
+    #[test]
+    fn test_variables_are_renamed_in_strings_and_closures() {
+        let src = r#"<?php
+function greet($name) {
+    $f = function () use ($name) { return "Hi, $name and {$name}s, not \$name or $names"; };
+    return strlen($name);
+}
+$name = "$name";
+"#;
+        let document = Document::new(src);
+        let (items, text) = (document.items().unwrap(), document.text());
+        let declarations = document.declarations();
+        let resolver = Resolver::new([("file:///greet.php", declarations.as_slice())]);
+
+        let offset = text.byte_to_char(src.find("$name").unwrap());
+        let target = resolver.reference_at(items, offset).unwrap().target;
+        assert_eq!(refusal(&resolver, &target), None);
+        let Target::Variable { name, scope } = target else {
+            panic!("not a variable");
+        };
+        let mut spans = variable_spans(items, &name, scope);
+        spans.extend(interpolated(items, document.tokens(), text, &name, scope));
+        let edits = edits(text, &spans, "name", "who");
+        assert_eq!(edits.len(), 5);
+
+        let mut renamed = text.clone();
+        let mut edits = edits;
+        edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, new) in edits {
+            renamed.remove(span.clone());
+            renamed.insert(span.start, &new);
+        }
+        assert_eq!(
+            renamed.to_string(),
+            src.replacen("$name", "$who", 3).replacen("{$name}", "{$who}", 1).replacen(
+                "strlen($name)",
+                "strlen($who)",
+                1
+            )
+        );
+
+        // Built-ins belong to PHP
+        let strlen = text.byte_to_char(src.find("strlen").unwrap());
+        let strlen = resolver.reference_at(items, strlen).unwrap().target;
+        assert!(refusal(&resolver, &strlen).unwrap().contains("built into PHP"));
+
+        // A method of whatever a parameter without a type is could be any class's
+        let src = "<?php\nclass A { function save() {} }\nclass B { function save() {} }\nfunction f($x) { $x->save(); }\n(new B())->save();\n";
+        let document = Document::new(src);
+        let (items, text) = (document.items().unwrap(), document.text());
+        let declarations = document.declarations();
+        let resolver = Resolver::new([("file:///save.php", declarations.as_slice())]);
+        let at = |needle: &str| {
+            let offset = text.byte_to_char(src.find(needle).unwrap() + needle.len() - 4);
+            resolver.reference_at(items, offset).unwrap().target
+        };
+        assert!(refusal(&resolver, &at("$x->save")).unwrap().contains("isn't known"));
+        assert_eq!(refusal(&resolver, &at("(new B())->save")), None);
+        assert!(is_valid_name("_wer2") && !is_valid_name("2x") && !is_valid_name("a-b"));
+    }
+}
@@ -0,0 +1,1330 @@
+//! Name resolution: what a file declares, what the name under the cursor refers to, and the
+//! types of expressions, inferred from what they're assigned, declared or documented as.
+//!
+//! Names are resolved as PHP does, against the namespace they're in and its `use` imports, and
+//! looked up among the declarations of one or more files.
+
+use crate::builtins;
+use crate::chumsky::{
+    Class, ClassKind, Expr, Func, Item, Member, Method, Modifier, Namespace, Param, PropertyHook,
+    Span, Spanned, Type, UseKind, Value,
+};
+use crate::phpdoc;
+use crate::symbol::Symbol;
+use crate::visitor::{
+    node_at, walk_expr, walk_func, walk_items, walk_method, walk_param, walk_property_hook, Node,
+    Visitor,
+};
+use ropey::Rope;
+
+/// The namespace that `offset` is in. A namespace without braces ends where its last item does,
+/// but goes on up to the next one, so that's where each of them is taken to end.
+pub fn namespace_at(items: &[Item], offset: usize) -> Option<&Namespace> {
+    items.iter().rev().find_map(|item| match item {
+        Item::Namespace(namespace) if namespace.span.start <= offset => Some(namespace),
+        _ => None,
+    })
+}
+
+/// The namespace and imports that the names in part of a file are resolved against.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    pub namespace: Option<String>,
+    /// The imports, as their kind, the name they're imported as and the fully qualified name
+    uses: Vec<(UseKind, String, String)>,
+}
+
+impl Scope {
+    /// The scope of the namespace that `offset` is in.
+    pub fn at(items: &[Item], offset: usize) -> Self {
+        Scope::of(namespace_at(items, offset), items)
+    }
+
+    /// The scope of a `namespace`, or of the global code of a file without namespaces.
+    fn of(namespace: Option<&Namespace>, items: &[Item]) -> Self {
+        let items = namespace.map_or(items, |namespace| &namespace.items);
+        let uses = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Use(use_) => {
+                    let alias = use_.alias.as_ref().map_or_else(
+                        || last_segment(&use_.name.0).to_string(),
+                        |alias| alias.0.to_string(),
+                    );
+                    Some((use_.kind, alias, use_.name.0.to_string()))
+                }
+                _ => None,
+            })
+            .collect();
+        Scope {
+            namespace: namespace
+                .and_then(|namespace| namespace.name.as_ref())
+                .map(|name| name.0.to_string()),
+            uses,
+        }
+    }
+
+    /// The fully qualified name of something declared as `name` in this scope.
+    pub fn qualify(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}\\{}", namespace, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// The fully qualified name of the class `name` refers to. The names of types that aren't
+    /// classes, like `int` or `self`, are kept as they are.
+    pub fn class(&self, name: &str) -> String {
+        if let Some(name) = name.strip_prefix('\\') {
+            return name.to_string();
+        }
+        if builtins::is_type(name) {
+            return name.to_string();
+        }
+        self.imported(UseKind::Class, name, false).unwrap_or_else(|| self.qualify(name))
+    }
+
+    /// The names a function call of `name` could refer to, in the order PHP tries them: an
+    /// unqualified name falls back to a global function.
+    pub fn function(&self, name: &str) -> Vec<String> {
+        self.function_or_constant(UseKind::Function, name, false)
+    }
+
+    pub fn constant(&self, name: &str) -> Vec<String> {
+        self.function_or_constant(UseKind::Const, name, true)
+    }
+
+    fn function_or_constant(&self, kind: UseKind, name: &str, case_sensitive: bool) -> Vec<String> {
+        if let Some(name) = name.strip_prefix('\\') {
+            return vec![name.to_string()];
+        }
+        if name.contains('\\') {
+            return vec![self
+                .imported(UseKind::Class, name, false)
+                .unwrap_or_else(|| self.qualify(name))];
+        }
+        match self.imported(kind, name, case_sensitive) {
+            Some(name) => vec![name],
+            None if self.namespace.is_some() => vec![self.qualify(name), name.to_string()],
+            None => vec![name.to_string()],
+        }
+    }
+
+    /// The fully qualified name of `name` when its first segment is imported as a `kind`.
+    fn imported(&self, kind: UseKind, name: &str, case_sensitive: bool) -> Option<String> {
+        let (first, rest) = match name.split_once('\\') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        let (_, _, imported) = self.uses.iter().find(|(use_kind, alias, _)| {
+            *use_kind == kind
+                && match case_sensitive {
+                    true => alias == first,
+                    false => alias.eq_ignore_ascii_case(first),
+                }
+        })?;
+        Some(match rest {
+            Some(rest) => format!("{}\\{}", imported, rest),
+            None => imported.clone(),
+        })
+    }
+
+    /// The imports of this scope, as their kind, alias and fully qualified name.
+    pub fn uses(&self) -> impl Iterator<Item = (UseKind, &str, &str)> {
+        self.uses.iter().map(|(kind, alias, name)| (*kind, alias.as_str(), name.as_str()))
+    }
+}
+
+/// The last segment of a qualified name, as in `User` of `App\Models\User`.
+pub fn last_segment(name: &str) -> &str {
+    name.rsplit('\\').next().unwrap_or(name)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Function,
+    Class(ClassKind),
+    Constant,
+    Method,
+    Property,
+    ClassConstant,
+    EnumCase,
+}
+
+/// A function, class, constant or member that a file declares.
+#[derive(Clone, Debug)]
+pub struct Declaration {
+    pub kind: DeclarationKind,
+    /// The fully qualified name, as in `App\Models\User`, or the name of a member as declared,
+    /// without the `$` of a property
+    pub name: String,
+    /// The fully qualified name of the class of a member
+    pub class: Option<String>,
+    /// The type of a property, or what a function or method returns, as declared or documented
+    pub ty: Option<String>,
+    /// The classes, interfaces and traits that a class extends, implements or uses
+    pub parents: Vec<String>,
+    pub modifiers: Vec<Modifier>,
+    pub name_span: Span,
+    pub span: Span,
+    /// Where the body of a function, method or class starts, which its signature ends before
+    pub body: Option<usize>,
+    /// The declaration as written, up to its body, with its whitespace collapsed
+    pub signature: String,
+    /// The doc comment right before it
+    pub doc: Option<String>,
+}
+
+impl Declaration {
+    pub fn is_member(&self) -> bool {
+        self.class.is_some()
+    }
+
+    /// The name it's known by where it's used: the last segment of a qualified name.
+    pub fn short_name(&self) -> &str {
+        last_segment(&self.name)
+    }
+}
+
+/// Every declaration of a file, classes before their members.
+pub fn declarations(items: &[Item], text: &Rope) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    declare(items, &Scope::of(None, items), text, &mut declarations);
+    for declaration in &mut declarations {
+        declaration.signature = signature(declaration, text);
+        declaration.doc = phpdoc::doc_comment(text, declaration.span.start);
+    }
+    declarations
+}
+
+/// The source of a declaration up to its body, or up to the `{` of a class.
+fn signature(declaration: &Declaration, text: &Rope) -> String {
+    let span = &declaration.span;
+    let end = match (declaration.body, declaration.kind) {
+        (Some(body), _) => body,
+        (None, DeclarationKind::Class(_)) => text
+            .slice(declaration.name_span.end..span.end)
+            .chars()
+            .position(|c| c == '{')
+            .map_or(span.end, |at| declaration.name_span.end + at),
+        (None, _) => span.end,
+    };
+    let source = text.slice(span.start..end.clamp(span.start, text.len_chars())).to_string();
+    let signature = source.split_whitespace().collect::<Vec<_>>().join(" ");
+    signature.trim_end_matches(['{', ';', ',']).trim_end().to_string()
+}
+
+fn declare(items: &[Item], scope: &Scope, text: &Rope, declarations: &mut Vec<Declaration>) {
+    for item in items {
+        match item {
+            Item::Func(func) => {
+                let doc = doc(text, func.span.start);
+                declarations.push(Declaration {
+                    kind: DeclarationKind::Function,
+                    name: scope.qualify(&func.name.0),
+                    class: None,
+                    ty: declared_type(func.ret.as_ref(), doc.as_ref(), "return", scope, None),
+                    parents: Vec::new(),
+                    modifiers: Vec::new(),
+                    name_span: func.name.1.clone(),
+                    span: func.span.clone(),
+                    body: Some(func.body.1.start),
+                    signature: String::new(),
+                    doc: None,
+                })
+            }
+            Item::Class(class) => declare_class(class, scope, text, declarations),
+            Item::Const(constant) => declarations.push(Declaration {
+                kind: DeclarationKind::Constant,
+                name: scope.qualify(&constant.name.0),
+                class: None,
+                ty: None,
+                parents: Vec::new(),
+                modifiers: Vec::new(),
+                name_span: constant.name.1.clone(),
+                span: constant.span.clone(),
+                body: None,
+                signature: String::new(),
+                doc: None,
+            }),
+            Item::Namespace(namespace) => declare(
+                &namespace.items,
+                &Scope::of(Some(namespace), items),
+                text,
+                declarations,
+            ),
+            Item::Use(_) | Item::Stmt(_) => {}
+        }
+    }
+}
+
+fn declare_class(class: &Class, scope: &Scope, text: &Rope, declarations: &mut Vec<Declaration>) {
+    let name = scope.qualify(&class.name.0);
+    let traits = class.members.iter().filter_map(|member| match member {
+        Member::TraitUse((name, _)) => Some(name),
+        _ => None,
+    });
+    declarations.push(Declaration {
+        kind: DeclarationKind::Class(class.kind),
+        name: name.clone(),
+        class: None,
+        ty: None,
+        parents: class
+            .extends
+            .iter()
+            .chain(&class.implements)
+            .map(|(name, _)| name)
+            .chain(traits)
+            .map(|name| scope.class(name))
+            .collect(),
+        modifiers: class.modifiers.iter().map(|(modifier, _)| *modifier).collect(),
+        name_span: class.name.1.clone(),
+        span: class.span.clone(),
+        body: None,
+        signature: String::new(),
+        doc: None,
+    });
+
+    let member =
+        |kind, (member, span): &Spanned<Symbol>, modifiers: &[Spanned<Modifier>], ty| Declaration {
+            kind,
+            name: member.to_string(),
+            class: Some(name.clone()),
+            ty,
+            parents: Vec::new(),
+            modifiers: modifiers.iter().map(|(modifier, _)| *modifier).collect(),
+            name_span: span.clone(),
+            span: 0..0,
+            body: None,
+            signature: String::new(),
+            doc: None,
+        };
+    for item in &class.members {
+        let declaration = match item {
+            Member::Method(method) => {
+                let doc = doc(text, method.span.start);
+                let ty = declared_type(
+                    method.ret.as_ref(),
+                    doc.as_ref(),
+                    "return",
+                    scope,
+                    Some(&name),
+                );
+                Declaration {
+                    span: method.span.clone(),
+                    body: method.body.as_ref().map(|(_, span)| span.start),
+                    ..member(DeclarationKind::Method, &method.name, &method.modifiers, ty)
+                }
+            }
+            Member::Property(property) => {
+                let doc = doc(text, property.span.start);
+                let ty = declared_type(
+                    property.ty.as_ref(),
+                    doc.as_ref(),
+                    "var",
+                    scope,
+                    Some(&name),
+                );
+                Declaration {
+                    span: property.span.clone(),
+                    ..member(
+                        DeclarationKind::Property,
+                        &property.name,
+                        &property.modifiers,
+                        ty,
+                    )
+                }
+            }
+            Member::Const(constant) => Declaration {
+                span: constant.span.clone(),
+                ..member(
+                    DeclarationKind::ClassConstant,
+                    &constant.name,
+                    &constant.modifiers,
+                    None,
+                )
+            },
+            Member::Case(case) => Declaration {
+                span: case.span.clone(),
+                ..member(
+                    DeclarationKind::EnumCase,
+                    &case.name,
+                    &[],
+                    Some(name.clone()),
+                )
+            },
+            Member::TraitUse(_) => continue,
+        };
+        declarations.push(declaration);
+    }
+
+    // Promoted constructor parameters are properties too
+    for item in &class.members {
+        let Member::Method(method) = item else {
+            continue;
+        };
+        for param in method.args.iter().filter(|param| is_promoted(param)) {
+            let ty = param.ty.as_ref().map(|(ty, _)| type_name(ty, scope, Some(&name)));
+            declarations.push(Declaration {
+                span: param.span.clone(),
+                ..member(DeclarationKind::Property, &param.name, &param.modifiers, ty)
+            });
+        }
+    }
+}
+
+/// Whether a constructor parameter is a property too, as in `private int $id`.
+pub fn is_promoted(param: &Param) -> bool {
+    param.modifiers.iter().any(|(modifier, _)| {
+        matches!(
+            modifier,
+            Modifier::Public | Modifier::Protected | Modifier::Private | Modifier::Readonly
+        )
+    })
+}
+
+fn doc(text: &Rope, start: usize) -> Option<phpdoc::DocBlock> {
+    phpdoc::doc_comment(text, start).map(|comment| phpdoc::parse(&comment))
+}
+
+/// The declared type, or else the type of the doc comment's `tag`.
+fn declared_type(
+    ty: Option<&Spanned<Type>>,
+    doc: Option<&phpdoc::DocBlock>,
+    tag: &str,
+    scope: &Scope,
+    class: Option<&str>,
+) -> Option<String> {
+    match ty {
+        Some((ty, _)) => Some(type_name(ty, scope, class)),
+        None => {
+            let documented = doc?.tag(tag)?.ty.as_deref()?;
+            Some(doc_type_name(documented, scope, class))
+        }
+    }
+}
+
+/// A type as written, with its class names fully qualified and `self` and `static` as the
+/// `class` they're in.
+pub fn type_name(ty: &Type, scope: &Scope, class: Option<&str>) -> String {
+    match ty {
+        Type::Named((name, _)) => class_name(name, scope, class),
+        Type::Nullable(ty) => format!("?{}", type_name(ty, scope, class)),
+        Type::Union(types) => {
+            let types = types.iter().map(|ty| match ty {
+                Type::Intersection(_) => format!("({})", type_name(ty, scope, class)),
+                ty => type_name(ty, scope, class),
+            });
+            types.collect::<Vec<_>>().join("|")
+        }
+        Type::Intersection(types) => {
+            types.iter().map(|ty| type_name(ty, scope, class)).collect::<Vec<_>>().join("&")
+        }
+    }
+}
+
+fn class_name(name: &str, scope: &Scope, class: Option<&str>) -> String {
+    match class {
+        Some(class) if name.eq_ignore_ascii_case("self") || name.eq_ignore_ascii_case("static") => {
+            class.to_string()
+        }
+        _ => scope.class(name),
+    }
+}
+
+/// A documented type, like `Foo[]|null`, with its class names resolved as in [`type_name`].
+fn doc_type_name(ty: &str, scope: &Scope, class: Option<&str>) -> String {
+    let types = ty.split('|').map(|ty| {
+        let (ty, nullable) = match ty.strip_prefix('?') {
+            Some(ty) => (ty, "?"),
+            None => (ty, ""),
+        };
+        let (name, array) = match ty.strip_suffix("[]") {
+            Some(name) => (name, "[]"),
+            None => (ty, ""),
+        };
+        // Generic types, like `array<int, Foo>`, are kept as they are
+        match name.contains(['<', '{', '(']) {
+            true => format!("{}{}", nullable, ty),
+            false => format!("{}{}{}", nullable, class_name(name, scope, class), array),
+        }
+    });
+    types.collect::<Vec<_>>().join("|")
+}
+
+/// The class that a value of the type `ty` is an object of, if it's one class.
+pub fn class_of(ty: &str) -> Option<&str> {
+    ty.split('|')
+        .map(|ty| ty.trim_start_matches('?').trim_matches(['(', ')']))
+        .find(|ty| !builtins::is_type(ty) && !ty.ends_with("[]") && !ty.contains('<'))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberKind {
+    Method,
+    Property,
+    /// A class constant or an enum case
+    Constant,
+}
+
+/// What a name refers to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// The names that a function call could refer to, in the order they're tried
+    Function(Vec<String>),
+    Class(String),
+    Constant(Vec<String>),
+    /// A member of a class, which is `None` when the type of what it's accessed on is unknown
+    Member {
+        class: Option<String>,
+        kind: MemberKind,
+        name: Symbol,
+    },
+    /// A variable of the function, method or closure that starts at the `scope` offset, or of
+    /// the global scope
+    Variable {
+        name: Symbol,
+        scope: Option<usize>,
+    },
+}
+
+/// A name in the source and what it refers to.
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub target: Target,
+    pub span: Span,
+}
+
+/// What the nodes around an offset say about the names there.
+pub struct Context<'ast> {
+    /// The nodes containing the offset, from the outermost to the innermost
+    pub chain: Vec<Node<'ast>>,
+    pub scope: Scope,
+    /// The class that the offset is in, and the class it extends
+    pub class: Option<String>,
+    pub parent: Option<String>,
+}
+
+impl<'ast> Context<'ast> {
+    pub fn at(items: &'ast [Item], offset: usize) -> Self {
+        let chain = node_at(items, offset);
+        let scope = Scope::at(items, offset);
+        let class = chain.iter().rev().find_map(|node| match node {
+            Node::Class(class) => Some(*class),
+            _ => None,
+        });
+        Context {
+            class: class.map(|class| scope.qualify(&class.name.0)),
+            parent: class
+                .and_then(|class| class.extends.first())
+                .map(|(name, _)| scope.class(name)),
+            chain,
+            scope,
+        }
+    }
+
+    /// The class a name of a class refers to, where `self`, `static` and `parent` refer to the
+    /// classes around.
+    pub fn class(&self, name: &str) -> Option<String> {
+        if name.eq_ignore_ascii_case("self") || name.eq_ignore_ascii_case("static") {
+            self.class.clone()
+        } else if name.eq_ignore_ascii_case("parent") {
+            self.parent.clone()
+        } else {
+            Some(self.scope.class(name))
+        }
+    }
+
+    /// Where the function, method or closure that the variable `name` at the offset belongs to
+    /// starts, or `None` in the global scope. Arrow functions see the variables around them, and
+    /// closures the ones they capture.
+    pub fn variable_scope(&self, name: &str) -> Option<usize> {
+        self.chain.iter().rev().find_map(|node| match node {
+            Node::Func(func) => Some(func.span.start),
+            Node::Method(method) => Some(method.span.start),
+            Node::PropertyHook(hook) => Some(hook.span.start),
+            Node::Expr((Expr::Closure(_, captured, ..), _))
+                if captured.iter().any(|(captured, _)| *captured == name) =>
+            {
+                None
+            }
+            Node::Expr((Expr::Closure(..), span)) => Some(span.start),
+            _ => None,
+        })
+    }
+}
+
+/// Looks names up among the declarations of files, and infers the types of expressions.
+pub struct Resolver<'a> {
+    /// The declarations of every file, with the URI of the file
+    pub declarations: Vec<(&'a str, &'a Declaration)>,
+}
+
+/// How deep type inference follows variables and calls, which could be cyclic.
+const MAX_DEPTH: usize = 8;
+
+impl<'a> Resolver<'a> {
+    /// A resolver of the names declared in `files`, as their URIs and declarations.
+    pub fn new(files: impl IntoIterator<Item = (&'a str, &'a [Declaration])>) -> Self {
+        let declarations = files
+            .into_iter()
+            .flat_map(|(uri, declarations)| declarations.iter().map(move |d| (uri, d)));
+        Resolver {
+            declarations: declarations.collect(),
+        }
+    }
+
+    /// The declarations that `target` refers to. A member is looked up in its class and then in
+    /// what the class extends, implements and uses.
+    pub fn find(&self, target: &Target) -> Vec<&'a Declaration> {
+        let found = self.locate(target).into_iter();
+        found.map(|(_, declaration)| declaration).collect()
+    }
+
+    /// The declarations that `target` refers to, with the URIs of their files.
+    pub fn locate(&self, target: &Target) -> Vec<(&'a str, &'a Declaration)> {
+        let named = |kind: fn(DeclarationKind) -> bool, names: &[String], case_sensitive: bool| {
+            names
+                .iter()
+                .map(|name| {
+                    self.declarations
+                        .iter()
+                        .copied()
+                        .filter(|(_, declaration)| {
+                            kind(declaration.kind)
+                                && match case_sensitive {
+                                    true => declaration.name == *name,
+                                    false => declaration.name.eq_ignore_ascii_case(name),
+                                }
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .find(|found| !found.is_empty())
+                .unwrap_or_default()
+        };
+        match target {
+            Target::Function(names) => {
+                named(|kind| kind == DeclarationKind::Function, names, false)
+            }
+            Target::Class(name) => named(
+                |kind| matches!(kind, DeclarationKind::Class(_)),
+                std::slice::from_ref(name),
+                false,
+            ),
+            Target::Constant(names) => named(|kind| kind == DeclarationKind::Constant, names, true),
+            Target::Member {
+                class: Some(class),
+                kind,
+                name,
+            } => self.member(class, *kind, name, &mut Vec::new()),
+            Target::Member {
+                class: None,
+                kind,
+                name,
+            } => self
+                .declarations
+                .iter()
+                .copied()
+                .filter(|(_, declaration)| is_member(declaration, *kind, name))
+                .collect(),
+            Target::Variable { .. } => Vec::new(),
+        }
+    }
+
+    fn member(
+        &self,
+        class: &str,
+        kind: MemberKind,
+        name: &str,
+        seen: &mut Vec<String>,
+    ) -> Vec<(&'a str, &'a Declaration)> {
+        if seen.iter().any(|seen| seen.eq_ignore_ascii_case(class)) {
+            return Vec::new();
+        }
+        seen.push(class.to_string());
+
+        let found = self
+            .declarations
+            .iter()
+            .copied()
+            .filter(|(_, declaration)| {
+                declaration.class.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(class))
+                    && is_member(declaration, kind, name)
+            })
+            .collect::<Vec<_>>();
+        if !found.is_empty() {
+            return found;
+        }
+        self.parents(class)
+            .into_iter()
+            .map(|parent| self.member(&parent, kind, name, seen))
+            .find(|found| !found.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// What the class `class` extends, implements and uses.
+    pub fn parents(&self, class: &str) -> Vec<String> {
+        self.declarations
+            .iter()
+            .filter(|(_, declaration)| {
+                matches!(declaration.kind, DeclarationKind::Class(_))
+                    && declaration.name.eq_ignore_ascii_case(class)
+            })
+            .flat_map(|(_, declaration)| declaration.parents.clone())
+            .collect()
+    }
+
+    /// The members of the class `class`, its own and then those it inherits and uses, except
+    /// the ones overridden. Each is paired with the class it's a member of, which for the members
+    /// of a trait is the class that uses the trait.
+    pub fn members(&self, class: &str) -> Vec<(String, &'a Declaration)> {
+        let mut members = Vec::new();
+        self.collect_members(class, class, &mut Vec::new(), &mut members);
+        members
+    }
+
+    fn collect_members(
+        &self,
+        class: &str,
+        owner: &str,
+        seen: &mut Vec<String>,
+        members: &mut Vec<(String, &'a Declaration)>,
+    ) {
+        if seen.iter().any(|seen| seen.eq_ignore_ascii_case(class)) {
+            return;
+        }
+        seen.push(class.to_string());
+
+        let is_trait = self.declarations.iter().any(|(_, declaration)| {
+            declaration.kind == DeclarationKind::Class(ClassKind::Trait)
+                && declaration.name.eq_ignore_ascii_case(class)
+        });
+        let owner = if is_trait { owner } else { class };
+        for (_, declaration) in &self.declarations {
+            if !declaration.class.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(class)) {
+                continue;
+            }
+            let overridden = member_kind(declaration.kind).is_some_and(|kind| {
+                members.iter().any(|(_, member)| is_member(member, kind, &declaration.name))
+            });
+            if !overridden {
+                members.push((owner.to_string(), *declaration));
+            }
+        }
+        for parent in self.parents(class) {
+            self.collect_members(&parent, owner, seen, members);
+        }
+    }
+
+    /// Whether the class `class` is `ancestor`, or extends, implements or uses it.
+    pub fn is_a(&self, class: &str, ancestor: &str) -> bool {
+        let mut pending = vec![class.to_string()];
+        let mut seen: Vec<String> = Vec::new();
+        while let Some(class) = pending.pop() {
+            if class.eq_ignore_ascii_case(ancestor) {
+                return true;
+            }
+            if !seen.iter().any(|seen| seen.eq_ignore_ascii_case(&class)) {
+                pending.extend(self.parents(&class));
+                seen.push(class);
+            }
+        }
+        false
+    }
+
+    /// The type of the variable `name` at `offset`, from the assignment to it or the parameter
+    /// it is that's closest before.
+    pub fn variable_type(&self, context: &Context, name: &str, offset: usize) -> Option<String> {
+        self.variable_type_at(context, name, offset, 0)
+    }
+
+    fn variable_type_at(
+        &self,
+        context: &Context,
+        name: &str,
+        offset: usize,
+        depth: usize,
+    ) -> Option<String> {
+        if name == "this" {
+            return context.class.clone();
+        }
+        for node in context.chain.iter().rev() {
+            let params: &[Param] = match node {
+                Node::Expr((Expr::Var((var, _), value, rest), _))
+                    if *var == name && rest.1.start <= offset =>
+                {
+                    return self.type_at(context, value, depth + 1);
+                }
+                Node::Expr((Expr::Foreach(subject, _, value, body), _))
+                    if matches!(&value.0, Expr::Local((var, _)) if *var == name)
+                        && body.1.contains(&offset) =>
+                {
+                    let ty = self.type_at(context, subject, depth + 1)?;
+                    return ty.strip_suffix("[]").map(str::to_string);
+                }
+                Node::Func(func) => &func.args,
+                Node::Method(method) => &method.args,
+                Node::Expr((Expr::Closure(args, ..), _))
+                | Node::Expr((Expr::ArrowFn(args, ..), _)) => args,
+                _ => continue,
+            };
+            if let Some(param) = params.iter().find(|param| param.name.0 == name) {
+                return param
+                    .ty
+                    .as_ref()
+                    .map(|(ty, _)| type_name(ty, &context.scope, context.class.as_deref()));
+            }
+            // Functions and methods don't see the variables around them, and closures only
+            // the ones they capture
+            if !matches!(node, Node::Expr((Expr::ArrowFn(..), _))) {
+                match node {
+                    Node::Expr((Expr::Closure(_, captured, ..), _))
+                        if captured.iter().any(|(var, _)| *var == name) => {}
+                    _ => return None,
+                }
+            }
+        }
+        None
+    }
+
+    /// The type of `expr`, as a type name with fully qualified classes, as in `?App\User`.
+    pub fn type_of(&self, context: &Context, expr: &Spanned<Expr>) -> Option<String> {
+        self.type_at(context, expr, 0)
+    }
+
+    fn type_at(
+        &self,
+        context: &Context,
+        (expr, span): &Spanned<Expr>,
+        depth: usize,
+    ) -> Option<String> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let class_of = |expr: &Spanned<Expr>| match &expr.0 {
+            Expr::Name((name, _)) => context.class(name),
+            _ => class_of(&self.type_at(context, expr, depth + 1)?).map(str::to_string),
+        };
+        let member_type = |class: Option<String>, kind, name: &Symbol| {
+            let target = Target::Member {
+                class: Some(class?),
+                kind,
+                name: name.clone(),
+            };
+            self.find(&target).into_iter().find_map(|declaration| declaration.ty.clone())
+        };
+        Some(match expr {
+            Expr::Value(Value::Null) => "null".to_string(),
+            Expr::Value(Value::Bool(_)) => "bool".to_string(),
+            Expr::Value(Value::Num(n)) if n.fract() == 0.0 => "int".to_string(),
+            Expr::Value(Value::Num(_)) => "float".to_string(),
+            Expr::Value(Value::Str(..)) => "string".to_string(),
+            Expr::Value(Value::List(_)) | Expr::List(_) => "array".to_string(),
+            Expr::Value(Value::Func(_)) => "callable".to_string(),
+            Expr::Local((name, _)) => {
+                return self.variable_type_at(context, name, span.start, depth)
+            }
+            Expr::Assign(_, value) => return self.type_at(context, value, depth + 1),
+            Expr::New(class, _) => return class_of(class),
+            Expr::Closure(..) | Expr::ArrowFn(..) => "Closure".to_string(),
+            Expr::Binary(a, op, b) => {
+                use crate::chumsky::BinaryOp;
+                match op {
+                    BinaryOp::Concat => "string".to_string(),
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        let a = self.type_at(context, a, depth + 1);
+                        let b = self.type_at(context, b, depth + 1);
+                        match (a.as_deref(), b.as_deref()) {
+                            (Some("int"), Some("int")) => "int".to_string(),
+                            (Some("array"), Some("array")) => "array".to_string(),
+                            _ => "int|float".to_string(),
+                        }
+                    }
+                    BinaryOp::Div => "int|float".to_string(),
+                    _ => "bool".to_string(),
+                }
+            }
+            Expr::Unary(crate::chumsky::UnaryOp::Not, _) | Expr::Isset(_) | Expr::Empty(_) => {
+                "bool".to_string()
+            }
+            Expr::Unary(_, expr) => return self.type_at(context, expr, depth + 1),
+            Expr::Call(callee, _) => match &callee.0 {
+                Expr::Name((name, _)) => {
+                    let target = Target::Function(context.scope.function(name));
+                    return self
+                        .find(&target)
+                        .into_iter()
+                        .find_map(|declaration| declaration.ty.clone());
+                }
+                Expr::Prop(object, (name, _)) | Expr::NullsafeProp(object, (name, _)) => {
+                    return member_type(class_of(object), MemberKind::Method, name)
+                }
+                Expr::ClassConst(class, (name, _)) => {
+                    return member_type(class_of(class), MemberKind::Method, name)
+                }
+                _ => return None,
+            },
+            Expr::Prop(object, (name, _)) | Expr::NullsafeProp(object, (name, _)) => {
+                return member_type(class_of(object), MemberKind::Property, name)
+            }
+            Expr::StaticProp(class, (name, _)) => {
+                return member_type(class_of(class), MemberKind::Property, name)
+            }
+            Expr::ClassConst(_, (name, _)) if name.eq_ignore_ascii_case("class") => {
+                "string".to_string()
+            }
+            Expr::ClassConst(class, (name, _)) => {
+                return member_type(class_of(class), MemberKind::Constant, name)
+            }
+            _ => return None,
+        })
+    }
+
+    /// What the name at `offset` refers to.
+    pub fn reference_at(&self, items: &[Item], offset: usize) -> Option<Reference> {
+        let context = Context::at(items, offset);
+        let reference = |target, span: &Span| {
+            span.contains(&offset).then(|| Reference {
+                target,
+                span: span.clone(),
+            })
+        };
+        let variable = |(name, span): &Spanned<Symbol>| {
+            let scope = context.variable_scope(name);
+            reference(
+                Target::Variable {
+                    name: name.clone(),
+                    scope,
+                },
+                span,
+            )
+        };
+        let member = |class, kind, (name, span): &Spanned<Symbol>| {
+            reference(
+                Target::Member {
+                    class,
+                    kind,
+                    name: name.clone(),
+                },
+                span,
+            )
+        };
+        let class =
+            |(name, span): &Spanned<Symbol>| reference(Target::Class(context.class(name)?), span);
+        let class_type = |(name, span): &Spanned<Symbol>| match builtins::is_type(name) {
+            true if !["self", "static", "parent"].iter().any(|n| name.eq_ignore_ascii_case(n)) => {
+                None
+            }
+            _ => class(&(name.clone(), span.clone())),
+        };
+
+        let (innermost, outer) = context.chain.split_last()?;
+        let parent = outer.last();
+        match innermost {
+            Node::Func(func) => reference(
+                Target::Function(vec![context.scope.qualify(&func.name.0)]),
+                &func.name.1,
+            ),
+            Node::Class(declared) => {
+                let name = &declared.name;
+                reference(Target::Class(context.scope.qualify(&name.0)), &name.1)
+                    .or_else(|| declared.extends.iter().chain(&declared.implements).find_map(class))
+            }
+            Node::Method(method) => member(context.class.clone(), MemberKind::Method, &method.name),
+            Node::Property(property) => {
+                member(context.class.clone(), MemberKind::Property, &property.name)
+            }
+            Node::EnumCase(case) => member(context.class.clone(), MemberKind::Constant, &case.name),
+            Node::Const(constant) if context.class.is_some() => {
+                member(context.class.clone(), MemberKind::Constant, &constant.name)
+            }
+            Node::Const(constant) => reference(
+                Target::Constant(vec![context.scope.qualify(&constant.name.0)]),
+                &constant.name.1,
+            ),
+            Node::TraitUse(name) => class(name),
+            Node::Use(use_) => {
+                let name = use_.name.0.to_string();
+                let target = match use_.kind {
+                    UseKind::Class => Target::Class(name),
+                    UseKind::Function => Target::Function(vec![name]),
+                    UseKind::Const => Target::Constant(vec![name]),
+                };
+                let alias = use_.alias.as_ref().map(|(_, span)| span);
+                reference(target.clone(), &use_.name.1).or_else(|| reference(target, alias?))
+            }
+            Node::Param(param) if is_promoted(param) && context.class.is_some() => {
+                member(context.class.clone(), MemberKind::Property, &param.name)
+            }
+            Node::Param(param) => variable(&param.name),
+            Node::Type((ty, _)) => {
+                fn named(ty: &Type, offset: usize) -> Option<&Spanned<Symbol>> {
+                    match ty {
+                        Type::Named(name) => name.1.contains(&offset).then_some(name),
+                        Type::Nullable(ty) => named(ty, offset),
+                        Type::Union(types) | Type::Intersection(types) => {
+                            types.iter().find_map(|ty| named(ty, offset))
+                        }
+                    }
+                }
+                class_type(named(ty, offset)?)
+            }
+            Node::Expr((expr, _)) => match expr {
+                Expr::Local(name) | Expr::Var(name, ..) => variable(name),
+                Expr::Global(names) => names.iter().find_map(variable),
+                Expr::Static(vars) => vars.iter().find_map(|(name, _)| variable(name)),
+                Expr::Closure(_, captured, ..) => captured.iter().find_map(variable),
+                Expr::Name((name, span)) => {
+                    let target = match parent {
+                        Some(Node::Expr((Expr::Call(callee, _), _))) if callee.1 == *span => {
+                            Target::Function(context.scope.function(name))
+                        }
+                        Some(Node::Expr((
+                            Expr::New(class, _)
+                            | Expr::StaticProp(class, _)
+                            | Expr::ClassConst(class, _),
+                            _,
+                        ))) if class.1 == *span => Target::Class(context.class(name)?),
+                        _ => Target::Constant(context.scope.constant(name)),
+                    };
+                    reference(target, span)
+                }
+                Expr::Prop(object, name) | Expr::NullsafeProp(object, name) => {
+                    let called = matches!(parent, Some(Node::Expr((Expr::Call(callee, _), _))) if callee.1 == innermost.span().clone());
+                    let kind = if called {
+                        MemberKind::Method
+                    } else {
+                        MemberKind::Property
+                    };
+                    let class = self
+                        .type_of(&context, object)
+                        .and_then(|ty| class_of(&ty).map(str::to_string));
+                    member(class, kind, name)
+                }
+                Expr::StaticProp(class, name) => {
+                    let class = self.class_of(&context, class);
+                    member(class, MemberKind::Property, name)
+                }
+                Expr::ClassConst(class, name) => {
+                    let called = matches!(parent, Some(Node::Expr((Expr::Call(callee, _), _))) if callee.1 == innermost.span().clone());
+                    let kind = if called {
+                        MemberKind::Method
+                    } else {
+                        MemberKind::Constant
+                    };
+                    let class = self.class_of(&context, class);
+                    member(class, kind, name)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The class that a static member is accessed on.
+    fn class_of(&self, context: &Context, class: &Spanned<Expr>) -> Option<String> {
+        match &class.0 {
+            Expr::Name((name, _)) => context.class(name),
+            _ => class_of(&self.type_of(context, class)?).map(str::to_string),
+        }
+    }
+}
+
+/// The spans of the variable `name` of the function, method or closure that starts at `scope`,
+/// or of the global scope, in source order: its parameter, assignments and reads.
+pub fn variable_spans(items: &[Item], name: &str, scope: Option<usize>) -> Vec<Span> {
+    let mut variables = Variables {
+        name,
+        scope,
+        current: None,
+        spans: Vec::new(),
+    };
+    walk_items(&mut variables, items);
+    variables.spans.sort_by_key(|span| span.start);
+    variables.spans
+}
+
+struct Variables<'n> {
+    name: &'n str,
+    scope: Option<usize>,
+    /// The scope being walked
+    current: Option<usize>,
+    spans: Vec<Span>,
+}
+
+impl Variables<'_> {
+    fn push(&mut self, (name, span): &Spanned<Symbol>) {
+        if self.current == self.scope && *name == self.name {
+            self.spans.push(span.clone());
+        }
+    }
+
+    fn within(&mut self, scope: usize, walk: impl FnOnce(&mut Self)) {
+        let outer = self.current.replace(scope);
+        walk(self);
+        self.current = outer;
+    }
+}
+
+impl<'ast> Visitor<'ast> for Variables<'_> {
+    fn visit_func(&mut self, func: &'ast Func) {
+        self.within(func.span.start, |this| walk_func(this, func));
+    }
+
+    fn visit_method(&mut self, method: &'ast Method) {
+        self.within(method.span.start, |this| walk_method(this, method));
+    }
+
+    fn visit_property_hook(&mut self, hook: &'ast PropertyHook) {
+        self.within(hook.span.start, |this| walk_property_hook(this, hook));
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.push(&param.name);
+        walk_param(self, param);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Local(name) | Expr::Var(name, ..) => self.push(name),
+            Expr::Global(names) => names.iter().for_each(|name| self.push(name)),
+            Expr::Static(vars) => vars.iter().for_each(|(name, _)| self.push(name)),
+            // What a closure captures is a variable of the scope around it, in the closure too
+            Expr::Closure(_, captured, ..) => {
+                captured.iter().for_each(|name| self.push(name));
+                if !captured.iter().any(|(name, _)| *name == self.name) {
+                    return self.within(expr.1.start, |this| walk_expr(this, expr));
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Every variable of a file, in source order, with where the function, method or closure it
+/// belongs to starts, as `Context::variable_scope` tells: `None` for the global scope.
+pub fn variables(items: &[Item]) -> Vec<(Spanned<Symbol>, Option<usize>)> {
+    let mut variables = AllVariables::default();
+    walk_items(&mut variables, items);
+    variables.found.sort_by_key(|((_, span), _)| span.start);
+    variables.found
+}
+
+#[derive(Default)]
+struct AllVariables<'ast> {
+    /// The scopes being walked, from the outermost, with what closures capture
+    scopes: Vec<(usize, &'ast [Spanned<Symbol>])>,
+    found: Vec<(Spanned<Symbol>, Option<usize>)>,
+}
+
+impl<'ast> AllVariables<'ast> {
+    fn push(&mut self, name: &Spanned<Symbol>) {
+        let scope = self.scopes.iter().rev().find_map(|(start, captured)| {
+            match captured.iter().any(|(captured, _)| *captured == name.0) {
+                true => None,
+                false => Some(Some(*start)),
+            }
+        });
+        self.found.push((name.clone(), scope.flatten()));
+    }
+
+    fn within(
+        &mut self,
+        scope: usize,
+        captured: &'ast [Spanned<Symbol>],
+        walk: impl FnOnce(&mut Self),
+    ) {
+        self.scopes.push((scope, captured));
+        walk(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'ast> Visitor<'ast> for AllVariables<'ast> {
+    fn visit_func(&mut self, func: &'ast Func) {
+        self.within(func.span.start, &[], |this| walk_func(this, func));
+    }
+
+    fn visit_method(&mut self, method: &'ast Method) {
+        self.within(method.span.start, &[], |this| walk_method(this, method));
+    }
+
+    fn visit_property_hook(&mut self, hook: &'ast PropertyHook) {
+        self.within(hook.span.start, &[], |this| walk_property_hook(this, hook));
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.push(&param.name);
+        walk_param(self, param);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Local(name) | Expr::Var(name, ..) => self.push(name),
+            Expr::Global(names) => names.iter().for_each(|name| self.push(name)),
+            Expr::Static(vars) => vars.iter().for_each(|(name, _)| self.push(name)),
+            Expr::Closure(_, captured, ..) => {
+                captured.iter().for_each(|name| self.push(name));
+                return self.within(expr.1.start, captured, |this| walk_expr(this, expr));
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+fn member_kind(kind: DeclarationKind) -> Option<MemberKind> {
+    match kind {
+        DeclarationKind::Method => Some(MemberKind::Method),
+        DeclarationKind::Property => Some(MemberKind::Property),
+        DeclarationKind::ClassConstant | DeclarationKind::EnumCase => Some(MemberKind::Constant),
+        _ => None,
+    }
+}
+
+fn is_member(declaration: &Declaration, kind: MemberKind, name: &str) -> bool {
+    match (kind, declaration.kind) {
+        (MemberKind::Method, DeclarationKind::Method) => {
+            declaration.name.eq_ignore_ascii_case(name)
+        }
+        (MemberKind::Property, DeclarationKind::Property) => declaration.name == name,
+        (MemberKind::Constant, DeclarationKind::ClassConstant | DeclarationKind::EnumCase) => {
+            declaration.name == name
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn test_names_resolve_through_namespaces_and_imports() {
+        let src = r#"<?php
+namespace App\Http;
+
+use App\Models\User as Account;
+use function App\Support\helper;
+
+class Controller extends Base {
+    private Account $user;
+
+    public function show(Account $account): static {
+        $name = $account->name();
+        $self = $this->show($account);
+        return strlen(helper($name)) + LIMIT;
+    }
+}
+"#;
+        let document = Document::new(src);
+        let (items, text) = (document.items().unwrap(), document.text());
+        let declarations = declarations(items, text);
+        let resolver = Resolver::new([("file:///app.php", declarations.as_slice())]);
+        let at = |needle: &str, nth: usize| {
+            let byte = src.match_indices(needle).nth(nth).unwrap().0;
+            let reference = resolver.reference_at(items, text.byte_to_char(byte));
+            reference.map(|reference| reference.target)
+        };
+
+        assert_eq!(
+            at("Base", 0),
+            Some(Target::Class("App\\Http\\Base".to_string()))
+        );
+        assert_eq!(
+            at("Account", 1),
+            Some(Target::Class("App\\Models\\User".to_string()))
+        );
+        assert_eq!(
+            at("helper", 1),
+            Some(Target::Function(vec!["App\\Support\\helper".to_string()]))
+        );
+        assert_eq!(
+            at("strlen", 0),
+            Some(Target::Function(vec![
+                "App\\Http\\strlen".to_string(),
+                "strlen".to_string()
+            ]))
+        );
+        assert_eq!(
+            at("LIMIT", 0),
+            Some(Target::Constant(vec![
+                "App\\Http\\LIMIT".to_string(),
+                "LIMIT".to_string()
+            ]))
+        );
+        // Members are looked up on the type of what they're accessed on
+        assert_eq!(
+            at("name()", 0),
+            Some(Target::Member {
+                class: Some("App\\Models\\User".to_string()),
+                kind: MemberKind::Method,
+                name: "name".into(),
+            })
+        );
+        let show = resolver.find(&at("show($account)", 0).unwrap());
+        assert_eq!(show.len(), 1);
+        assert_eq!(show[0].ty.as_deref(), Some("App\\Http\\Controller"));
+        assert!(matches!(
+            at("$self", 0),
+            Some(Target::Variable { name, scope: Some(_) }) if name == "self"
+        ));
+
+        let context = Context::at(items, text.byte_to_char(src.find("return").unwrap()));
+        let ty = |name: &str| {
+            resolver.variable_type(&context, name, context.chain.last().unwrap().span().start)
+        };
+        assert_eq!(ty("account").as_deref(), Some("App\\Models\\User"));
+        assert_eq!(ty("self").as_deref(), Some("App\\Http\\Controller"));
+        assert_eq!(ty("name"), None);
+    }
+
+    #[test]
+    fn test_definitions_are_found_across_files_and_scopes() {
+        let models = Document::new("<?php\nnamespace App;\n\nclass Base { public function id() {} }\nclass User extends Base {}\n");
+        let src = r#"<?php
+use App\User;
+
+function main() {
+    $user = new User();
+    $f = function () use ($user) { return $user->id(); };
+    $user = null;
+}
+$user = 1;
+"#;
+        let main = Document::new(src);
+        let (items, text) = (main.items().unwrap(), main.text());
+        let (models_declarations, main_declarations) = (models.declarations(), main.declarations());
+        let resolver = Resolver::new([
+            ("file:///models.php", models_declarations.as_slice()),
+            ("file:///main.php", main_declarations.as_slice()),
+        ]);
+        let offset = |needle: &str| text.byte_to_char(src.find(needle).unwrap());
+
+        // Methods are found in the classes that a class extends
+        let id = resolver.reference_at(items, offset("id()")).unwrap();
+        let found = resolver.locate(&id.target);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "file:///models.php");
+        assert_eq!(found[0].1.class.as_deref(), Some("App\\Base"));
+        let user = resolver.reference_at(items, offset("User;")).unwrap();
+        assert_eq!(resolver.locate(&user.target)[0].1.name, "App\\User");
+
+        // What a closure captures is a variable of the function around it, in the closure too
+        let Target::Variable { name, scope } =
+            resolver.reference_at(items, offset("$user)")).unwrap().target
+        else {
+            panic!("not a variable");
+        };
+        let spans = variable_spans(items, &name, scope);
+        let starts = src.match_indices("$user").map(|(i, _)| i).collect::<Vec<_>>();
+        let expected = starts[..4].iter().map(|&i| text.byte_to_char(i)).collect::<Vec<_>>();
+        assert_eq!(
+            spans.iter().map(|span| span.start).collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(variable_spans(items, &name, None).len(), 1);
+    }
+}
@@ -0,0 +1,819 @@
+//! Semantic tokens: the names in the AST, told apart by what they name, and their encoding as
+//! the LSP sends them, where each token is relative to the one before it, by lines and, on the
+//! same line, by columns.
+
+use crate::builtins;
+use crate::chumsky::{
+    Class, ClassKind, Const, EnumCase, Expr, Func, ImCompleteSemanticToken, Item, Member, Method,
+    Modifier, Namespace, Param, Property, PropertyHook, Span, Spanned, Type, Use, UseKind,
+};
+use crate::phpdoc;
+use crate::position::PositionEncoding;
+use crate::symbol::Symbol;
+use crate::visitor::{
+    walk_class, walk_const, walk_enum_case, walk_expr, walk_func, walk_items, walk_member,
+    walk_method, walk_namespace, walk_param, walk_property, walk_property_hook, Visitor,
+};
+use ropey::Rope;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensEdit,
+};
+
+/// Encodes `tokens`, in source order, relative to each other. A token that spans lines, like a
+/// multi-line string, is sent as one token per line, since not every client takes tokens that
+/// span lines. Columns and lengths count code units of the `encoding`.
+pub fn encode(
+    tokens: &[ImCompleteSemanticToken],
+    rope: &Rope,
+    encoding: PositionEncoding,
+) -> Vec<SemanticToken> {
+    let units = |offset: usize| encoding.units(rope.slice(..), offset);
+    let mut encoded = Vec::with_capacity(tokens.len());
+    let (mut prev_line, mut prev_column) = (0, 0);
+
+    for token in tokens {
+        let end = (token.start + token.length).min(rope.len_chars());
+        let mut start = token.start;
+        while start < end {
+            let Ok(line) = rope.try_char_to_line(start) else {
+                break;
+            };
+            let line_start = rope.line_to_char(line);
+            let next_line = rope.try_line_to_char(line + 1).unwrap_or(rope.len_chars());
+            let piece_end = end.min(next_line);
+            let mut text_end = piece_end;
+            while text_end > start && matches!(rope.char(text_end - 1), '\n' | '\r') {
+                text_end -= 1;
+            }
+
+            if text_end > start {
+                let (line, column) = (line as u32, (units(start) - units(line_start)) as u32);
+                let delta_line = line - prev_line;
+                encoded.push(SemanticToken {
+                    delta_line,
+                    delta_start: if delta_line == 0 {
+                        column - prev_column
+                    } else {
+                        column
+                    },
+                    length: (units(text_end) - units(start)) as u32,
+                    token_type: token.token_type as u32,
+                    token_modifiers_bitset: token.token_modifiers,
+                });
+                (prev_line, prev_column) = (line, column);
+            }
+            start = piece_end;
+        }
+    }
+    encoded
+}
+
+/// The `tokens`, in source order, that overlap `range`.
+pub fn in_range(tokens: &[ImCompleteSemanticToken], range: Span) -> &[ImCompleteSemanticToken] {
+    let first = tokens.partition_point(|token| token.start + token.length <= range.start);
+    let last = tokens.partition_point(|token| token.start < range.end);
+    &tokens[first..last.max(first)]
+}
+
+/// The edit that turns the `previous` tokens into the `current` ones, which replaces whatever is
+/// between what they start and end with. Tokens are relative to each other, so an edit usually
+/// changes only the few tokens it touches and the one after them.
+pub fn delta(previous: &[SemanticToken], current: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = previous.iter().zip(current).take_while(|(a, b)| a == b).count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix + suffix == previous.len() && previous.len() == current.len() {
+        return Vec::new();
+    }
+
+    // Edits count the integers that tokens are sent as, five per token
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: ((previous.len() - prefix - suffix) * 5) as u32,
+        data: Some(current[prefix..current.len() - suffix].to_vec()),
+    }]
+}
+
+/// The semantic tokens of the names in `items`, which the lexer leaves out, in source order.
+pub fn from_ast(items: &[Item], text: &Rope) -> Vec<ImCompleteSemanticToken> {
+    let mut declarations = Declarations::default();
+    declarations.declare(items, text);
+    let mut highlighter = Highlighter {
+        text,
+        declarations,
+        class: None,
+        params: Vec::new(),
+        tokens: Vec::new(),
+    };
+    walk_items(&mut highlighter, items);
+
+    let mut tokens = highlighter.tokens;
+    tokens.sort_by_key(|token| token.start);
+    let mut end = 0;
+    tokens.retain(|token| {
+        let overlaps = token.start < end;
+        end = end.max(token.start + token.length);
+        !overlaps
+    });
+    tokens
+}
+
+/// Merges the `lexical` tokens with the tokens of the `names` in the AST, in source order. Where
+/// they overlap, as for a type named `static`, the name's token wins.
+pub fn merge(
+    lexical: Vec<ImCompleteSemanticToken>,
+    names: Vec<ImCompleteSemanticToken>,
+) -> Vec<ImCompleteSemanticToken> {
+    let mut merged: Vec<ImCompleteSemanticToken> = Vec::with_capacity(lexical.len() + names.len());
+    let mut names = names.into_iter().peekable();
+    for token in lexical {
+        let end = token.start + token.length;
+        while let Some(name) = names.next_if(|name| name.start < end) {
+            merged.push(name);
+        }
+        if merged.last().map_or(true, |last| last.start + last.length <= token.start) {
+            merged.push(token);
+        }
+    }
+    merged.extend(names);
+    merged
+}
+
+/// What a file declares, by lowercased name, to tell what the names that refer to it are.
+#[derive(Default)]
+struct Declarations {
+    /// Classes, interfaces, traits and enums by their short name
+    classes: HashMap<String, ClassKind>,
+    /// The short names of the classes that `use` imports under an alias, by alias
+    aliases: HashMap<String, String>,
+    /// Enum cases, as `enum::case`
+    cases: HashSet<String>,
+    /// What a doc comment marks `@deprecated`: functions as `name()`, classes and constants as
+    /// `name`, and members as `class::name()`, `class::$name` or `class::name`
+    deprecated: HashSet<String>,
+}
+
+impl Declarations {
+    fn declare(&mut self, items: &[Item], text: &Rope) {
+        for item in items {
+            match item {
+                Item::Func(func) => {
+                    self.deprecate(text, func.span.start, format!("{}()", func.name.0))
+                }
+                Item::Const(constant) => {
+                    self.deprecate(text, constant.span.start, constant.name.0.to_string())
+                }
+                Item::Class(class) => {
+                    let name = class.name.0.to_lowercase();
+                    self.deprecate(text, class.span.start, name.clone());
+                    for member in &class.members {
+                        let (key, start) = match member {
+                            Member::Method(method) => {
+                                (format!("{}()", method.name.0), method.span.start)
+                            }
+                            Member::Property(property) => {
+                                (format!("${}", property.name.0), property.span.start)
+                            }
+                            Member::Const(constant) => {
+                                (constant.name.0.to_string(), constant.span.start)
+                            }
+                            Member::Case(EnumCase {
+                                name: (case, _),
+                                span,
+                                ..
+                            }) => {
+                                let start = span.start;
+                                let case = case.to_lowercase();
+                                self.cases.insert(format!("{}::{}", name, case));
+                                (case, start)
+                            }
+                            Member::TraitUse(_) => continue,
+                        };
+                        self.deprecate(text, start, format!("{}::{}", name, key));
+                    }
+                    self.classes.insert(name, class.kind);
+                }
+                Item::Use(Use {
+                    kind: UseKind::Class,
+                    name,
+                    alias: Some(alias),
+                    ..
+                }) => {
+                    self.aliases.insert(alias.0.to_lowercase(), short_name(&name.0));
+                }
+                Item::Namespace(namespace) => self.declare(&namespace.items, text),
+                Item::Use(_) | Item::Stmt(_) => {}
+            }
+        }
+    }
+
+    /// Keeps `key` as deprecated when the doc comment of the declaration at `start` says so.
+    fn deprecate(&mut self, text: &Rope, start: usize, key: String) {
+        if phpdoc::doc_comment(text, start).is_some_and(|comment| phpdoc::is_deprecated(&comment)) {
+            self.deprecated.insert(key.to_lowercase());
+        }
+    }
+
+    /// The short name of the class that `name` refers to, once aliases are resolved.
+    fn class(&self, name: &str) -> String {
+        let name = short_name(name);
+        self.aliases.get(&name).cloned().unwrap_or(name)
+    }
+
+    fn is_deprecated(&self, key: &str) -> bool {
+        self.deprecated.contains(&key.to_lowercase())
+    }
+}
+
+/// The lowercased last segment of a qualified name.
+fn short_name(name: &str) -> String {
+    name.rsplit('\\').next().unwrap_or(name).to_lowercase()
+}
+
+struct Highlighter<'a> {
+    text: &'a Rope,
+    declarations: Declarations,
+    /// The lowercased name of the class whose body is being highlighted
+    class: Option<String>,
+    /// The parameters in scope, which are highlighted apart from other variables
+    params: Vec<Symbol>,
+    tokens: Vec<ImCompleteSemanticToken>,
+}
+
+impl Highlighter<'_> {
+    fn push(
+        &mut self,
+        span: &Span,
+        token_type: SemanticTokenType,
+        modifiers: &[SemanticTokenModifier],
+    ) {
+        self.tokens.push(ImCompleteSemanticToken::new(span, token_type, modifiers));
+    }
+
+    /// Pushes a name that may be qualified, whose namespace is highlighted as one.
+    fn qualified(
+        &mut self,
+        span: &Span,
+        token_type: SemanticTokenType,
+        modifiers: &[SemanticTokenModifier],
+    ) {
+        let name = self.text.slice(span.start..span.end.min(self.text.len_chars())).to_string();
+        match name.rfind('\\') {
+            Some(at) => {
+                let namespace = name[..at].chars().count();
+                self.push(
+                    &(span.start..span.start + namespace),
+                    SemanticTokenType::NAMESPACE,
+                    &[],
+                );
+                self.push(
+                    &(span.start + namespace + 1..span.end),
+                    token_type,
+                    modifiers,
+                );
+            }
+            None => self.push(span, token_type, modifiers),
+        }
+    }
+
+    fn modifiers(&mut self, modifiers: &[Spanned<Modifier>]) {
+        for (_, span) in modifiers {
+            self.push(span, SemanticTokenType::KEYWORD, &[]);
+        }
+    }
+
+    /// Pushes a name of a class, which is taken to be a `default` one when it isn't declared in
+    /// the file.
+    fn class_ref(&mut self, (name, span): &Spanned<Symbol>, default: ClassKind) {
+        if ["self", "parent", "static"].iter().any(|keyword| name.eq_ignore_ascii_case(keyword)) {
+            return self.push(span, SemanticTokenType::KEYWORD, &[]);
+        }
+        let class = self.declarations.class(name);
+        let declared = self.declarations.classes.get(&class).copied();
+        let mut modifiers = Vec::new();
+        if declared.is_none() && builtins::is_class(name) {
+            modifiers.push(SemanticTokenModifier::DEFAULT_LIBRARY);
+        }
+        if self.declarations.is_deprecated(&class) {
+            modifiers.push(SemanticTokenModifier::DEPRECATED);
+        }
+        self.qualified(span, class_type(declared.unwrap_or(default)), &modifiers);
+    }
+
+    /// Pushes the class that a member is accessed on, or visits the expression it's accessed on.
+    fn base(&mut self, base: &Spanned<Expr>) {
+        match &base.0 {
+            Expr::Name(name) => self.class_ref(name, ClassKind::Class),
+            _ => self.visit_expr(base),
+        }
+    }
+
+    /// Pushes the name of a member, deprecated when what it's accessed on is a class of the file
+    /// whose member is. Members are keyed as in [`Declarations::deprecated`].
+    fn member(
+        &mut self,
+        base: &Spanned<Expr>,
+        span: &Span,
+        key: &str,
+        token_type: SemanticTokenType,
+        modifiers: &[SemanticTokenModifier],
+    ) {
+        let class = match &base.0 {
+            Expr::Local((name, _)) if *name == "this" => self.class.clone(),
+            Expr::Name((name, _))
+                if name.eq_ignore_ascii_case("self") || name.eq_ignore_ascii_case("static") =>
+            {
+                self.class.clone()
+            }
+            Expr::Name((name, _)) => Some(self.declarations.class(name)),
+            _ => None,
+        };
+        let mut modifiers = modifiers.to_vec();
+        if class
+            .is_some_and(|class| self.declarations.is_deprecated(&format!("{}::{}", class, key)))
+        {
+            modifiers.push(SemanticTokenModifier::DEPRECATED);
+        }
+        self.push(span, token_type, &modifiers);
+    }
+
+    /// Pushes the name of a member of the class being highlighted.
+    fn member_declaration(
+        &mut self,
+        span: &Span,
+        key: &str,
+        token_type: SemanticTokenType,
+        mut modifiers: Vec<SemanticTokenModifier>,
+    ) {
+        let class = self.class.as_deref().unwrap_or_default();
+        if self.declarations.is_deprecated(&format!("{}::{}", class, key)) {
+            modifiers.push(SemanticTokenModifier::DEPRECATED);
+        }
+        self.push(span, token_type, &modifiers);
+    }
+
+    fn local(&mut self, (name, span): &Spanned<Symbol>, modifiers: &[SemanticTokenModifier]) {
+        if *name == "this" {
+            self.push(
+                span,
+                SemanticTokenType::VARIABLE,
+                &[SemanticTokenModifier::READONLY],
+            );
+        } else if self.params.contains(name) {
+            self.push(span, SemanticTokenType::PARAMETER, modifiers);
+        } else {
+            self.push(span, SemanticTokenType::VARIABLE, modifiers);
+        }
+    }
+
+    /// Highlights a function-like body with `params` in scope.
+    fn scope(&mut self, params: impl IntoIterator<Item = Symbol>, walk: impl FnOnce(&mut Self)) {
+        let outer = self.params.len();
+        self.params.extend(params);
+        walk(self);
+        self.params.truncate(outer);
+    }
+}
+
+fn class_type(kind: ClassKind) -> SemanticTokenType {
+    match kind {
+        ClassKind::Interface => SemanticTokenType::INTERFACE,
+        ClassKind::Enum => SemanticTokenType::ENUM,
+        ClassKind::Class | ClassKind::Trait => SemanticTokenType::CLASS,
+    }
+}
+
+fn has(modifiers: &[Spanned<Modifier>], modifier: Modifier) -> bool {
+    modifiers.iter().any(|(m, _)| *m == modifier)
+}
+
+fn params(params: &[Param]) -> Vec<Symbol> {
+    params.iter().map(|param| param.name.0.clone()).collect()
+}
+
+impl<'ast> Visitor<'ast> for Highlighter<'_> {
+    fn visit_func(&mut self, func: &'ast Func) {
+        let mut modifiers = vec![SemanticTokenModifier::DECLARATION];
+        if self.declarations.is_deprecated(&format!("{}()", func.name.0)) {
+            modifiers.push(SemanticTokenModifier::DEPRECATED);
+        }
+        self.push(&func.name.1, SemanticTokenType::FUNCTION, &modifiers);
+        self.scope(params(&func.args), |this| walk_func(this, func));
+    }
+
+    fn visit_class(&mut self, class: &'ast Class) {
+        let name = class.name.0.to_lowercase();
+        let mut modifiers = vec![SemanticTokenModifier::DECLARATION];
+        if has(&class.modifiers, Modifier::Abstract) {
+            modifiers.push(SemanticTokenModifier::ABSTRACT);
+        }
+        if self.declarations.is_deprecated(&name) {
+            modifiers.push(SemanticTokenModifier::DEPRECATED);
+        }
+        self.modifiers(&class.modifiers);
+        self.push(&class.name.1, class_type(class.kind), &modifiers);
+
+        // An interface extends interfaces, and a class extends a class
+        let extends = match class.kind {
+            ClassKind::Interface => ClassKind::Interface,
+            _ => ClassKind::Class,
+        };
+        class.extends.iter().for_each(|name| self.class_ref(name, extends));
+        class.implements.iter().for_each(|name| self.class_ref(name, ClassKind::Interface));
+
+        let outer = self.class.replace(name);
+        walk_class(self, class);
+        self.class = outer;
+    }
+
+    fn visit_member(&mut self, member: &'ast Member) {
+        match member {
+            Member::TraitUse(name) => self.class_ref(name, ClassKind::Trait),
+            _ => walk_member(self, member),
+        }
+    }
+
+    fn visit_method(&mut self, method: &'ast Method) {
+        let mut modifiers = vec![SemanticTokenModifier::DECLARATION];
+        if has(&method.modifiers, Modifier::Static) {
+            modifiers.push(SemanticTokenModifier::STATIC);
+        }
+        if has(&method.modifiers, Modifier::Abstract) || method.body.is_none() {
+            modifiers.push(SemanticTokenModifier::ABSTRACT);
+        }
+        self.modifiers(&method.modifiers);
+        let key = format!("{}()", method.name.0);
+        self.member_declaration(&method.name.1, &key, SemanticTokenType::METHOD, modifiers);
+        self.scope(params(&method.args), |this| walk_method(this, method));
+    }
+
+    fn visit_property(&mut self, property: &'ast Property) {
+        let mut modifiers = vec![SemanticTokenModifier::DECLARATION];
+        if has(&property.modifiers, Modifier::Static) {
+            modifiers.push(SemanticTokenModifier::STATIC);
+        }
+        if has(&property.modifiers, Modifier::Readonly) {
+            modifiers.push(SemanticTokenModifier::READONLY);
+        }
+        self.modifiers(&property.modifiers);
+        let key = format!("${}", property.name.0);
+        self.member_declaration(
+            &property.name.1,
+            &key,
+            SemanticTokenType::PROPERTY,
+            modifiers,
+        );
+        walk_property(self, property);
+    }
+
+    fn visit_property_hook(&mut self, hook: &'ast PropertyHook) {
+        self.push(
+            &hook.name.1,
+            SemanticTokenType::METHOD,
+            &[SemanticTokenModifier::DECLARATION],
+        );
+        self.scope(params(&hook.args), |this| walk_property_hook(this, hook));
+    }
+
+    fn visit_enum_case(&mut self, case: &'ast EnumCase) {
+        let modifiers = vec![SemanticTokenModifier::DECLARATION];
+        self.member_declaration(
+            &case.name.1,
+            &case.name.0,
+            SemanticTokenType::ENUM_MEMBER,
+            modifiers,
+        );
+        walk_enum_case(self, case);
+    }
+
+    fn visit_const(&mut self, constant: &'ast Const) {
+        self.modifiers(&constant.modifiers);
+        let mut modifiers = vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::READONLY,
+        ];
+        if self.class.is_some() {
+            modifiers.push(SemanticTokenModifier::STATIC);
+            self.member_declaration(
+                &constant.name.1,
+                &constant.name.0,
+                SemanticTokenType::PROPERTY,
+                modifiers,
+            );
+        } else {
+            if self.declarations.is_deprecated(&constant.name.0) {
+                modifiers.push(SemanticTokenModifier::DEPRECATED);
+            }
+            self.push(&constant.name.1, SemanticTokenType::VARIABLE, &modifiers);
+        }
+        walk_const(self, constant);
+    }
+
+    fn visit_use(&mut self, use_: &'ast Use) {
+        let (token_type, modifiers) = match use_.kind {
+            UseKind::Class => {
+                let declared = self.declarations.classes.get(&short_name(&use_.name.0)).copied();
+                return self.class_ref(&use_.name, declared.unwrap_or(ClassKind::Class));
+            }
+            UseKind::Function => (SemanticTokenType::FUNCTION, vec![]),
+            UseKind::Const => (
+                SemanticTokenType::VARIABLE,
+                vec![SemanticTokenModifier::READONLY],
+            ),
+        };
+        self.qualified(&use_.name.1, token_type.clone(), &modifiers);
+        if let Some((_, span)) = &use_.alias {
+            self.push(span, token_type, &[SemanticTokenModifier::DECLARATION]);
+        }
+    }
+
+    fn visit_namespace(&mut self, namespace: &'ast Namespace) {
+        if let Some((_, span)) = &namespace.name {
+            self.push(span, SemanticTokenType::NAMESPACE, &[]);
+        }
+        walk_namespace(self, namespace);
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        let mut modifiers = vec![SemanticTokenModifier::DECLARATION];
+        if has(&param.modifiers, Modifier::Readonly) {
+            modifiers.push(SemanticTokenModifier::READONLY);
+        }
+        self.modifiers(&param.modifiers);
+        self.push(&param.name.1, SemanticTokenType::PARAMETER, &modifiers);
+        walk_param(self, param);
+    }
+
+    fn visit_type(&mut self, (ty, _): &'ast Spanned<Type>) {
+        fn collect<'t>(ty: &'t Type, names: &mut Vec<&'t Spanned<Symbol>>) {
+            match ty {
+                Type::Named(name) => names.push(name),
+                Type::Nullable(ty) => collect(ty, names),
+                Type::Union(types) | Type::Intersection(types) => {
+                    types.iter().for_each(|ty| collect(ty, names))
+                }
+            }
+        }
+        let mut named = Vec::new();
+        collect(ty, &mut named);
+        for name in named {
+            if builtins::is_type(&name.0) {
+                self.push(
+                    &name.1,
+                    SemanticTokenType::TYPE,
+                    &[SemanticTokenModifier::DEFAULT_LIBRARY],
+                );
+            } else {
+                self.class_ref(name, ClassKind::Class);
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Local(name) => self.local(name, &[]),
+            Expr::Var(name, value, rest) => {
+                self.local(name, &[]);
+                self.visit_expr(value);
+                self.visit_expr(rest);
+            }
+            Expr::Name((name, span)) => {
+                let mut modifiers = vec![SemanticTokenModifier::READONLY];
+                if builtins::is_constant(name) {
+                    modifiers.push(SemanticTokenModifier::DEFAULT_LIBRARY);
+                }
+                if self.declarations.is_deprecated(&short_name(name)) {
+                    modifiers.push(SemanticTokenModifier::DEPRECATED);
+                }
+                self.qualified(span, SemanticTokenType::VARIABLE, &modifiers);
+            }
+            Expr::Call(callee, (args, _)) => {
+                match &callee.0 {
+                    Expr::Name((name, span)) => {
+                        let mut modifiers = Vec::new();
+                        if builtins::is_function(name) {
+                            modifiers.push(SemanticTokenModifier::DEFAULT_LIBRARY);
+                        }
+                        if self.declarations.is_deprecated(&format!("{}()", short_name(name))) {
+                            modifiers.push(SemanticTokenModifier::DEPRECATED);
+                        }
+                        self.qualified(span, SemanticTokenType::FUNCTION, &modifiers);
+                    }
+                    Expr::Prop(object, (name, span)) | Expr::NullsafeProp(object, (name, span)) => {
+                        self.visit_expr(object);
+                        self.member(
+                            object,
+                            span,
+                            &format!("{}()", name),
+                            SemanticTokenType::METHOD,
+                            &[],
+                        );
+                    }
+                    Expr::ClassConst(class, (name, span)) => {
+                        self.base(class);
+                        let key = format!("{}()", name);
+                        let modifiers = [SemanticTokenModifier::STATIC];
+                        self.member(class, span, &key, SemanticTokenType::METHOD, &modifiers);
+                    }
+                    _ => self.visit_expr(callee),
+                }
+                args.iter().for_each(|arg| self.visit_expr(arg));
+            }
+            Expr::New(class, args) => {
+                match &class.0 {
+                    Expr::Name(name) => self.class_ref(name, ClassKind::Class),
+                    _ => self.visit_expr(class),
+                }
+                args.iter().flat_map(|(args, _)| args).for_each(|arg| self.visit_expr(arg));
+            }
+            Expr::Prop(object, (name, span)) | Expr::NullsafeProp(object, (name, span)) => {
+                self.visit_expr(object);
+                self.member(
+                    object,
+                    span,
+                    &format!("${}", name),
+                    SemanticTokenType::PROPERTY,
+                    &[],
+                );
+            }
+            Expr::StaticProp(class, (name, span)) => {
+                self.base(class);
+                let modifiers = [SemanticTokenModifier::STATIC];
+                self.member(
+                    class,
+                    span,
+                    &format!("${}", name),
+                    SemanticTokenType::PROPERTY,
+                    &modifiers,
+                );
+            }
+            Expr::ClassConst(class, (name, span)) => {
+                self.base(class);
+                if name.eq_ignore_ascii_case("class") {
+                    return self.push(span, SemanticTokenType::KEYWORD, &[]);
+                }
+                let case = match &class.0 {
+                    Expr::Name((class, _)) => {
+                        let case = format!(
+                            "{}::{}",
+                            self.declarations.class(class),
+                            name.to_lowercase()
+                        );
+                        self.declarations.cases.contains(&case)
+                    }
+                    _ => false,
+                };
+                if case {
+                    self.member(class, span, name, SemanticTokenType::ENUM_MEMBER, &[]);
+                } else {
+                    let modifiers = [
+                        SemanticTokenModifier::STATIC,
+                        SemanticTokenModifier::READONLY,
+                    ];
+                    self.member(class, span, name, SemanticTokenType::PROPERTY, &modifiers);
+                }
+            }
+            Expr::Global(names) => names.iter().for_each(|name| self.local(name, &[])),
+            Expr::Static(vars) => {
+                for (name, value) in vars {
+                    self.local(name, &[SemanticTokenModifier::STATIC]);
+                    value.iter().for_each(|value| self.visit_expr(value));
+                }
+            }
+            // A closure sees only the variables it captures, and an arrow function sees them all
+            Expr::Closure(args, captured, _, _) => {
+                let outer = std::mem::take(&mut self.params);
+                captured.iter().for_each(|name| self.local(name, &[]));
+                self.scope(params(args), |this| walk_expr(this, expr));
+                self.params = outer;
+            }
+            Expr::ArrowFn(args, _, _) => self.scope(params(args), |this| walk_expr(this, expr)),
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::{parser, LEGEND_MODIFIER, LEGEND_TYPE};
+    use crate::document::Document;
+    use crate::version::PhpVersion;
+    use tower_lsp::lsp_types::SemanticTokenType;
+
+    fn token_type(token_type: SemanticTokenType) -> u32 {
+        LEGEND_TYPE.iter().position(|t| *t == token_type).unwrap() as u32
+    }
+
+    #[test]
+    fn test_encode_is_relative_and_splits_lines() {
+        let src = "<?php\necho 1, '👋\r\nb';\n  echo 2;";
+        let tokens = parser(src, PhpVersion::default()).semantic_tokens;
+        let encoded = encode(&tokens, &Rope::from_str(src), PositionEncoding::Utf16);
+
+        let (echo, number, string) = (
+            token_type(SemanticTokenType::KEYWORD),
+            token_type(SemanticTokenType::NUMBER),
+            token_type(SemanticTokenType::STRING),
+        );
+        let relative = encoded
+            .iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relative,
+            vec![
+                (1, 0, 4, echo),
+                (0, 5, 1, number),
+                // The emoji is two UTF-16 code units
+                (0, 3, 3, string),
+                (1, 0, 2, string),
+                (1, 2, 4, echo),
+                (0, 5, 1, number),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_names_are_told_apart_by_the_ast() {
+        let src = "<?php
+namespace App;
+/** @deprecated */
+function old() {}
+enum Suit { case Hearts; }
+abstract class Card implements \\Countable {
+    public readonly Suit $suit;
+    abstract public static function make(int $n): static;
+    public function count(): int { return strlen($this->name($n)) + old() + Suit::Hearts; }
+}";
+        let document = Document::new(src);
+        let text = document.text();
+        let tokens = from_ast(document.items().unwrap(), text);
+
+        let token = |name: &str, nth: usize| {
+            let start = text.byte_to_char(src.match_indices(name).nth(nth).unwrap().0);
+            let token = tokens.iter().find(|token| token.start == start);
+            let token = token.unwrap_or_else(|| panic!("no token for {} {}", name, nth));
+            let modifiers = LEGEND_MODIFIER
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| token.token_modifiers & 1 << i != 0)
+                .map(|(_, modifier)| modifier.as_str())
+                .collect::<Vec<_>>();
+            (LEGEND_TYPE[token.token_type].as_str(), modifiers.join(" "))
+        };
+        let expected = [
+            (("App", 0), ("namespace", "")),
+            (("old", 0), ("function", "declaration deprecated")),
+            (("Suit", 0), ("enum", "declaration")),
+            (("Hearts", 0), ("enumMember", "declaration")),
+            (("Card", 0), ("class", "declaration abstract")),
+            (("Countable", 0), ("interface", "defaultLibrary")),
+            (("Suit", 1), ("enum", "")),
+            (("$suit", 0), ("property", "declaration readonly")),
+            (("make", 0), ("method", "declaration static abstract")),
+            (("int", 0), ("type", "defaultLibrary")),
+            (("$n", 0), ("parameter", "declaration")),
+            (("strlen", 0), ("function", "defaultLibrary")),
+            (("$this", 0), ("variable", "readonly")),
+            (("name(", 0), ("method", "")),
+            (("$n", 1), ("variable", "")),
+            (("old", 1), ("function", "deprecated")),
+            (("Hearts", 1), ("enumMember", "")),
+        ];
+        for ((name, nth), (token_type, modifiers)) in expected {
+            assert_eq!(
+                token(name, nth),
+                (token_type, modifiers.to_string()),
+                "{} {}",
+                name,
+                nth
+            );
+        }
+    }
+
+    #[test]
+    fn test_delta_replaces_only_what_changed() {
+        let encode_src = |src: &str| {
+            encode(
+                &parser(src, PhpVersion::default()).semantic_tokens,
+                &Rope::from_str(src),
+                PositionEncoding::default(),
+            )
+        };
+        let previous = encode_src("<?php\necho 1;\necho 2;\necho 3;");
+        let current = encode_src("<?php\necho 1;\necho 'two', 2;\necho 3;");
+
+        let edits = delta(&previous, &current);
+        assert_eq!(edits.len(), 1);
+        // The string is new, and the number after it moved
+        assert_eq!((edits[0].start, edits[0].delete_count), (15, 5));
+        assert_eq!(edits[0].data.as_ref().unwrap().len(), 2);
+        assert!(delta(&current, &current).is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+//! Interned names. Each distinct name in use is stored once for the whole server, however many
+//! files and tokens it appears in, so that comparing and copying names stays cheap.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+
+/// An interned name. Only identifiers are interned, not the text of strings or inline HTML.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+/// Every name that is still in use. A name is dropped from the set once the set holds its last
+/// reference, so the set is bounded by the names in the open and indexed files, not by every
+/// partial identifier typed since the server started.
+#[derive(Default)]
+struct Interner {
+    names: HashSet<Arc<str>>,
+    /// The size to sweep at, twice the live size after the last sweep, so that sweeping stays
+    /// amortised constant per name interned.
+    sweep_at: usize,
+}
+
+static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+
+impl Interner {
+    /// Drops the names nothing else refers to. The count can't grow behind our back: another
+    /// reference is only ever cloned from a live `Symbol` or handed out under the lock.
+    fn sweep(&mut self) {
+        self.names.retain(|name| Arc::strong_count(name) > 1);
+        self.sweep_at = (self.names.len() * 2).max(1024);
+    }
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        let mut interner =
+            INTERNER.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(name) = interner.names.get(name) {
+            return Symbol(name.clone());
+        }
+        if interner.names.len() >= interner.sweep_at {
+            interner.sweep();
+        }
+        let name: Arc<str> = name.into();
+        interner.names.insert(name.clone());
+        Symbol(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Names are interned once, so equal names are at the same address.
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Prints as the name does, so that it reads the same as a `String` would.
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interned_names_are_stored_once() {
+        let name = String::from("App\\Models\\User");
+        let (a, b) = (Symbol::intern(&name), Symbol::from(name.clone()));
+
+        assert_eq!(a, b);
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        assert_ne!(a.as_ptr(), name.as_ptr());
+        assert_ne!(a, Symbol::intern("App\\Models\\Post"));
+        assert_eq!(format!("{} {:?}", a, a), format!("{} {:?}", name, name));
+    }
+
+    #[test]
+    fn test_names_are_freed_once_unused() {
+        let interned = |name: &str| {
+            let interner = INTERNER.get_or_init(Default::default).lock().unwrap();
+            interner.names.contains(name)
+        };
+        let kept = Symbol::intern("test_names_are_freed_once_unused_kept");
+        drop(Symbol::intern("test_names_are_freed_once_unused_typed"));
+        assert!(interned("test_names_are_freed_once_unused_typed"));
+
+        INTERNER.get().unwrap().lock().unwrap().sweep();
+        assert!(!interned("test_names_are_freed_once_unused_typed"));
+        assert!(interned(&kept));
+        assert_eq!(
+            kept,
+            Symbol::intern("test_names_are_freed_once_unused_kept")
+        );
+    }
+}
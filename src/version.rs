@@ -0,0 +1,168 @@
+use crate::chumsky::{
+    Class, ClassKind, Expr, Item, Modifier, Param, Property, PropertyHook, Span, Spanned, Type,
+};
+use crate::visitor::{
+    walk_class, walk_expr, walk_items, walk_param, walk_property, walk_property_hook, Visitor,
+};
+use chumsky::error::Simple;
+use std::{fmt, str::FromStr};
+
+/// A PHP language version, which decides what syntax the parser accepts without a diagnostic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhpVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl PhpVersion {
+    pub const PHP_7_4: Self = Self::new(7, 4);
+    pub const PHP_8_0: Self = Self::new(8, 0);
+    pub const PHP_8_1: Self = Self::new(8, 1);
+    pub const PHP_8_2: Self = Self::new(8, 2);
+    pub const PHP_8_3: Self = Self::new(8, 3);
+    pub const PHP_8_4: Self = Self::new(8, 4);
+
+    pub const fn new(major: u8, minor: u8) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// Without a configured version, every supported syntax is accepted.
+impl Default for PhpVersion {
+    fn default() -> Self {
+        Self::PHP_8_4
+    }
+}
+
+impl fmt::Display for PhpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Parses versions as written in settings, like `8.1`, `8.1.2` or `7`.
+impl FromStr for PhpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.').map(str::parse::<u8>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(major)), None) => Ok(Self::new(major, 0)),
+            (Some(Ok(major)), Some(Ok(minor))) => Ok(Self::new(major, minor)),
+            _ => Err(format!("Invalid PHP version '{}'", s)),
+        }
+    }
+}
+
+/// Reports syntax in `items` that is newer than the `target` version.
+pub fn check(items: &[Item], target: PhpVersion) -> Vec<Simple<String>> {
+    let mut checker = Checker {
+        target,
+        errors: Vec::new(),
+    };
+    walk_items(&mut checker, items);
+    checker.errors
+}
+
+struct Checker {
+    target: PhpVersion,
+    errors: Vec<Simple<String>>,
+}
+
+impl Checker {
+    fn require(&mut self, since: PhpVersion, feature: &str, span: Span) {
+        if self.target < since {
+            self.errors.push(Simple::custom(
+                span,
+                format!(
+                    "{} requires PHP {}, but the target is PHP {}",
+                    feature, since, self.target
+                ),
+            ));
+        }
+    }
+
+    fn readonly(&mut self, modifiers: &[Spanned<Modifier>], since: PhpVersion, feature: &str) {
+        for (_, span) in modifiers.iter().filter(|(modifier, _)| *modifier == Modifier::Readonly) {
+            self.require(since, feature, span.clone());
+        }
+    }
+}
+
+impl Visitor<'_> for Checker {
+    fn visit_class(&mut self, class: &Class) {
+        if class.kind == ClassKind::Enum {
+            self.require(
+                PhpVersion::PHP_8_1,
+                "Enum",
+                class.span.start..class.name.1.end,
+            );
+        }
+        self.readonly(&class.modifiers, PhpVersion::PHP_8_2, "Readonly class");
+        walk_class(self, class);
+    }
+
+    fn visit_property(&mut self, property: &Property) {
+        self.readonly(
+            &property.modifiers,
+            PhpVersion::PHP_8_1,
+            "Readonly property",
+        );
+        walk_property(self, property);
+    }
+
+    fn visit_property_hook(&mut self, hook: &PropertyHook) {
+        self.require(PhpVersion::PHP_8_4, "Property hook", hook.span.clone());
+        walk_property_hook(self, hook);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        if let (Some((_, first)), Some((_, last))) =
+            (param.modifiers.first(), param.modifiers.last())
+        {
+            self.require(
+                PhpVersion::PHP_8_0,
+                "Constructor property promotion",
+                first.start..last.end,
+            );
+        }
+        self.readonly(&param.modifiers, PhpVersion::PHP_8_1, "Readonly property");
+        walk_param(self, param);
+    }
+
+    fn visit_type(&mut self, (ty, span): &Spanned<Type>) {
+        match ty {
+            Type::Union(types) => {
+                match types.iter().any(|ty| matches!(ty, Type::Intersection(_))) {
+                    true => self.require(PhpVersion::PHP_8_2, "DNF type", span.clone()),
+                    false => self.require(PhpVersion::PHP_8_0, "Union type", span.clone()),
+                }
+            }
+            Type::Intersection(_) => {
+                self.require(PhpVersion::PHP_8_1, "Intersection type", span.clone())
+            }
+            Type::Named(_) | Type::Nullable(_) => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        match expr {
+            // Only the keyword, rather than every arm
+            (Expr::Match(..), span) => self.require(
+                PhpVersion::PHP_8_0,
+                "Match expression",
+                span.start..span.start + 5,
+            ),
+            (Expr::NullsafeProp(object, name), _) => self.require(
+                PhpVersion::PHP_8_0,
+                "Nullsafe operator",
+                object.1.end..name.1.start,
+            ),
+            (Expr::ArrowFn(..), span) => {
+                self.require(PhpVersion::PHP_7_4, "Arrow function", span.clone())
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
@@ -0,0 +1,671 @@
+//! Traversal of the AST produced by [`crate::chumsky::parser`].
+//!
+//! Each `visit_*` method of [`Visitor`] and [`VisitorMut`] defaults to the matching `walk_*`
+//! function, which visits the children of a node in source order. Implementations override the
+//! methods for the nodes they care about and call the `walk_*` function to keep descending.
+
+use crate::chumsky::{
+    Class, Const, EnumCase, Expr, Func, Item, MatchArm, Member, Method, Namespace, Param, Property,
+    PropertyHook, Span, Spanned, SwitchCase, Type, Use,
+};
+use crate::symbol::Symbol;
+
+pub trait Visitor<'ast> {
+    fn visit_item(&mut self, item: &'ast Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_func(&mut self, func: &'ast Func) {
+        walk_func(self, func)
+    }
+
+    fn visit_class(&mut self, class: &'ast Class) {
+        walk_class(self, class)
+    }
+
+    fn visit_member(&mut self, member: &'ast Member) {
+        walk_member(self, member)
+    }
+
+    fn visit_method(&mut self, method: &'ast Method) {
+        walk_method(self, method)
+    }
+
+    fn visit_property(&mut self, property: &'ast Property) {
+        walk_property(self, property)
+    }
+
+    fn visit_property_hook(&mut self, hook: &'ast PropertyHook) {
+        walk_property_hook(self, hook)
+    }
+
+    fn visit_enum_case(&mut self, case: &'ast EnumCase) {
+        walk_enum_case(self, case)
+    }
+
+    fn visit_const(&mut self, constant: &'ast Const) {
+        walk_const(self, constant)
+    }
+
+    fn visit_use(&mut self, _use: &'ast Use) {}
+
+    fn visit_namespace(&mut self, namespace: &'ast Namespace) {
+        walk_namespace(self, namespace)
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_type(&mut self, _ty: &'ast Spanned<Type>) {}
+
+    fn visit_expr(&mut self, expr: &'ast Spanned<Expr>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_switch_case(&mut self, case: &'ast SwitchCase) {
+        walk_switch_case(self, case)
+    }
+}
+
+pub fn walk_items<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, items: &'ast [Item]) {
+    for item in items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, item: &'ast Item) {
+    match item {
+        Item::Func(func) => visitor.visit_func(func),
+        Item::Class(class) => visitor.visit_class(class),
+        Item::Const(constant) => visitor.visit_const(constant),
+        Item::Use(use_) => visitor.visit_use(use_),
+        Item::Namespace(namespace) => visitor.visit_namespace(namespace),
+        Item::Stmt(stmt) => visitor.visit_expr(stmt),
+    }
+}
+
+pub fn walk_func<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, func: &'ast Func) {
+    func.args.iter().for_each(|param| visitor.visit_param(param));
+    func.ret.iter().for_each(|ty| visitor.visit_type(ty));
+    visitor.visit_expr(&func.body);
+}
+
+pub fn walk_class<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, class: &'ast Class) {
+    class.backing.iter().for_each(|ty| visitor.visit_type(ty));
+    class.members.iter().for_each(|member| visitor.visit_member(member));
+}
+
+pub fn walk_member<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, member: &'ast Member) {
+    match member {
+        Member::Method(method) => visitor.visit_method(method),
+        Member::Property(property) => visitor.visit_property(property),
+        Member::Const(constant) => visitor.visit_const(constant),
+        Member::TraitUse(_) => {}
+        Member::Case(case) => visitor.visit_enum_case(case),
+    }
+}
+
+pub fn walk_method<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, method: &'ast Method) {
+    method.args.iter().for_each(|param| visitor.visit_param(param));
+    method.ret.iter().for_each(|ty| visitor.visit_type(ty));
+    method.body.iter().for_each(|body| visitor.visit_expr(body));
+}
+
+pub fn walk_property<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, property: &'ast Property) {
+    property.ty.iter().for_each(|ty| visitor.visit_type(ty));
+    property.default.iter().for_each(|default| visitor.visit_expr(default));
+    property.hooks.iter().for_each(|hook| visitor.visit_property_hook(hook));
+}
+
+pub fn walk_property_hook<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    hook: &'ast PropertyHook,
+) {
+    hook.args.iter().for_each(|param| visitor.visit_param(param));
+    hook.body.iter().for_each(|body| visitor.visit_expr(body));
+}
+
+pub fn walk_enum_case<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, case: &'ast EnumCase) {
+    case.value.iter().for_each(|value| visitor.visit_expr(value));
+}
+
+pub fn walk_const<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, constant: &'ast Const) {
+    visitor.visit_expr(&constant.value);
+}
+
+pub fn walk_namespace<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    namespace: &'ast Namespace,
+) {
+    walk_items(visitor, &namespace.items);
+}
+
+pub fn walk_param<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, param: &'ast Param) {
+    param.ty.iter().for_each(|ty| visitor.visit_type(ty));
+    param.default.iter().for_each(|default| visitor.visit_expr(default));
+}
+
+pub fn walk_match_arm<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, arm: &'ast MatchArm) {
+    arm.conditions.iter().flatten().for_each(|condition| visitor.visit_expr(condition));
+    visitor.visit_expr(&arm.body);
+}
+
+pub fn walk_switch_case<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, case: &'ast SwitchCase) {
+    case.test.iter().chain(&case.body).for_each(|expr| visitor.visit_expr(expr));
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, (expr, _): &'ast Spanned<Expr>) {
+    let mut exprs = |exprs: &mut dyn Iterator<Item = &'ast Spanned<Expr>>| {
+        exprs.for_each(|expr| visitor.visit_expr(expr))
+    };
+    match expr {
+        Expr::Error
+        | Expr::Value(_)
+        | Expr::Local(_)
+        | Expr::Name(_)
+        | Expr::Global(_)
+        | Expr::Goto(_)
+        | Expr::Label(_)
+        | Expr::HaltCompiler
+        | Expr::InlineHtml(_) => {}
+        Expr::List(items) | Expr::Echo(items) | Expr::Unset(items) | Expr::Isset(items) => {
+            exprs(&mut items.iter())
+        }
+        Expr::Var(_, a, b)
+        | Expr::Then(a, b)
+        | Expr::Binary(a, _, b)
+        | Expr::Assign(a, b)
+        | Expr::While(a, b) => exprs(&mut [&**a, b].into_iter()),
+        Expr::Call(f, (args, _)) => exprs(&mut [&**f].into_iter().chain(args)),
+        Expr::If(cond, then, else_) => exprs(&mut [&**cond, then, else_].into_iter()),
+        Expr::Print(expr)
+        | Expr::Empty(expr)
+        | Expr::Include(_, expr)
+        | Expr::Unary(_, expr)
+        | Expr::Prop(expr, _)
+        | Expr::StaticProp(expr, _)
+        | Expr::ClassConst(expr, _)
+        | Expr::NullsafeProp(expr, _) => visitor.visit_expr(expr),
+        Expr::Return(expr) | Expr::Exit(expr) | Expr::Break(expr) | Expr::Continue(expr) => {
+            exprs(&mut expr.as_deref().into_iter())
+        }
+        Expr::Static(vars) => exprs(&mut vars.iter().filter_map(|(_, value)| value.as_ref())),
+        Expr::Declare(directives) => exprs(&mut directives.iter().map(|(_, value)| value)),
+        Expr::For(init, cond, step, body) => {
+            exprs(&mut init.iter().chain(cond).chain(step).chain([&**body]))
+        }
+        Expr::Foreach(subject, key, value, body) => {
+            exprs(&mut [&**subject].into_iter().chain(key.as_deref()).chain([&**value, body]))
+        }
+        Expr::Switch(subject, cases) => {
+            visitor.visit_expr(subject);
+            cases.iter().for_each(|case| visitor.visit_switch_case(case));
+        }
+        Expr::New(class, args) => {
+            exprs(&mut [&**class].into_iter().chain(args.iter().flat_map(|(args, _)| args)))
+        }
+        Expr::Match(subject, arms) => {
+            visitor.visit_expr(subject);
+            arms.iter().for_each(|arm| visitor.visit_match_arm(arm));
+        }
+        Expr::Closure(args, _, ret, body) => {
+            args.iter().for_each(|param| visitor.visit_param(param));
+            ret.iter().for_each(|ty| visitor.visit_type(ty));
+            visitor.visit_expr(body);
+        }
+        Expr::ArrowFn(args, ret, body) => {
+            args.iter().for_each(|param| visitor.visit_param(param));
+            ret.iter().for_each(|ty| visitor.visit_type(ty));
+            visitor.visit_expr(body);
+        }
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item)
+    }
+
+    fn visit_func_mut(&mut self, func: &mut Func) {
+        walk_func_mut(self, func)
+    }
+
+    fn visit_class_mut(&mut self, class: &mut Class) {
+        walk_class_mut(self, class)
+    }
+
+    fn visit_member_mut(&mut self, member: &mut Member) {
+        walk_member_mut(self, member)
+    }
+
+    fn visit_method_mut(&mut self, method: &mut Method) {
+        walk_method_mut(self, method)
+    }
+
+    fn visit_property_mut(&mut self, property: &mut Property) {
+        walk_property_mut(self, property)
+    }
+
+    fn visit_property_hook_mut(&mut self, hook: &mut PropertyHook) {
+        walk_property_hook_mut(self, hook)
+    }
+
+    fn visit_enum_case_mut(&mut self, case: &mut EnumCase) {
+        walk_enum_case_mut(self, case)
+    }
+
+    fn visit_const_mut(&mut self, constant: &mut Const) {
+        walk_const_mut(self, constant)
+    }
+
+    fn visit_use_mut(&mut self, _use: &mut Use) {}
+
+    fn visit_namespace_mut(&mut self, namespace: &mut Namespace) {
+        walk_namespace_mut(self, namespace)
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        walk_param_mut(self, param)
+    }
+
+    fn visit_type_mut(&mut self, _ty: &mut Spanned<Type>) {}
+
+    fn visit_expr_mut(&mut self, expr: &mut Spanned<Expr>) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm)
+    }
+
+    fn visit_switch_case_mut(&mut self, case: &mut SwitchCase) {
+        walk_switch_case_mut(self, case)
+    }
+}
+
+pub fn walk_items_mut<V: VisitorMut + ?Sized>(visitor: &mut V, items: &mut [Item]) {
+    for item in items {
+        visitor.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(visitor: &mut V, item: &mut Item) {
+    match item {
+        Item::Func(func) => visitor.visit_func_mut(func),
+        Item::Class(class) => visitor.visit_class_mut(class),
+        Item::Const(constant) => visitor.visit_const_mut(constant),
+        Item::Use(use_) => visitor.visit_use_mut(use_),
+        Item::Namespace(namespace) => visitor.visit_namespace_mut(namespace),
+        Item::Stmt(stmt) => visitor.visit_expr_mut(stmt),
+    }
+}
+
+pub fn walk_func_mut<V: VisitorMut + ?Sized>(visitor: &mut V, func: &mut Func) {
+    func.args.iter_mut().for_each(|param| visitor.visit_param_mut(param));
+    func.ret.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+    visitor.visit_expr_mut(&mut func.body);
+}
+
+pub fn walk_class_mut<V: VisitorMut + ?Sized>(visitor: &mut V, class: &mut Class) {
+    class.backing.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+    class.members.iter_mut().for_each(|member| visitor.visit_member_mut(member));
+}
+
+pub fn walk_member_mut<V: VisitorMut + ?Sized>(visitor: &mut V, member: &mut Member) {
+    match member {
+        Member::Method(method) => visitor.visit_method_mut(method),
+        Member::Property(property) => visitor.visit_property_mut(property),
+        Member::Const(constant) => visitor.visit_const_mut(constant),
+        Member::TraitUse(_) => {}
+        Member::Case(case) => visitor.visit_enum_case_mut(case),
+    }
+}
+
+pub fn walk_method_mut<V: VisitorMut + ?Sized>(visitor: &mut V, method: &mut Method) {
+    method.args.iter_mut().for_each(|param| visitor.visit_param_mut(param));
+    method.ret.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+    method.body.iter_mut().for_each(|body| visitor.visit_expr_mut(body));
+}
+
+pub fn walk_property_mut<V: VisitorMut + ?Sized>(visitor: &mut V, property: &mut Property) {
+    property.ty.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+    property.default.iter_mut().for_each(|default| visitor.visit_expr_mut(default));
+    property.hooks.iter_mut().for_each(|hook| visitor.visit_property_hook_mut(hook));
+}
+
+pub fn walk_property_hook_mut<V: VisitorMut + ?Sized>(visitor: &mut V, hook: &mut PropertyHook) {
+    hook.args.iter_mut().for_each(|param| visitor.visit_param_mut(param));
+    hook.body.iter_mut().for_each(|body| visitor.visit_expr_mut(body));
+}
+
+pub fn walk_enum_case_mut<V: VisitorMut + ?Sized>(visitor: &mut V, case: &mut EnumCase) {
+    case.value.iter_mut().for_each(|value| visitor.visit_expr_mut(value));
+}
+
+pub fn walk_const_mut<V: VisitorMut + ?Sized>(visitor: &mut V, constant: &mut Const) {
+    visitor.visit_expr_mut(&mut constant.value);
+}
+
+pub fn walk_namespace_mut<V: VisitorMut + ?Sized>(visitor: &mut V, namespace: &mut Namespace) {
+    walk_items_mut(visitor, &mut namespace.items);
+}
+
+pub fn walk_param_mut<V: VisitorMut + ?Sized>(visitor: &mut V, param: &mut Param) {
+    param.ty.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+    param.default.iter_mut().for_each(|default| visitor.visit_expr_mut(default));
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut + ?Sized>(visitor: &mut V, arm: &mut MatchArm) {
+    arm.conditions
+        .iter_mut()
+        .flatten()
+        .for_each(|condition| visitor.visit_expr_mut(condition));
+    visitor.visit_expr_mut(&mut arm.body);
+}
+
+pub fn walk_switch_case_mut<V: VisitorMut + ?Sized>(visitor: &mut V, case: &mut SwitchCase) {
+    case.test
+        .iter_mut()
+        .chain(&mut case.body)
+        .for_each(|expr| visitor.visit_expr_mut(expr));
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, (expr, _): &mut Spanned<Expr>) {
+    let mut exprs = |exprs: &mut dyn Iterator<Item = &mut Spanned<Expr>>| {
+        exprs.for_each(|expr| visitor.visit_expr_mut(expr))
+    };
+    match expr {
+        Expr::Error
+        | Expr::Value(_)
+        | Expr::Local(_)
+        | Expr::Name(_)
+        | Expr::Global(_)
+        | Expr::Goto(_)
+        | Expr::Label(_)
+        | Expr::HaltCompiler
+        | Expr::InlineHtml(_) => {}
+        Expr::List(items) | Expr::Echo(items) | Expr::Unset(items) | Expr::Isset(items) => {
+            exprs(&mut items.iter_mut())
+        }
+        Expr::Var(_, a, b)
+        | Expr::Then(a, b)
+        | Expr::Binary(a, _, b)
+        | Expr::Assign(a, b)
+        | Expr::While(a, b) => exprs(&mut [&mut **a, b].into_iter()),
+        Expr::Call(f, (args, _)) => exprs(&mut [&mut **f].into_iter().chain(args)),
+        Expr::If(cond, then, else_) => exprs(&mut [&mut **cond, then, else_].into_iter()),
+        Expr::Print(expr)
+        | Expr::Empty(expr)
+        | Expr::Include(_, expr)
+        | Expr::Unary(_, expr)
+        | Expr::Prop(expr, _)
+        | Expr::StaticProp(expr, _)
+        | Expr::ClassConst(expr, _)
+        | Expr::NullsafeProp(expr, _) => visitor.visit_expr_mut(expr),
+        Expr::Return(expr) | Expr::Exit(expr) | Expr::Break(expr) | Expr::Continue(expr) => {
+            exprs(&mut expr.as_deref_mut().into_iter())
+        }
+        Expr::Static(vars) => exprs(&mut vars.iter_mut().filter_map(|(_, value)| value.as_mut())),
+        Expr::Declare(directives) => exprs(&mut directives.iter_mut().map(|(_, value)| value)),
+        Expr::For(init, cond, step, body) => {
+            exprs(&mut init.iter_mut().chain(cond).chain(step).chain([&mut **body]))
+        }
+        Expr::Foreach(subject, key, value, body) => exprs(
+            &mut [&mut **subject].into_iter().chain(key.as_deref_mut()).chain([&mut **value, body]),
+        ),
+        Expr::Switch(subject, cases) => {
+            visitor.visit_expr_mut(subject);
+            cases.iter_mut().for_each(|case| visitor.visit_switch_case_mut(case));
+        }
+        Expr::New(class, args) => {
+            exprs(&mut [&mut **class].into_iter().chain(args.iter_mut().flat_map(|(args, _)| args)))
+        }
+        Expr::Match(subject, arms) => {
+            visitor.visit_expr_mut(subject);
+            arms.iter_mut().for_each(|arm| visitor.visit_match_arm_mut(arm));
+        }
+        Expr::Closure(args, _, ret, body) => {
+            args.iter_mut().for_each(|param| visitor.visit_param_mut(param));
+            ret.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+            visitor.visit_expr_mut(body);
+        }
+        Expr::ArrowFn(args, ret, body) => {
+            args.iter_mut().for_each(|param| visitor.visit_param_mut(param));
+            ret.iter_mut().for_each(|ty| visitor.visit_type_mut(ty));
+            visitor.visit_expr_mut(body);
+        }
+    }
+}
+
+/// A node of the AST, as found by [`node_at`].
+#[derive(Clone, Copy, Debug)]
+pub enum Node<'ast> {
+    Func(&'ast Func),
+    Class(&'ast Class),
+    Method(&'ast Method),
+    Property(&'ast Property),
+    PropertyHook(&'ast PropertyHook),
+    EnumCase(&'ast EnumCase),
+    Const(&'ast Const),
+    /// A `use Name;` of a trait
+    TraitUse(&'ast Spanned<Symbol>),
+    Use(&'ast Use),
+    Namespace(&'ast Namespace),
+    Param(&'ast Param),
+    Type(&'ast Spanned<Type>),
+    Expr(&'ast Spanned<Expr>),
+    MatchArm(&'ast MatchArm),
+    SwitchCase(&'ast SwitchCase),
+}
+
+impl Node<'_> {
+    pub fn span(&self) -> &Span {
+        match self {
+            Node::Func(func) => &func.span,
+            Node::Class(class) => &class.span,
+            Node::Method(method) => &method.span,
+            Node::Property(property) => &property.span,
+            Node::PropertyHook(hook) => &hook.span,
+            Node::EnumCase(case) => &case.span,
+            Node::Const(constant) => &constant.span,
+            Node::TraitUse((_, span)) => span,
+            Node::Use(use_) => &use_.span,
+            Node::Namespace(namespace) => &namespace.span,
+            Node::Param(param) => &param.span,
+            Node::Type((_, span)) => span,
+            Node::Expr((_, span)) => span,
+            Node::MatchArm(arm) => &arm.span,
+            Node::SwitchCase(case) => &case.span,
+        }
+    }
+}
+
+/// Returns the nodes containing the character `offset`, from the outermost to the innermost.
+pub fn node_at(items: &[Item], offset: usize) -> Vec<Node<'_>> {
+    let mut finder = NodeAt {
+        offset,
+        depth: 0,
+        chain: Vec::new(),
+    };
+    walk_items(&mut finder, items);
+    finder.chain
+}
+
+struct NodeAt<'ast> {
+    offset: usize,
+    depth: usize,
+    chain: Vec<Node<'ast>>,
+}
+
+impl<'ast> NodeAt<'ast> {
+    /// Adds `node` to the chain and walks it with `walk` when it contains the offset.
+    fn enter<T: ?Sized>(&mut self, node: Node<'ast>, walk: fn(&mut Self, &'ast T), inner: &'ast T) {
+        let span = node.span();
+        // Siblings sharing a span, as properties of one declaration, only match once
+        if self.depth < self.chain.len() || !(span.start <= self.offset && self.offset < span.end) {
+            return;
+        }
+        self.chain.push(node);
+        self.depth += 1;
+        walk(self, inner);
+        self.depth -= 1;
+    }
+}
+
+impl<'ast> Visitor<'ast> for NodeAt<'ast> {
+    fn visit_func(&mut self, func: &'ast Func) {
+        self.enter(Node::Func(func), walk_func, func)
+    }
+
+    fn visit_class(&mut self, class: &'ast Class) {
+        self.enter(Node::Class(class), walk_class, class)
+    }
+
+    fn visit_member(&mut self, member: &'ast Member) {
+        match member {
+            Member::TraitUse(name) => self.enter(Node::TraitUse(name), |_, _| {}, name),
+            member => walk_member(self, member),
+        }
+    }
+
+    fn visit_method(&mut self, method: &'ast Method) {
+        self.enter(Node::Method(method), walk_method, method)
+    }
+
+    fn visit_property(&mut self, property: &'ast Property) {
+        self.enter(Node::Property(property), walk_property, property)
+    }
+
+    fn visit_property_hook(&mut self, hook: &'ast PropertyHook) {
+        self.enter(Node::PropertyHook(hook), walk_property_hook, hook)
+    }
+
+    fn visit_enum_case(&mut self, case: &'ast EnumCase) {
+        self.enter(Node::EnumCase(case), walk_enum_case, case)
+    }
+
+    fn visit_const(&mut self, constant: &'ast Const) {
+        self.enter(Node::Const(constant), walk_const, constant)
+    }
+
+    fn visit_use(&mut self, use_: &'ast Use) {
+        self.enter(Node::Use(use_), |_, _| {}, use_)
+    }
+
+    fn visit_namespace(&mut self, namespace: &'ast Namespace) {
+        self.enter(Node::Namespace(namespace), walk_namespace, namespace)
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.enter(Node::Param(param), walk_param, param)
+    }
+
+    fn visit_type(&mut self, ty: &'ast Spanned<Type>) {
+        self.enter(Node::Type(ty), |_, _| {}, ty)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Spanned<Expr>) {
+        self.enter(Node::Expr(expr), walk_expr, expr)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        self.enter(Node::MatchArm(arm), walk_match_arm, arm)
+    }
+
+    fn visit_switch_case(&mut self, case: &'ast SwitchCase) {
+        self.enter(Node::SwitchCase(case), walk_switch_case, case)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parser;
+    use crate::version::PhpVersion;
+
+    #[test]
+    fn test_node_at_returns_innermost_chain() {
+        let src = r#"<?php
+            class Greeter {
+                public $greeting = 'Hello', $name;
+
+                public function greet(string $name) {
+                    echo $this->greeting . $name;
+                }
+            }
+        "#;
+        let ast = parser(src, PhpVersion::default()).ast.unwrap();
+
+        let offset = src.find("greeting .").unwrap();
+        let chain = node_at(&ast, offset)
+            .iter()
+            .map(|node| match node {
+                Node::Class(_) => "class".to_string(),
+                Node::Method(method) => format!("method {}", method.name.0),
+                Node::Expr((Expr::Then(..), _)) => "then".to_string(),
+                Node::Expr((Expr::Echo(_), _)) => "echo".to_string(),
+                Node::Expr((Expr::Binary(..), _)) => "binary".to_string(),
+                Node::Expr((Expr::Prop(_, name), _)) => format!("prop {}", name.0),
+                node => format!("{:?}", node),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chain,
+            [
+                "class",
+                "method greet",
+                "then",
+                "echo",
+                "binary",
+                "prop greeting"
+            ]
+        );
+
+        // Properties sharing a declaration only match once
+        let offset = src.find("public $greeting").unwrap();
+        let chain = node_at(&ast, offset);
+        assert!(
+            matches!(chain[..], [Node::Class(_), Node::Property(property)] if property.name.0 == "greeting")
+        );
+
+        assert!(node_at(&ast, 0).is_empty());
+    }
+
+    #[test]
+    fn test_visitor_mut_walks_every_expression() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_expr_mut(&mut self, expr: &mut Spanned<Expr>) {
+                if let Expr::Local((name, _)) | Expr::Var((name, _), ..) = &mut expr.0 {
+                    *name = name.to_uppercase().into();
+                }
+                walk_expr_mut(self, expr);
+            }
+        }
+
+        struct Locals(Vec<String>);
+
+        impl Visitor<'_> for Locals {
+            fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+                if let Expr::Local((name, _)) | Expr::Var((name, _), ..) = &expr.0 {
+                    self.0.push(name.to_string());
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        let src = "<?php $a = 1; function f($b = 2) { return fn() => $a + $b; }";
+        let mut ast = parser(src, PhpVersion::default()).ast.unwrap();
+        walk_items_mut(&mut Rename, &mut ast);
+
+        let mut locals = Locals(Vec::new());
+        walk_items(&mut locals, &ast);
+        assert_eq!(locals.0, ["A", "A", "B"]);
+    }
+}
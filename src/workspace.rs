@@ -0,0 +1,177 @@
+//! The PHP files of the workspace folders, which are indexed so that names are found in files
+//! that aren't open.
+
+use crate::chumsky::ClassKind;
+use crate::fuzzy;
+use crate::resolve::{Declaration, DeclarationKind};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::SymbolKind;
+
+/// The most symbols a search returns.
+pub const MAX_SYMBOLS: usize = 1000;
+
+/// Directories that hold no PHP, or none worth indexing.
+const SKIPPED: &[&str] = &["node_modules"];
+
+/// The `.php` files under `root`, leaving out hidden directories, like `.git`.
+pub fn php_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            match entry.file_type() {
+                Ok(ty)
+                    if ty.is_dir()
+                        && !name.starts_with('.')
+                        && !SKIPPED.contains(&name.as_ref()) =>
+                {
+                    dirs.push(path)
+                }
+                Ok(ty) if ty.is_file() && path.extension().is_some_and(|ext| ext == "php") => {
+                    files.push(path)
+                }
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Whether `path` is one of the `.php` files under `root` that [`php_files`] finds.
+pub fn is_php_file(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    let mut dirs = relative.parent().into_iter().flat_map(Path::components);
+    path.extension().is_some_and(|ext| ext == "php")
+        && !dirs.any(|dir| {
+            let name = dir.as_os_str().to_string_lossy();
+            name.starts_with('.') || SKIPPED.contains(&name.as_ref())
+        })
+}
+
+/// Whether the file at `uri` belongs to a dependency, as what Composer installs in `vendor/`.
+pub fn is_vendored(uri: &str) -> bool {
+    uri.split('/').any(|segment| segment == "vendor")
+}
+
+/// The declarations of `files` that match `query`, best first. A query with a `\` or a `::` is
+/// matched against fully qualified names, as in `App\Models\User::save`, and any other against
+/// the names themselves.
+pub fn search<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a [Declaration])>,
+    query: &str,
+) -> Vec<(&'a str, &'a Declaration)> {
+    let query = query.trim().trim_start_matches('\\');
+    let qualified = query.contains('\\') || query.contains("::");
+    let mut found = files
+        .into_iter()
+        .flat_map(|(uri, declarations)| declarations.iter().map(move |d| (uri, d)))
+        .filter_map(|(uri, declaration)| {
+            let name = match (qualified, &declaration.class) {
+                (true, Some(class)) => format!("{}::{}", class, declaration.name),
+                (true, None) => declaration.name.clone(),
+                (false, _) => declaration.short_name().to_string(),
+            };
+            let score = fuzzy::score(query, &name)?;
+            Some((Reverse(score), name.len(), uri, declaration))
+        })
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| (a.0, a.1, &a.3.name).cmp(&(b.0, b.1, &b.3.name)));
+    found.truncate(MAX_SYMBOLS);
+    found.into_iter().map(|(_, _, uri, declaration)| (uri, declaration)).collect()
+}
+
+/// The name of a declaration as a symbol: the name it's known by, with the `$` of a property.
+pub fn symbol_name(declaration: &Declaration) -> String {
+    match declaration.kind {
+        DeclarationKind::Property => format!("${}", declaration.name),
+        _ => declaration.short_name().to_string(),
+    }
+}
+
+/// What a symbol is in: the class of a member, or the namespace of anything else.
+pub fn container_name(declaration: &Declaration) -> Option<String> {
+    match &declaration.class {
+        Some(class) => Some(class.clone()),
+        None => declaration.name.rsplit_once('\\').map(|(namespace, _)| namespace.to_string()),
+    }
+}
+
+pub fn symbol_kind(declaration: &Declaration) -> SymbolKind {
+    match declaration.kind {
+        DeclarationKind::Function => SymbolKind::FUNCTION,
+        DeclarationKind::Class(ClassKind::Interface) => SymbolKind::INTERFACE,
+        DeclarationKind::Class(ClassKind::Enum) => SymbolKind::ENUM,
+        DeclarationKind::Class(_) => SymbolKind::CLASS,
+        DeclarationKind::Constant | DeclarationKind::ClassConstant => SymbolKind::CONSTANT,
+        DeclarationKind::Method if declaration.name.eq_ignore_ascii_case("__construct") => {
+            SymbolKind::CONSTRUCTOR
+        }
+        DeclarationKind::Method => SymbolKind::METHOD,
+        DeclarationKind::Property => SymbolKind::PROPERTY,
+        DeclarationKind::EnumCase => SymbolKind::ENUM_MEMBER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn test_search_ranks_and_qualifies() {
+        let app = Document::new(
+            "<?php\nnamespace App\\Http;\n\nclass UserController { public function store() {} }\n",
+        );
+        let models = Document::new(
+            "<?php\nnamespace App\\Models;\n\nclass User { public $name; }\nfunction user_count() {}\n",
+        );
+        let (app_declarations, models_declarations) = (app.declarations(), models.declarations());
+        let files = [
+            ("file:///app.php", app_declarations.as_slice()),
+            ("file:///models.php", models_declarations.as_slice()),
+        ];
+        let names = |query: &str| {
+            let found = search(files, query).into_iter();
+            found.map(|(_, declaration)| symbol_name(declaration)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("UsrCtrl"), ["UserController"]);
+        assert_eq!(names("user"), ["User", "user_count", "UserController"]);
+        assert_eq!(names("App\\Models\\User")[..2], ["User", "user_count"]);
+        assert_eq!(names("\\App\\Http\\UserController::store"), ["store"]);
+        assert_eq!(names("Http\\User"), ["UserController", "store"]);
+        assert_eq!(names("name"), ["$name"]);
+
+        let store = search(files, "store")[0].1;
+        assert_eq!(symbol_kind(store), SymbolKind::METHOD);
+        assert_eq!(
+            container_name(store).as_deref(),
+            Some("App\\Http\\UserController")
+        );
+    }
+
+    #[test]
+    fn test_php_files_leave_out_hidden_and_skipped_directories() {
+        let root = Path::new("/project");
+        assert!(is_php_file(root, Path::new("/project/src/User.php")));
+        assert!(is_php_file(root, Path::new("/project/.env.php")));
+        assert!(!is_php_file(root, Path::new("/project/src/User.txt")));
+        assert!(!is_php_file(root, Path::new("/project/.git/hooks/x.php")));
+        assert!(!is_php_file(
+            root,
+            Path::new("/project/node_modules/a/b.php")
+        ));
+        assert!(!is_php_file(root, Path::new("/elsewhere/User.php")));
+    }
+}
@@ -1,11 +1,12 @@
 use phantom_language_server::chumsky::parser;
+use phantom_language_server::version::PhpVersion;
 
 #[test]
 fn test_parser() {
     let source = std::fs::read_to_string("examples/sem_erros.php").unwrap();
-    let result = parser(&source);
+    let result = parser(&source, PhpVersion::default());
 
     dbg!(result);
 
     // println!("{:?}", result);
-}
\ No newline at end of file
+}
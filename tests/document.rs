@@ -0,0 +1,177 @@
+use phantom_language_server::document::Document;
+use phantom_language_server::version::PhpVersion;
+
+const NAMESPACED: &str = r#"<?php
+namespace App\Blog;
+
+use App\Models\{User, Post};
+
+const LIMIT = 10;
+
+function total(array $items): int
+{
+    $sum = 0;
+    foreach ($items as $item) {
+        $sum = $sum + $item->price;
+    }
+    return $sum;
+}
+
+/* Users that can post */
+final class Author extends User
+{
+    private array $posts = [];
+
+    public function add(Post $post): void
+    {
+        $this->posts = [$post];
+        echo "added $post->title", 'ok';
+    }
+
+    public function count(): int
+    {
+        return count($this->posts); // all of them
+    }
+}
+
+$author = new Author();
+$author->add(new Post());
+"#;
+
+const GLOBAL: &str = r#"<?php
+$x = 1;
+echo $x - -1;
+
+function greet($name) {
+    if ($name) {
+        return "Hello, " . $name;
+    }
+    return null;
+}
+
+# Greets twice
+echo greet('you');
+echo greet(strtoupper('you'));
+
+class Greeter {
+    const HELLO = 'hello';
+    public static function hello() { return self::HELLO; }
+}
+"#;
+
+const BRACED: &str = r#"<?php
+namespace Shop {
+    class Cart {
+        public function add($item) { $this->items = [$item]; }
+    }
+
+    function cart() { return new Cart(); }
+}
+
+namespace {
+    $cart = \Shop\cart();
+    $cart->add(1);
+}
+"#;
+
+/// The errors of a document, in an order and form that don't depend on how it was parsed.
+fn errors(document: &Document) -> Vec<impl Ord + std::fmt::Debug> {
+    let mut errors = document
+        .errors(PhpVersion::default())
+        .into_iter()
+        .map(|error| {
+            let mut expected = error.expected().cloned().collect::<Vec<_>>();
+            expected.sort();
+            (
+                (error.span().start, error.span().end),
+                format!("{:?}", error.reason()),
+                error.found().cloned(),
+                expected,
+                error.label(),
+            )
+        })
+        .collect::<Vec<_>>();
+    errors.sort();
+    errors
+}
+
+/// Asserts that a document edited in place is the same as `full`, parsed from scratch.
+fn assert_same(document: &Document, full: &Document, edit: &str) {
+    assert_eq!(document.tokens(), full.tokens(), "tokens after {}", edit);
+    assert_eq!(
+        format!("{:?}", document.items()),
+        format!("{:?}", full.items()),
+        "items after {}",
+        edit
+    );
+    assert_eq!(errors(document), errors(full), "errors after {}", edit);
+}
+
+/// Makes each edit at every few characters of `src`, then undoes it, checking the document
+/// against a full parse after each.
+fn assert_edits_reparse_in_full(src: &str) {
+    // Edits that leave something open are parsed in full, so they're made at fewer places
+    let edits = [(9, 0, "x"), (9, 0, "$y = 1;"), (9, 1, ""), (9, 3, "")].into_iter().chain([
+        (29, 0, "}"),
+        (29, 0, "'"),
+        (29, 0, "/*"),
+        (29, 2, "("),
+    ]);
+    let original = Document::new(src);
+    let mut document = Document::new(src);
+    let len = src.chars().count();
+
+    for (step, deleted, text) in edits {
+        // Typing at the very end, as much as anywhere else
+        for start in (0..len).step_by(step).chain([len]) {
+            let range = start..(start + deleted).min(len);
+            let removed = src.chars().skip(start).take(range.len()).collect::<String>();
+            let edit = format!("replacing {:?} ({:?}) with {:?}", range, removed, text);
+
+            document.edit(range.clone(), text);
+            let full = Document::new(&document.text().to_string());
+            assert_same(&document, &full, &edit);
+
+            document.edit(start..start + text.chars().count(), &removed);
+            assert_same(&document, &original, &format!("undoing {}", edit));
+        }
+    }
+}
+
+#[test]
+fn test_edits_in_a_namespace_reparse_like_a_full_parse() {
+    assert_edits_reparse_in_full(NAMESPACED);
+}
+
+#[test]
+fn test_edits_in_the_global_namespace_reparse_like_a_full_parse() {
+    assert_edits_reparse_in_full(GLOBAL);
+}
+
+#[test]
+fn test_edits_in_braced_namespaces_reparse_like_a_full_parse() {
+    assert_edits_reparse_in_full(BRACED);
+}
+
+#[test]
+fn test_typing_reparses_like_a_full_parse() {
+    // Both are ASCII, so byte offsets are character offsets
+    let method =
+        "\n    private function first(): ?Post\n    {\n        return $this->posts;\n    }\n";
+    let at = NAMESPACED.rfind("\n}\n").unwrap();
+
+    let mut document = Document::new(NAMESPACED);
+    for (i, c) in method.char_indices() {
+        document.edit(at + i..at + i, &c.to_string());
+        assert_same(
+            &document,
+            &Document::new(&document.text().to_string()),
+            &format!("typing {:?}", &method[..i + c.len_utf8()]),
+        );
+    }
+    assert!(errors(&document).is_empty());
+
+    // Then replaced by what the client sends in full
+    document.replace(NAMESPACED);
+    assert_same(&document, &Document::new(NAMESPACED), "replacing the text");
+}
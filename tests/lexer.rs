@@ -0,0 +1,80 @@
+use phantom_language_server::chumsky::lex;
+use phantom_language_server::document::Document;
+use phantom_language_server::lexer::{self, Lexeme};
+
+/// Tokens and errors that the two lexers should agree on, the spans in characters.
+const SNIPPETS: &[&str] = &[
+    "<?php $a = 1.5 + 0.25 - 007 . '1.' ;",
+    "<?php echo \"a\\\"b\" . 'it''s';",
+    "<?php \\App\\Models\\User::find(1)?->name ?? App\\ . \\ $$x;",
+    "<?php ECHO TRUE; If (NULL) { fn() => 1; } __halt_compiler ( ) ; <?php raw",
+    "<?php __HALT_COMPILER(); $a; __halt_compiler(1); __halt_compilerx;",
+    "<?php $a+//c\n=/* x */-/*y*/1; # end ?>\n<b>é</b><?= $b ?>\r\n<?php",
+    "<?php /* unclosed ",
+    "<?php $a = @$b; ` €x; ?> <? <?xml <?=",
+    "<?php $a = 'unclosed ",
+    "<?php $a = \"unclosed ; @ $b",
+    "html only",
+    "<?php\u{a0}$x\u{2003}=\u{3000}1;",
+];
+
+/// The tokens of both lexers, with spans in characters, and how many errors they found.
+fn lex_both(src: &str) -> ((Vec<String>, usize), (Vec<String>, usize)) {
+    let char_at = |byte: usize| src[..byte].chars().count();
+
+    let (tokens, errors) = lex(src);
+    let expected = (
+        tokens
+            .unwrap()
+            .iter()
+            .map(|(token, span)| format!("{:?} {:?}", token, span))
+            .collect(),
+        errors.len(),
+    );
+
+    let (lexemes, errors) = lexer::lex(src);
+    let actual = (
+        lexemes
+            .iter()
+            .map(|(lexeme, span)| {
+                let span = char_at(span.start)..char_at(span.end);
+                format!("{:?} {:?}", lexeme.to_token(), span)
+            })
+            .collect(),
+        errors.len(),
+    );
+    (expected, actual)
+}
+
+#[test]
+fn test_lexer_lexes_like_the_combinator_lexer() {
+    let examples = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap());
+
+    for src in SNIPPETS.iter().map(|src| src.to_string()).chain(examples) {
+        let (expected, actual) = lex_both(&src);
+        assert_eq!(actual, expected, "lexing {:?}", src);
+    }
+}
+
+#[test]
+fn test_documents_lex_like_the_combinator_lexer() {
+    for src in SNIPPETS {
+        let document = Document::new(src);
+        let (tokens, _) = lex(src);
+        assert_eq!(document.tokens(), tokens.unwrap(), "lexing {:?}", src);
+    }
+}
+
+#[test]
+fn test_lexer_borrows_from_the_source() {
+    let src = "<?php $name = 'phantom';";
+    let (tokens, _) = lexer::lex(src);
+
+    let Lexeme::Str(_, text) = tokens[4].0 else {
+        panic!("expected a string, got {:?}", tokens[4]);
+    };
+    assert_eq!(text, "phantom");
+    assert!(src.as_bytes().as_ptr_range().contains(&text.as_ptr()));
+}
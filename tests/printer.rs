@@ -0,0 +1,76 @@
+use phantom_language_server::chumsky::{parser, Item};
+use phantom_language_server::printer::print;
+use phantom_language_server::version::PhpVersion;
+
+/// The AST without its spans, which change when the source is printed again.
+fn shape(ast: &[Item]) -> String {
+    let debug = format!("{:?}", ast);
+    let mut shape = String::new();
+    let mut chars = debug.chars().peekable();
+    while let Some(c) = chars.next() {
+        shape.push(c);
+        // Spans print as `start..end`
+        if c.is_ascii_digit() {
+            let mut rest = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                rest.push(c);
+            }
+            if rest.contains("..") {
+                shape.pop();
+                shape.push('_');
+            } else {
+                shape.push_str(&rest);
+            }
+        }
+    }
+    shape
+}
+
+#[test]
+fn test_printer_round_trips_over_examples() {
+    let mut paths = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "php"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut checked = 0;
+    for path in paths {
+        let src = std::fs::read_to_string(&path).unwrap();
+        let result = parser(&src, PhpVersion::default());
+        // What was skipped while recovering from errors has no source to print
+        if !result.parse_errors.is_empty() {
+            continue;
+        }
+        let ast = result.ast.unwrap();
+
+        let printed = print(&ast);
+        let result = parser(&printed, PhpVersion::default());
+        assert!(
+            result.parse_errors.is_empty(),
+            "{} printed with errors {:?}:\n{}",
+            path.display(),
+            result.parse_errors,
+            printed
+        );
+        assert_eq!(
+            shape(&ast),
+            shape(&result.ast.unwrap()),
+            "{} doesn't round-trip, printed as:\n{}",
+            path.display(),
+            printed
+        );
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
+
+#[test]
+fn test_strings_print_in_their_quotes() {
+    let src =
+        "<?php\necho \"hi $name\";\necho 'hi $name';\necho \"tab\\t\" . 'it\"s' . \"it's\";\n";
+    let ast = parser(src, PhpVersion::default()).ast.unwrap();
+    assert_eq!(print(&ast), src);
+}
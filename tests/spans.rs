@@ -0,0 +1,426 @@
+use phantom_language_server::chumsky::{
+    parser, Class, Expr, Item, MatchArm, Member, Param, Span, Spanned, SwitchCase, Type,
+};
+use phantom_language_server::symbol::Symbol;
+use phantom_language_server::version::PhpVersion;
+
+/// Checks that every node of an AST lies within its parent, that siblings come in source
+/// order, and that names span exactly their source text.
+struct Checker {
+    src: Vec<char>,
+    errors: Vec<String>,
+}
+
+impl Checker {
+    fn text(&self, span: &Span) -> String {
+        self.src[span.clone()].iter().collect()
+    }
+
+    fn error(&mut self, span: &Span, message: String) {
+        let line = self.src[..span.start].iter().filter(|c| **c == '\n').count() + 1;
+        self.errors.push(format!("line {}: {:?} {}", line, span, message));
+    }
+
+    /// Checks that `spans` are nested in `parent` and don't overlap each other.
+    fn nested(&mut self, spans: &[&Span], parent: &Span) {
+        let mut end = parent.start;
+        for span in spans {
+            if span.start > span.end || span.start < parent.start || span.end > parent.end {
+                self.error(span, format!("is not within {:?}", parent));
+            } else if span.start < end {
+                self.error(span, format!("overlaps a sibling ending at {}", end));
+            }
+            end = end.max(span.end);
+        }
+    }
+
+    fn name(&mut self, (name, span): &Spanned<Symbol>, prefix: &str) {
+        let text = self.text(span);
+        if !text.eq_ignore_ascii_case(&format!("{}{}", prefix, name)) {
+            self.error(span, format!("spans {:?} instead of {:?}", text, name));
+        }
+    }
+
+    fn keyword(&mut self, span: &Span, keywords: &[&str]) {
+        let text = self.text(span).to_ascii_lowercase();
+        if !keywords.iter().any(|keyword| text.starts_with(keyword)) {
+            self.error(
+                span,
+                format!("starts with {:?} instead of {:?}", text, keywords),
+            );
+        }
+    }
+
+    fn items(&mut self, items: &[Item], parent: &Span) {
+        // Items sharing a declaration, as in `use A\{B, C};`, share its span
+        let mut spans = items.iter().map(item_span).collect::<Vec<_>>();
+        spans.dedup();
+        self.nested(&spans, parent);
+
+        for item in items {
+            match item {
+                Item::Func(func) => {
+                    self.keyword(&func.span, &["function"]);
+                    self.name(&func.name, "");
+                    let mut spans = vec![&func.name.1];
+                    spans.extend(func.args.iter().map(|param| &param.span));
+                    spans.extend(func.ret.iter().map(|(_, span)| span));
+                    spans.push(&func.body.1);
+                    self.nested(&spans, &func.span);
+                    self.params(&func.args);
+                    self.ty_opt(&func.ret);
+                    self.expr(&func.body);
+                }
+                Item::Class(class) => self.class(class),
+                Item::Const(constant) => {
+                    self.keyword(&constant.span, &["const"]);
+                    self.name(&constant.name, "");
+                    self.nested(&[&constant.name.1, &constant.value.1], &constant.span);
+                    self.expr(&constant.value);
+                }
+                Item::Use(use_) => {
+                    self.keyword(&use_.span, &["use"]);
+                    let mut spans = vec![&use_.name.1];
+                    spans.extend(use_.alias.iter().map(|(_, span)| span));
+                    self.nested(&spans, &use_.span);
+                }
+                Item::Namespace(namespace) => {
+                    self.keyword(&namespace.span, &["namespace"]);
+                    if let Some(name) = &namespace.name {
+                        self.name(name, "");
+                        self.nested(&[&name.1], &namespace.span);
+                    }
+                    self.items(&namespace.items, &namespace.span);
+                }
+                Item::Stmt(stmt) => self.expr(stmt),
+            }
+        }
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.keyword(
+            &class.span,
+            &[
+                "class",
+                "interface",
+                "trait",
+                "abstract",
+                "final",
+                "readonly",
+                "enum",
+            ],
+        );
+        self.name(&class.name, "");
+        let mut spans = class.modifiers.iter().map(|(_, span)| span).collect::<Vec<_>>();
+        spans.push(&class.name.1);
+        spans.extend(class.backing.iter().map(|(_, span)| span));
+        spans.extend(class.extends.iter().map(|(_, span)| span));
+        spans.extend(class.implements.iter().map(|(_, span)| span));
+        self.nested(&spans, &class.span);
+
+        // Members sharing a declaration, as in `public $a, $b;`, share its span
+        let mut start = class.span.start;
+        for member in &class.members {
+            let span = match member {
+                Member::Method(method) => &method.span,
+                Member::Property(property) => &property.span,
+                Member::Const(constant) => &constant.span,
+                Member::TraitUse((_, span)) => span,
+                Member::Case(case) => &case.span,
+            };
+            if span.start < start || span.end > class.span.end {
+                self.error(span, format!("is out of order in {:?}", class.span));
+            }
+            start = span.start;
+
+            match member {
+                Member::Method(method) => {
+                    self.name(&method.name, "");
+                    let mut spans =
+                        method.modifiers.iter().map(|(_, span)| span).collect::<Vec<_>>();
+                    spans.push(&method.name.1);
+                    spans.extend(method.args.iter().map(|param| &param.span));
+                    spans.extend(method.ret.iter().map(|(_, span)| span));
+                    spans.extend(method.body.iter().map(|(_, span)| span));
+                    self.nested(&spans, &method.span);
+                    self.params(&method.args);
+                    self.ty_opt(&method.ret);
+                    if let Some(body) = &method.body {
+                        self.expr(body);
+                    }
+                }
+                Member::Property(property) => {
+                    self.name(&property.name, "$");
+                    let mut spans =
+                        property.modifiers.iter().map(|(_, span)| span).collect::<Vec<_>>();
+                    spans.extend(property.ty.iter().map(|(_, span)| span));
+                    spans.push(&property.name.1);
+                    spans.extend(property.default.iter().map(|(_, span)| span));
+                    spans.extend(property.hooks.iter().map(|hook| &hook.span));
+                    self.nested(&spans, &property.span);
+                    self.ty_opt(&property.ty);
+                    if let Some(default) = &property.default {
+                        self.expr(default);
+                    }
+                    for hook in &property.hooks {
+                        self.name(&hook.name, "");
+                        let mut spans = vec![&hook.name.1];
+                        spans.extend(hook.args.iter().map(|param| &param.span));
+                        spans.extend(hook.body.iter().map(|(_, span)| span));
+                        self.nested(&spans, &hook.span);
+                        self.params(&hook.args);
+                        if let Some(body) = &hook.body {
+                            self.expr(body);
+                        }
+                    }
+                }
+                Member::Const(constant) => {
+                    self.name(&constant.name, "");
+                    self.nested(&[&constant.value.1], &constant.span);
+                    self.expr(&constant.value);
+                }
+                Member::TraitUse(name) => self.name(name, ""),
+                Member::Case(case) => {
+                    self.keyword(&case.span, &["case"]);
+                    self.name(&case.name, "");
+                    let mut spans = vec![&case.name.1];
+                    spans.extend(case.value.iter().map(|(_, span)| span));
+                    self.nested(&spans, &case.span);
+                    if let Some(value) = &case.value {
+                        self.expr(value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn params(&mut self, params: &[Param]) {
+        for param in params {
+            self.name(&param.name, "$");
+            let mut spans = param.modifiers.iter().map(|(_, span)| span).collect::<Vec<_>>();
+            spans.extend(param.ty.iter().map(|(_, span)| span));
+            spans.push(&param.name.1);
+            spans.extend(param.default.iter().map(|(_, span)| span));
+            self.nested(&spans, &param.span);
+            self.ty_opt(&param.ty);
+            if let Some(default) = &param.default {
+                self.expr(default);
+            }
+        }
+    }
+
+    fn ty_opt(&mut self, ty: &Option<Spanned<Type>>) {
+        if let Some((ty, span)) = ty {
+            self.ty(ty, span);
+        }
+    }
+
+    fn ty(&mut self, ty: &Type, parent: &Span) {
+        match ty {
+            Type::Named(name) => {
+                self.nested(&[&name.1], parent);
+                self.name(name, "");
+            }
+            Type::Nullable(ty) => self.ty(ty, parent),
+            Type::Union(types) | Type::Intersection(types) => {
+                for ty in types {
+                    self.ty(ty, parent);
+                }
+            }
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[&Spanned<Expr>], parent: &Span) {
+        let spans = exprs.iter().map(|(_, span)| span).collect::<Vec<_>>();
+        self.nested(&spans, parent);
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, (expr, span): &Spanned<Expr>) {
+        match expr {
+            Expr::Error | Expr::Value(_) | Expr::HaltCompiler | Expr::InlineHtml(_) => {}
+            Expr::Local(name) => {
+                self.name(name, "$");
+                self.nested(&[&name.1], span);
+            }
+            Expr::Name(name) | Expr::Goto(name) | Expr::Label(name) => {
+                self.name(name, "");
+                self.nested(&[&name.1], span);
+            }
+            Expr::List(items) | Expr::Echo(items) | Expr::Unset(items) | Expr::Isset(items) => {
+                self.exprs(&items.iter().collect::<Vec<_>>(), span)
+            }
+            Expr::Var(name, val, body) => {
+                self.name(name, "$");
+                self.nested(&[&name.1], span);
+                self.exprs(&[val, body], &(name.1.end..span.end));
+            }
+            Expr::Then(a, b) | Expr::Binary(a, _, b) | Expr::Assign(a, b) | Expr::While(a, b) => {
+                self.exprs(&[a, b], span)
+            }
+            Expr::Call(f, (args, args_span)) => {
+                self.nested(&[&f.1, args_span], span);
+                self.expr(f);
+                self.exprs(&args.iter().collect::<Vec<_>>(), args_span);
+            }
+            Expr::If(cond, then, else_) => self.exprs(&[cond, then, else_], span),
+            Expr::Print(expr) | Expr::Empty(expr) | Expr::Include(_, expr) => {
+                self.exprs(&[expr], span)
+            }
+            Expr::Unary(_, expr) => self.exprs(&[expr], span),
+            Expr::Return(expr) | Expr::Exit(expr) | Expr::Break(expr) | Expr::Continue(expr) => {
+                self.exprs(&expr.iter().map(|expr| &**expr).collect::<Vec<_>>(), span)
+            }
+            Expr::Global(names) => {
+                self.nested(
+                    &names.iter().map(|(_, span)| span).collect::<Vec<_>>(),
+                    span,
+                );
+                for name in names {
+                    self.name(name, "$");
+                }
+            }
+            Expr::Static(vars) => {
+                for (name, default) in vars {
+                    self.name(name, "$");
+                    let mut spans = vec![&name.1];
+                    spans.extend(default.iter().map(|(_, span)| span));
+                    self.nested(&spans, span);
+                    if let Some(default) = default {
+                        self.expr(default);
+                    }
+                }
+            }
+            Expr::Declare(directives) => {
+                for (name, value) in directives {
+                    self.name(name, "");
+                    self.nested(&[&name.1, &value.1], span);
+                    self.expr(value);
+                }
+            }
+            Expr::For(init, cond, step, body) => {
+                let exprs = init.iter().chain(cond).chain(step).chain([&**body]);
+                self.exprs(&exprs.collect::<Vec<_>>(), span)
+            }
+            Expr::Foreach(subject, key, value, body) => {
+                let exprs = [&**subject].into_iter().chain(key.as_deref()).chain([&**value, body]);
+                self.exprs(&exprs.collect::<Vec<_>>(), span)
+            }
+            Expr::Switch(subject, cases) => {
+                let mut spans = vec![&subject.1];
+                spans.extend(cases.iter().map(|case| &case.span));
+                self.nested(&spans, span);
+                self.expr(subject);
+                for SwitchCase { test, body, span } in cases {
+                    self.keyword(span, &["case", "default"]);
+                    self.exprs(&test.iter().chain(body).collect::<Vec<_>>(), span);
+                }
+            }
+            Expr::New(class, args) => {
+                let mut spans = vec![&class.1];
+                spans.extend(args.iter().map(|(_, span)| span));
+                self.nested(&spans, span);
+                self.expr(class);
+                if let Some((args, args_span)) = args {
+                    self.exprs(&args.iter().collect::<Vec<_>>(), args_span);
+                }
+            }
+            Expr::Prop(object, name) | Expr::ClassConst(object, name) => {
+                self.nested(&[&object.1, &name.1], span);
+                self.name(name, "");
+                self.expr(object);
+            }
+            Expr::NullsafeProp(object, name) => {
+                self.nested(&[&object.1, &name.1], span);
+                self.name(name, "");
+                self.expr(object);
+            }
+            Expr::Match(subject, arms) => {
+                self.keyword(span, &["match"]);
+                let mut spans = vec![&subject.1];
+                spans.extend(arms.iter().map(|arm| &arm.span));
+                self.nested(&spans, span);
+                self.expr(subject);
+                for MatchArm {
+                    conditions,
+                    body,
+                    span,
+                } in arms
+                {
+                    self.exprs(
+                        &conditions.iter().flatten().chain([body]).collect::<Vec<_>>(),
+                        span,
+                    );
+                }
+            }
+            Expr::Closure(args, uses, ret, body) => {
+                self.keyword(span, &["function", "static"]);
+                let mut spans = args.iter().map(|param| &param.span).collect::<Vec<_>>();
+                spans.extend(uses.iter().map(|(_, span)| span));
+                spans.extend(ret.iter().map(|(_, span)| span));
+                spans.push(&body.1);
+                self.nested(&spans, span);
+                for name in uses {
+                    self.name(name, "$");
+                }
+                self.params(args);
+                self.ty_opt(ret);
+                self.expr(body);
+            }
+            Expr::ArrowFn(args, ret, body) => {
+                self.keyword(span, &["fn", "static"]);
+                let mut spans = args.iter().map(|param| &param.span).collect::<Vec<_>>();
+                spans.extend(ret.iter().map(|(_, span)| span));
+                spans.push(&body.1);
+                self.nested(&spans, span);
+                self.params(args);
+                self.ty_opt(ret);
+                self.expr(body);
+            }
+            Expr::StaticProp(class, name) => {
+                self.nested(&[&class.1, &name.1], span);
+                self.name(name, "$");
+                self.expr(class);
+            }
+        }
+    }
+}
+
+fn item_span(item: &Item) -> &Span {
+    match item {
+        Item::Func(func) => &func.span,
+        Item::Class(class) => &class.span,
+        Item::Const(constant) => &constant.span,
+        Item::Use(use_) => &use_.span,
+        Item::Namespace(namespace) => &namespace.span,
+        Item::Stmt((_, span)) => span,
+    }
+}
+
+#[test]
+fn test_spans_are_nested_over_examples() {
+    let mut paths = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "php"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut errors = Vec::new();
+    for path in paths {
+        let src = std::fs::read_to_string(&path).unwrap();
+        let ast = parser(&src, PhpVersion::default()).ast.unwrap();
+
+        let mut checker = Checker {
+            src: src.chars().collect(),
+            errors: Vec::new(),
+        };
+        checker.items(&ast, &(0..checker.src.len()));
+        errors.extend(checker.errors.into_iter().map(|e| format!("{}: {}", path.display(), e)));
+    }
+
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+}
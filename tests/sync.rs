@@ -0,0 +1,143 @@
+use phantom_language_server::document::Document;
+use phantom_language_server::position::{offset_to_position, PositionEncoding};
+use ropey::Rope;
+use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+
+const SRC: &str = "<?php\r\nnamespace App;\n\nfunction héllo(string $name): string\n{\n    return \"Hi, $name\";\n}\n\nclass Greeter {\n    public function greet() { echo héllo('€'); }\n}\n";
+
+/// What's typed into the document, and in place of how many characters.
+const EDITS: &[(usize, &str)] = &[
+    (0, "x"),
+    (0, "\n"),
+    (1, ""),
+    (3, ""),
+    (0, "$y = 'é';"),
+    (2, "}"),
+    (0, "/* "),
+    (4, "\r\n"),
+    (0, "€"),
+    (1, "👋"),
+];
+
+/// A change that replaces `len` characters at `offset` of `text`, as a client that counts the
+/// columns in the `encoding` sends it.
+fn change(
+    text: &Rope,
+    offset: usize,
+    len: usize,
+    inserted: &str,
+    encoding: PositionEncoding,
+) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(Range::new(
+            offset_to_position(offset, text, encoding).unwrap(),
+            offset_to_position(offset + len, text, encoding).unwrap(),
+        )),
+        range_length: None,
+        text: inserted.to_string(),
+    }
+}
+
+/// Asserts that a document synced by edits is the one that syncing the full text would give.
+fn assert_synced(document: &Document, text: &str, after: &str) {
+    let full = Document::new(text);
+    assert_eq!(document.text().to_string(), text, "text after {}", after);
+    assert_eq!(document.tokens(), full.tokens(), "tokens after {}", after);
+    assert_eq!(
+        format!("{:?}", document.items()),
+        format!("{:?}", full.items()),
+        "items after {}",
+        after
+    );
+}
+
+/// Replays edits at pseudo-random places, each sent on its own, which is how typing is synced.
+fn replay_edits(encoding: PositionEncoding) {
+    let mut document = Document::new(SRC);
+    let mut text = SRC.to_string();
+    let mut seed = 7usize;
+    for step in 0..200 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+        let (len, inserted) = EDITS[seed % EDITS.len()];
+        let chars = text.chars().count();
+        // Positions can't fall between the `\r` and `\n` of a line break
+        let splits = |offset: usize| {
+            let mut at = text.chars().skip(offset.saturating_sub(1));
+            offset > 0 && at.next() == Some('\r') && at.next() == Some('\n')
+        };
+        // Every few edits are at the end, where typing most often happens
+        let mut offset = match step % 5 {
+            0 => chars,
+            _ => (seed >> 8) % (chars + 1),
+        };
+        if splits(offset) {
+            offset -= 1;
+        }
+        let mut len = len.min(chars - offset);
+        if splits(offset + len) {
+            len += 1;
+        }
+
+        let edit = change(document.text(), offset, len, inserted, encoding);
+        document.apply_changes(&[edit], encoding);
+
+        let start = text.char_indices().nth(offset).map_or(text.len(), |(i, _)| i);
+        let end = text[start..].char_indices().nth(len).map_or(text.len(), |(i, _)| start + i);
+        text.replace_range(start..end, inserted);
+        assert_synced(
+            &document,
+            &text,
+            &format!("step {} in {:?}", step, encoding),
+        );
+    }
+}
+
+#[test]
+fn test_replaying_edits_syncs_like_the_full_text() {
+    for encoding in [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ] {
+        replay_edits(encoding);
+    }
+}
+
+#[test]
+fn test_changes_are_applied_in_order() {
+    let encoding = PositionEncoding::Utf16;
+    let mut document = Document::new(SRC);
+    let text = Rope::from_str(SRC);
+
+    // The second change is at a position in the text the first one left
+    let greet = SRC.find("greet").unwrap();
+    let first = change(&text, text.byte_to_char(greet), 5, "welcome", encoding);
+    let mut edited = Rope::from_str(&SRC.replacen("greet", "welcome", 1));
+    let second = change(&edited, edited.byte_to_char(greet + 7), 0, "Back", encoding);
+    edited.insert(edited.byte_to_char(greet + 7), "Back");
+
+    document.apply_changes(&[first, second], encoding);
+    assert_synced(&document, &edited.to_string(), "two changes");
+
+    // A change without a range is the whole text, and no changes change nothing
+    let full = TextDocumentContentChangeEvent {
+        range: None,
+        range_length: None,
+        text: "<?php echo 1;".to_string(),
+    };
+    document.apply_changes(&[full], encoding);
+    document.apply_changes(&[], encoding);
+    assert_synced(&document, "<?php echo 1;", "replacing the text");
+}
+
+#[test]
+fn test_positions_past_the_end_are_clamped() {
+    let mut document = Document::new("<?php\n$a = 1;\n");
+    let past = TextDocumentContentChangeEvent {
+        range: Some(Range::new(Position::new(1, 40), Position::new(9, 0))),
+        range_length: None,
+        text: " // end".to_string(),
+    };
+    document.apply_changes(&[past], PositionEncoding::Utf16);
+    assert_synced(&document, "<?php\n$a = 1; // end", "an edit past the end");
+}